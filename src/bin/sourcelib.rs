@@ -0,0 +1,597 @@
+// Command-line tool for poking at Source Engine files
+// Every command prints something readable by default, or JSON with --json
+
+use sourcelib::bsp::{
+    self, vmf, Bsp, BspWriter, EntityChange, IoNode, LumpFile, LumpIndex, NavOptions, PakChange, RadarOptions, RadarShading,
+    StaticPropChange,
+};
+use sourcelib::keyvalues::{KeyValues, Value};
+use sourcelib::stripper;
+use sourcelib::vtf::{Flags, Vtf};
+
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "\
+usage: sourcelib [--json] <command> [args]
+
+commands:
+    bsp info <map.bsp>                  header, versions and lump table
+    bsp entities <map.bsp>              every entity in the Entities lump
+    bsp extract-pak <map.bsp> <dir>     unpack the PakFile lump into dir
+    bsp decompile <map.bsp> <out.vmf>   rebuild a Hammer .vmf from the map
+    bsp radar <map.bsp> <out.tga>       top-down overview, plus its out.txt
+        [--size N] [--max-z Z] [--material]
+    bsp io <map.bsp> [out.dot]          check entity logic, optionally graph it
+    bsp strip <map.bsp> <config> <out>  apply a Stripper:Source config,
+                                        saving a .lmp or a whole new .bsp
+    bsp diff <old.bsp> <new.bsp>        what changed between two builds
+    bsp nav <map.bsp> <out.txt>         walkable areas for bots, as text
+        [--cell N] [--step N] [--all]
+    kv fmt <file>                       reformat a KeyValues file
+    kv get <file> <path>                print the value at a/b/c
+    vtf info <file.vtf>                 header information
+    vtf convert <file.vtf> <out.tga>    save an image as a 32-bit TGA
+        [--mip N] [--frame N] [--face N] [--slice N]";
+
+type CommandResult = Result<(), Box<dyn Error>>;
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let json = take_flag(&mut args, "--json");
+
+    if args.is_empty() || args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let result = match args.as_slice() {
+        ["bsp", "info", path] => bsp_info(path, json),
+        ["bsp", "entities", path] => bsp_entities(path, json),
+        ["bsp", "extract-pak", path, dir] => bsp_extract_pak(path, dir, json),
+        ["bsp", "decompile", path, out] => bsp_decompile(path, out, json),
+        ["bsp", "radar", path, out, options @ ..] => bsp_radar(path, out, options, json),
+        ["bsp", "io", path] => bsp_io(path, None, json),
+        ["bsp", "io", path, out] => bsp_io(path, Some(out), json),
+        ["bsp", "strip", path, config, out] => bsp_strip(path, config, out, json),
+        ["bsp", "diff", old, new] => bsp_diff(old, new, json),
+        ["bsp", "nav", path, out, options @ ..] => bsp_nav(path, out, options, json),
+        ["kv", "fmt", path] => kv_fmt(path, json),
+        ["kv", "get", path, key] => kv_get(path, key, json),
+        ["vtf", "info", path] => vtf_info(path, json),
+        ["vtf", "convert", path, out, options @ ..] => vtf_convert(path, out, options, json),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        },
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let before = args.len();
+    args.retain(|a| a != flag);
+    args.len() != before
+}
+
+fn bsp_info(path: &str, json: bool) -> CommandResult {
+    let mut bsp = Bsp::from_file(path)?;
+    let crc = bsp.map_crc()?;
+
+    let lumps: Vec<_> = LumpIndex::ALL.iter()
+        .map(|&index| (index, bsp.lumps[index as usize]))
+        .filter(|(_, lump)| lump.exists())
+        .collect();
+
+    if json {
+        let lumps = lumps.iter().map(|(index, lump)| Json::Object(vec![
+            ("index", Json::Number(*index as usize as f64)),
+            ("name", Json::Str(format!("{:?}", index))),
+            ("offset", Json::Number(lump.offset as f64)),
+            ("length", Json::Number(lump.length as f64)),
+            ("version", Json::Number(lump.version as f64)),
+            ("fourcc", Json::Str(String::from_utf8_lossy(&lump.indent_code).trim_end_matches('\0').to_string())),
+        ])).collect();
+        print_json(&Json::Object(vec![
+            ("version", Json::Number(bsp.version as f64)),
+            ("iteration", Json::Number(bsp.iteration as f64)),
+            ("endian", Json::Str(format!("{:?}", bsp.endian))),
+            ("layout", Json::Str(format!("{:?}", bsp.layout))),
+            ("file_size", Json::Number(bsp.file_size() as f64)),
+            ("map_crc", Json::Str(format!("{:08x}", crc))),
+            ("lumps", Json::Array(lumps)),
+        ]));
+    } else {
+        println!("{}", bsp);
+        println!("Endian: {:?}, Lump layout: {:?}", bsp.endian, bsp.layout);
+        println!("File size: {} bytes, Map CRC: {:08x}", bsp.file_size(), crc);
+        println!();
+        println!("{:>3}  {:<36} {:>10} {:>10} {:>7}", "#", "Lump", "Offset", "Length", "Version");
+        for (index, lump) in lumps {
+            println!("{:>3}  {:<36} {:>10} {:>10} {:>7}",
+                index as usize, format!("{:?}", index), lump.offset, lump.length, lump.version);
+        }
+    }
+    Ok(())
+}
+
+fn bsp_entities(path: &str, json: bool) -> CommandResult {
+    let mut bsp = Bsp::from_file(path)?;
+    bsp.load_lump_files()?;
+    let entities = bsp.entities()?;
+
+    if json {
+        // Pairs instead of objects, keys can repeat (outputs, mostly)
+        print_json(&Json::Array(entities.iter().map(|e| Json::Array(
+            e.properties.iter()
+                .map(|(k, v)| Json::Array(vec![Json::Str(k.clone()), Json::Str(v.clone())]))
+                .collect()
+        )).collect()));
+    } else {
+        for entity in entities {
+            print!("{}", entity);
+        }
+    }
+    Ok(())
+}
+
+fn bsp_extract_pak(path: &str, dir: &str, json: bool) -> CommandResult {
+    let mut bsp = Bsp::from_file(path)?;
+    let pak = bsp.pak_file()?;
+
+    let mut written = Vec::new();
+    for entry in pak.entries.iter() {
+        // Directories are entries too
+        if entry.name.ends_with('/') {
+            continue;
+        }
+        let out = safe_join(dir, &entry.name)
+            .ok_or_else(|| format!("refusing to extract {}, it points outside {}", entry.name, dir))?;
+        if let Some(parent) = out.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&out, pak.entry_data(entry)?)?;
+
+        if !json {
+            println!("{} ({} bytes)", entry.name, entry.size);
+        }
+        written.push(Json::Object(vec![
+            ("name", Json::Str(entry.name.clone())),
+            ("size", Json::Number(entry.size as f64)),
+        ]));
+    }
+
+    if json {
+        print_json(&Json::Array(written));
+    }
+    Ok(())
+}
+
+fn bsp_decompile(path: &str, out: &str, json: bool) -> CommandResult {
+    let mut bsp = Bsp::from_file(path)?;
+    bsp.load_lump_files()?;
+    let vmf = vmf::decompile(&mut bsp)?;
+    fs::write(out, vmf.to_string())?;
+
+    let world = vmf.get_subkey("world").map_or(0, |world| world.get_subkeys("solid").count());
+    let entities = vmf.get_subkeys("entity").count();
+    if json {
+        print_json(&Json::Object(vec![
+            ("output", Json::Str(out.to_string())),
+            ("world_solids", Json::Number(world as f64)),
+            ("entities", Json::Number(entities as f64)),
+        ]));
+    } else {
+        println!("Wrote {} with {} world brushes and {} entities", out, world, entities);
+    }
+    Ok(())
+}
+
+fn bsp_radar(path: &str, out: &str, options: &[&str], json: bool) -> CommandResult {
+    let mut radar_options = RadarOptions::default();
+    let mut options = options.iter();
+    while let Some(&option) = options.next() {
+        let mut value = || options.next().ok_or_else(|| format!("missing value for {}", option));
+        match option {
+            "--size" => radar_options.size = value()?.parse()?,
+            "--max-z" => radar_options.max_z = Some(value()?.parse()?),
+            "--material" => radar_options.shading = RadarShading::Material,
+            _ => return Err(format!("unknown option {}", option).into()),
+        }
+    }
+
+    let mut bsp = Bsp::from_file(path)?;
+    bsp.load_lump_files()?;
+    let radar = bsp.radar(&radar_options)?;
+    fs::write(out, tga(radar.size, radar.size, &radar.pixels)?)?;
+    let map_name = Path::new(path).file_stem().map_or("map".into(), |s| s.to_string_lossy());
+    let txt = Path::new(out).with_extension("txt");
+    fs::write(&txt, radar.overview_txt(&map_name))?;
+
+    if json {
+        print_json(&Json::Object(vec![
+            ("output", Json::Str(out.to_string())),
+            ("overview", Json::Str(txt.display().to_string())),
+            ("pos_x", Json::Number(radar.pos_x as f64)),
+            ("pos_y", Json::Number(radar.pos_y as f64)),
+            ("scale", Json::Number(radar.scale as f64)),
+        ]));
+    } else {
+        println!("Wrote {} and {} (pos_x {}, pos_y {}, scale {})", out, txt.display(), radar.pos_x, radar.pos_y, radar.scale);
+    }
+    Ok(())
+}
+
+fn bsp_nav(path: &str, out: &str, options: &[&str], json: bool) -> CommandResult {
+    let mut nav_options = NavOptions::default();
+    let mut options = options.iter();
+    while let Some(&option) = options.next() {
+        let mut value = || options.next().ok_or_else(|| format!("missing value for {}", option));
+        match option {
+            "--cell" => nav_options.cell_size = value()?.parse()?,
+            "--step" => nav_options.step_height = value()?.parse()?,
+            "--all" => nav_options.flood_from_spawns = false,
+            _ => return Err(format!("unknown option {}", option).into()),
+        }
+    }
+
+    let mut bsp = Bsp::from_file(path)?;
+    bsp.load_lump_files()?;
+    let nav = bsp.nav_mesh(&nav_options)?;
+    fs::write(out, nav.to_text())?;
+    let connections = nav.areas.iter().map(|a| a.connections.len()).sum::<usize>() / 2;
+
+    if json {
+        print_json(&Json::Object(vec![
+            ("output", Json::Str(out.to_string())),
+            ("areas", Json::Number(nav.areas.len() as f64)),
+            ("connections", Json::Number(connections as f64)),
+        ]));
+    } else {
+        println!("Wrote {} with {} areas and {} connections", out, nav.areas.len(), connections);
+    }
+    Ok(())
+}
+
+fn bsp_io(path: &str, dot: Option<&str>, json: bool) -> CommandResult {
+    let mut bsp = Bsp::from_file(path)?;
+    bsp.load_lump_files()?;
+    let graph = bsp.io_graph()?;
+    if let Some(out) = dot {
+        fs::write(out, graph.to_dot())?;
+    }
+
+    let name = |i: usize| {
+        let IoNode { classname, targetname } = &graph.nodes[i];
+        match targetname {
+            Some(targetname) => format!("{} ({})", targetname, classname),
+            None => format!("#{} ({})", i, classname),
+        }
+    };
+    let dangling: Vec<(String, String)> = graph.dangling()
+        .map(|e| (name(e.from), format!("{} -> {}.{}", e.connection.output, e.connection.target, e.connection.input)))
+        .collect();
+    let unreachable: Vec<String> = graph.unreachable().into_iter().map(name).collect();
+    let cycles: Vec<Vec<String>> = graph.cycles().into_iter().map(|c| c.into_iter().map(name).collect()).collect();
+
+    if json {
+        let strings = |v: &[String]| Json::Array(v.iter().map(|s| Json::Str(s.clone())).collect());
+        print_json(&Json::Object(vec![
+            ("entities", Json::Number(graph.nodes.len() as f64)),
+            ("connections", Json::Number(graph.edges.len() as f64)),
+            ("dangling", Json::Array(dangling.iter().map(|(from, output)| Json::Object(vec![
+                ("entity", Json::Str(from.clone())),
+                ("output", Json::Str(output.clone())),
+            ])).collect())),
+            ("unreachable", strings(&unreachable)),
+            ("cycles", Json::Array(cycles.iter().map(|c| strings(c)).collect())),
+        ]));
+    } else {
+        println!("{} entities, {} connections", graph.nodes.len(), graph.edges.len());
+        for (from, output) in dangling.iter() {
+            println!("dangling: {} {}", from, output);
+        }
+        for entity in unreachable.iter() {
+            println!("unreachable: {}", entity);
+        }
+        for cycle in cycles.iter() {
+            println!("cycle: {}", cycle.join(", "));
+        }
+        if let Some(out) = dot {
+            println!("Wrote {}", out);
+        }
+    }
+    Ok(())
+}
+
+fn bsp_strip(path: &str, config: &str, out: &str, json: bool) -> CommandResult {
+    let config = stripper::Config::from_file(config)?;
+    let mut bsp = Bsp::from_file(path)?;
+    bsp.load_lump_files()?;
+    let mut entities = bsp.entities()?;
+    let changes = config.apply(&mut entities);
+
+    if Path::new(out).extension().is_some_and(|e| e.eq_ignore_ascii_case("lmp")) {
+        LumpFile::from_entities(&entities, bsp.iteration).save(out)?;
+    } else {
+        let mut writer = BspWriter::from_bsp(&mut bsp)?;
+        writer.set_entities(&entities);
+        writer.save(out)?;
+    }
+
+    if json {
+        print_json(&Json::Object(vec![
+            ("output", Json::Str(out.to_string())),
+            ("removed", Json::Number(changes.removed as f64)),
+            ("added", Json::Number(changes.added as f64)),
+            ("modified", Json::Number(changes.modified as f64)),
+        ]));
+    } else {
+        println!("Wrote {}: {} removed, {} added, {} modified", out, changes.removed, changes.added, changes.modified);
+    }
+    Ok(())
+}
+
+fn bsp_diff(old: &str, new: &str, json: bool) -> CommandResult {
+    let mut old = Bsp::from_file(old)?;
+    old.load_lump_files()?;
+    let mut new = Bsp::from_file(new)?;
+    new.load_lump_files()?;
+    let report = bsp::diff(&mut old, &mut new)?;
+
+    // (what, kind of change, detail)
+    let mut lines: Vec<(&str, &str, String)> = Vec::new();
+    if let Some((a, b)) = report.version {
+        lines.push(("version", "changed", format!("{} -> {}", a, b)));
+    }
+    if let Some((a, b)) = report.revision {
+        lines.push(("revision", "changed", format!("{} -> {}", a, b)));
+    }
+    for lump in report.lumps.iter() {
+        lines.push(("lump", "changed", format!("{:?} ({}): {} -> {} bytes, crc {:08x} -> {:08x}",
+            lump.index, lump.index as usize, lump.old_size, lump.new_size, lump.old_crc, lump.new_crc)));
+    }
+    let describe = |e: &bsp::Entity| format!("{} {}", e.classname().unwrap_or("?"), e.targetname().unwrap_or_default());
+    for change in report.entities.iter() {
+        lines.push(match change {
+            EntityChange::Added(e) => ("entity", "added", describe(e)),
+            EntityChange::Removed(e) => ("entity", "removed", describe(e)),
+            EntityChange::Modified { new, keys, .. } => ("entity", "modified", format!("{}: {}", describe(new),
+                keys.iter().map(|k| k.key.as_str()).collect::<Vec<_>>().join(", "))),
+        });
+    }
+    for change in report.pak_files.iter() {
+        lines.push(match change {
+            PakChange::Added(e) => ("pakfile", "added", e.name.clone()),
+            PakChange::Removed(e) => ("pakfile", "removed", e.name.clone()),
+            PakChange::Changed { new, .. } => ("pakfile", "changed", new.name.clone()),
+        });
+    }
+    let at = |p: &bsp::StaticProp| format!("{} at ({} {} {})", p.model, p.origin.x, p.origin.y, p.origin.z);
+    for change in report.static_props.iter() {
+        lines.push(match change {
+            StaticPropChange::Added(p) => ("static prop", "added", at(p)),
+            StaticPropChange::Removed(p) => ("static prop", "removed", at(p)),
+            StaticPropChange::Moved { old, new } => ("static prop", "moved", format!("{} -> ({} {} {})",
+                at(old), new.origin.x, new.origin.y, new.origin.z)),
+        });
+    }
+
+    if json {
+        print_json(&Json::Array(lines.into_iter().map(|(what, change, detail)| Json::Object(vec![
+            ("type", Json::Str(what.to_string())),
+            ("change", Json::Str(change.to_string())),
+            ("detail", Json::Str(detail)),
+        ])).collect()));
+    } else if lines.is_empty() {
+        println!("No differences");
+    } else {
+        for (what, change, detail) in lines {
+            println!("{} {}: {}", what, change, detail);
+        }
+    }
+    Ok(())
+}
+
+// dir/name, unless name is absolute or climbs out with ..
+fn safe_join(dir: &str, name: &str) -> Option<PathBuf> {
+    let name = name.replace('\\', "/");
+    let name = Path::new(&name);
+    if name.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+        Some(Path::new(dir).join(name))
+    } else {
+        None
+    }
+}
+
+fn kv_fmt(path: &str, json: bool) -> CommandResult {
+    let kv = KeyValues::from_file(path)?;
+    if json {
+        print_json(&kv_json(&kv));
+    } else {
+        print!("{}", kv);
+    }
+    Ok(())
+}
+
+fn kv_get(path: &str, key: &str, json: bool) -> CommandResult {
+    let kv = KeyValues::from_file(path)?;
+    let value = kv.get_path(key).ok_or_else(|| format!("{} not found", key))?;
+    match (value, json) {
+        (Value::Str(s), false) => println!("{}", s),
+        (Value::Subkey(kv), false) => print!("{}", kv),
+        (Value::Str(s), true) => print_json(&Json::Str(s.clone())),
+        (Value::Subkey(kv), true) => print_json(&kv_json(kv)),
+    }
+    Ok(())
+}
+
+// Like entities, [key, value] pairs since keys can repeat
+fn kv_json(kv: &KeyValues) -> Json {
+    Json::Array(kv.entries().iter().map(|(key, value)| {
+        let value = match value {
+            Value::Str(s) => Json::Str(s.clone()),
+            Value::Subkey(kv) => kv_json(kv),
+        };
+        Json::Array(vec![Json::Str(key.clone()), value])
+    }).collect())
+}
+
+fn vtf_info(path: &str, json: bool) -> CommandResult {
+    let vtf = Vtf::from_file(path)?;
+    let h = &vtf.header;
+    let flags: Vec<Flags> = ALL_FLAGS.iter().copied().filter(|&flag| h.has_flag(flag)).collect();
+
+    if json {
+        print_json(&Json::Object(vec![
+            ("version", Json::Str(format!("{}.{}", h.version[0], h.version[1]))),
+            ("width", Json::Number(h.width as f64)),
+            ("height", Json::Number(h.height as f64)),
+            ("depth", Json::Number(h.depth as f64)),
+            ("format", Json::Str(format!("{:?}", h.high_res_format))),
+            ("mipmaps", Json::Number(h.mipmaps as f64)),
+            ("frames", Json::Number(h.frames as f64)),
+            ("faces", Json::Number(h.faces() as f64)),
+            ("flags", Json::Array(flags.iter().map(|f| Json::Str(format!("{:?}", f))).collect())),
+            ("reflectivity", Json::Array(h.reflectivity.iter().map(|&r| Json::Number(r as f64)).collect())),
+            ("bumpmap_scale", Json::Number(h.bumpmap_scale as f64)),
+            ("low_res_format", Json::Str(format!("{:?}", h.low_res_format))),
+            ("low_res_width", Json::Number(h.low_res_width as f64)),
+            ("low_res_height", Json::Number(h.low_res_height as f64)),
+        ]));
+    } else {
+        println!("VTF Version: {}.{}", h.version[0], h.version[1]);
+        println!("Size: {}x{}x{}, Format: {:?}", h.width, h.height, h.depth, h.high_res_format);
+        println!("Mipmaps: {}, Frames: {}, Faces: {}", h.mipmaps, h.frames, h.faces());
+        println!("Flags: {:?}", flags);
+        println!("Reflectivity: {:?}, Bumpmap scale: {}", h.reflectivity, h.bumpmap_scale);
+        println!("Low-res image: {}x{} {:?}", h.low_res_width, h.low_res_height, h.low_res_format);
+    }
+    Ok(())
+}
+
+fn vtf_convert(path: &str, out: &str, options: &[&str], json: bool) -> CommandResult {
+    let (mut mip, mut frame, mut face, mut slice) = (0, 0, 0, 0);
+    for pair in options.chunks(2) {
+        let (option, value) = match pair {
+            [option, value] => (*option, value.parse::<usize>()?),
+            _ => return Err(format!("missing value for {}", pair[0]).into()),
+        };
+        match option {
+            "--mip" => mip = value,
+            "--frame" => frame = value,
+            "--face" => face = value,
+            "--slice" => slice = value,
+            _ => return Err(format!("unknown option {}", option).into()),
+        }
+    }
+
+    let vtf = Vtf::from_file(path)?;
+    let (width, height, pixels) = vtf.to_rgba8(mip, frame, face, slice)?;
+    fs::write(out, tga(width, height, &pixels)?)?;
+
+    if json {
+        print_json(&Json::Object(vec![
+            ("output", Json::Str(out.to_string())),
+            ("width", Json::Number(width as f64)),
+            ("height", Json::Number(height as f64)),
+        ]));
+    } else {
+        println!("Wrote {}x{} image to {}", width, height, out);
+    }
+    Ok(())
+}
+
+// Uncompressed 32-bit TGA, stored top to bottom
+fn tga(width: usize, height: usize, rgba: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err("image is too big for a TGA".into());
+    }
+    let mut out = vec![0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    out.extend_from_slice(&(width as u16).to_le_bytes());
+    out.extend_from_slice(&(height as u16).to_le_bytes());
+    out.push(32);
+    out.push(0x28); // 8 alpha bits, top-left origin
+    for p in rgba.chunks_exact(4) {
+        out.extend_from_slice(&[p[2], p[1], p[0], p[3]]);
+    }
+    Ok(out)
+}
+
+const ALL_FLAGS: [Flags; 32] = {
+    use Flags::*;
+    [
+        PointSample, TriLinear, ClampS, ClampT, Anisotropic, HintDxt5, PwlCorrected, Normal,
+        NoMip, NoLOD, AllMips, Procedural, OneBitAlpha, EightBitAlpha, EnvMap, RenderTarget,
+        DepthRenderTarget, NoDebugOverride, SingleCopy, PreSrgb, Unused0, Unused1, Unused2,
+        NoDepthBuffer, NiceFilter, ClampU, VertexTexture, SSBump, Unused4, Border, Unused5, Unused6,
+    ]
+};
+
+// Just enough JSON to print results, objects keep their key order
+enum Json {
+    Str(String),
+    Number(f64),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+fn print_json(json: &Json) {
+    let mut out = String::new();
+    write_json(&mut out, json);
+    println!("{}", out);
+}
+
+fn write_json(out: &mut String, json: &Json) {
+    match json {
+        Json::Str(s) => write_json_string(out, s),
+        Json::Number(n) if n.is_finite() => { let _ = write!(out, "{}", n); },
+        Json::Number(_) => out.push_str("null"),
+        Json::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(out, item);
+            }
+            out.push(']');
+        },
+        Json::Object(fields) => {
+            out.push('{');
+            for (i, (key, value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json_string(out, key);
+                out.push(':');
+                write_json(out, value);
+            }
+            out.push('}');
+        },
+    }
+}
+
+fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(out, "\\u{:04x}", c as u32); },
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
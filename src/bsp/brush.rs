@@ -0,0 +1,143 @@
+// Brushes, the convex solids a map was built from
+// Each brush is a list of sides, each side a plane the brush is behind
+// The compiler adds extra "bevel" sides for collision, those aren't part of the original brush
+
+use super::lump_item::{LumpContext, LumpItem, LumpReader, LumpWriter};
+use crate::{Plane, Vector};
+
+// CONTENTS_DETAIL, brushes from func_detail that vbsp merged into the world
+pub const CONTENTS_DETAIL: i32 = 0x8000000;
+
+// dbrush_t
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Brush {
+    pub first_side: i32,
+    pub num_sides: i32,
+    pub contents: i32,
+}
+
+impl LumpItem for Brush {
+    fn size(_: &LumpContext) -> usize { 12 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self { first_side: r.i32(), num_sides: r.i32(), contents: r.i32() }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.i32(self.first_side);
+        w.i32(self.num_sides);
+        w.i32(self.contents);
+    }
+}
+
+// dbrushside_t
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BrushSide {
+    pub plane_number: u16,
+    pub texture_info: i16,
+    pub displacement_info: i16,
+    pub bevel: bool,
+    // Before version 21 bevel was a short, this is its high byte there
+    pub thin: bool,
+}
+
+impl LumpItem for BrushSide {
+    fn size(_: &LumpContext) -> usize { 8 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self {
+            plane_number: r.u16(),
+            texture_info: r.i16(),
+            displacement_info: r.i16(),
+            bevel: r.u8() != 0,
+            thin: r.u8() != 0,
+        }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.u16(self.plane_number);
+        w.i16(self.texture_info);
+        w.i16(self.displacement_info);
+        w.u8(self.bevel as u8);
+        w.u8(self.thin as u8);
+    }
+}
+
+// Bigger than any map, so the first polygon on a plane covers the whole brush
+const HUGE: f64 = 65536.0;
+const EPSILON: f64 = 0.01;
+
+// Clipping is done in f64, f32 loses too much precision this far out
+type V = [f64; 3];
+
+fn dot(a: V, b: V) -> f64 { a[0] * b[0] + a[1] * b[1] + a[2] * b[2] }
+fn add(a: V, b: V) -> V { [a[0] + b[0], a[1] + b[1], a[2] + b[2]] }
+fn sub(a: V, b: V) -> V { [a[0] - b[0], a[1] - b[1], a[2] - b[2]] }
+fn scale(a: V, s: f64) -> V { [a[0] * s, a[1] * s, a[2] * s] }
+fn cross(a: V, b: V) -> V { [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]] }
+
+// The polygon of each plane that's on the surface of the convex solid behind all of them
+// Planes that don't touch the solid (or repeat an earlier one) get an empty polygon
+pub fn clip_brush(planes: &[Plane]) -> Vec<Vec<Vector>> {
+    let planes: Vec<(V, f64)> = planes.iter()
+        .map(|p| ([p.normal.x as f64, p.normal.y as f64, p.normal.z as f64], p.distance as f64))
+        .collect();
+
+    planes.iter().enumerate().map(|(i, &(normal, distance))| {
+        let mut polygon = base_polygon(normal, distance);
+        for (j, &(other_normal, other_distance)) in planes.iter().enumerate() {
+            if i == j || polygon.is_empty() {
+                continue;
+            }
+            // Only the first of two identical planes gets the polygon
+            if dot(other_normal, normal) > 1.0 - 1e-6 && (other_distance - distance).abs() < EPSILON {
+                if j < i {
+                    polygon.clear();
+                }
+                continue;
+            }
+            polygon = clip_polygon(&polygon, other_normal, other_distance);
+        }
+        if polygon.len() < 3 {
+            return Vec::new();
+        }
+        polygon.iter().map(|v| Vector::new(v[0] as f32, v[1] as f32, v[2] as f32)).collect()
+    }).collect()
+}
+
+// A huge square on the plane
+fn base_polygon(normal: V, distance: f64) -> Vec<V> {
+    // Pick the axis least like the normal to build the square's sides from
+    let axis = if normal[2].abs() > normal[0].abs() && normal[2].abs() > normal[1].abs() {
+        [1.0, 0.0, 0.0]
+    } else {
+        [0.0, 0.0, 1.0]
+    };
+    let up = sub(axis, scale(normal, dot(axis, normal)));
+    let up = scale(up, HUGE / dot(up, up).sqrt());
+    let right = cross(up, normal);
+    let origin = scale(normal, distance);
+    vec![
+        add(sub(origin, right), up),
+        add(add(origin, right), up),
+        sub(add(origin, right), up),
+        sub(sub(origin, right), up),
+    ]
+}
+
+// Keep the part of the polygon behind the plane
+fn clip_polygon(polygon: &[V], normal: V, distance: f64) -> Vec<V> {
+    let side = |v: V| dot(normal, v) - distance;
+    let mut out = Vec::with_capacity(polygon.len() + 1);
+    for (i, &a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        let (da, db) = (side(a), side(b));
+        if da <= EPSILON {
+            out.push(a);
+        }
+        if (da > EPSILON && db < -EPSILON) || (da < -EPSILON && db > EPSILON) {
+            out.push(add(a, scale(sub(b, a), da / (da - db))));
+        }
+    }
+    out
+}
@@ -0,0 +1,340 @@
+// Small but complete maps, made in memory, for tests and fixtures that can't ship real game files
+//
+//   let mut builder = MapBuilder::new();
+//   builder.add_room(Vector::new(-256.0, -256.0, 0.0), Vector::new(256.0, 256.0, 128.0), 16.0, "dev/dev_measurewall01a");
+//   builder.add_entity(&[("classname", "info_player_start"), ("origin", "0 0 16")]);
+//   builder.add_file("materials/custom/thing.vmt", b"LightmappedGeneric {}");
+//   let bytes = builder.to_bytes()?;
+//
+// Every brush is an axis-aligned box, and gets all 6 of its sides as faces.
+// The tree is a chain of nodes per box (one per side, the back of the last one is the box's solid leaf),
+// with the front of every side leading on to the next box's chain, so the empty leaf at the end is everything else.
+// That makes it a DAG instead of a tree, which nothing that walks it down minds
+
+use super::entity::Entity;
+use super::error::*;
+use super::lump::LumpIndex;
+use super::pak::write_pak_file;
+use super::texture::{TextureData, TextureInfo};
+use super::tree::{Leaf, Model, Node, CONTENTS_EMPTY, CONTENTS_SOLID};
+use super::brush::{Brush, BrushSide};
+use super::writer::BspWriter;
+use crate::{Edge, Face, Plane, Vector};
+
+// Hammer's default texture scale and lightmap scale
+const TEXTURE_SCALE: f32 = 0.25;
+const LIGHTMAP_SCALE: f32 = 16.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoxBrush {
+    pub mins: Vector,
+    pub maxs: Vector,
+    pub material: String,
+    pub contents: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapBuilder {
+    pub version: u32,
+    pub brushes: Vec<BoxBrush>,
+    // worldspawn is always written first, with the world's key values
+    pub worldspawn: Entity,
+    pub entities: Vec<Entity>,
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+impl Default for MapBuilder {
+    fn default() -> Self {
+        let mut worldspawn = Entity::new();
+        worldspawn.add("classname", "worldspawn");
+        worldspawn.add("skyname", "sky_day01_01");
+        Self { version: 20, brushes: Vec::new(), worldspawn, entities: Vec::new(), files: Vec::new() }
+    }
+}
+
+impl MapBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A solid box
+    pub fn add_box(&mut self, mins: Vector, maxs: Vector, material: &str) {
+        self.brushes.push(BoxBrush { mins: mins.min(&maxs), maxs: mins.max(&maxs), material: material.to_string(), contents: CONTENTS_SOLID });
+    }
+
+    // Six boxes enclosing mins to maxs, with walls thickness units thick
+    pub fn add_room(&mut self, mins: Vector, maxs: Vector, thickness: f32, material: &str) {
+        let (lo, hi) = (mins.min(&maxs), mins.max(&maxs));
+        let t = thickness;
+        // Floor and ceiling cover everything, the X walls fit between them, the Y walls between those
+        self.add_box(Vector::new(lo.x - t, lo.y - t, lo.z - t), Vector::new(hi.x + t, hi.y + t, lo.z), material);
+        self.add_box(Vector::new(lo.x - t, lo.y - t, hi.z), Vector::new(hi.x + t, hi.y + t, hi.z + t), material);
+        self.add_box(Vector::new(lo.x - t, lo.y - t, lo.z), Vector::new(lo.x, hi.y + t, hi.z), material);
+        self.add_box(Vector::new(hi.x, lo.y - t, lo.z), Vector::new(hi.x + t, hi.y + t, hi.z), material);
+        self.add_box(Vector::new(lo.x, lo.y - t, lo.z), Vector::new(hi.x, lo.y, hi.z), material);
+        self.add_box(Vector::new(lo.x, hi.y, lo.z), Vector::new(hi.x, hi.y + t, hi.z), material);
+    }
+
+    pub fn add_entity(&mut self, properties: &[(&str, &str)]) {
+        let mut entity = Entity::new();
+        for (key, value) in properties.iter() {
+            entity.add(key, value);
+        }
+        self.entities.push(entity);
+    }
+
+    // A file in the PakFile lump. Without any, the map has no PakFile
+    pub fn add_file(&mut self, name: &str, data: &[u8]) {
+        self.files.push((name.to_string(), data.to_vec()));
+    }
+
+    // Everything as lumps, ready to write or change further
+    pub fn build(&self) -> BspWriter {
+        let mut planes: Vec<Plane> = Vec::new();
+        let mut vertices: Vec<Vector> = Vec::new();
+        // Edge 0 can't be used, -0 would be the same surface edge
+        let mut edges = vec![Edge { v: [0, 0] }];
+        let mut surface_edges: Vec<i32> = Vec::new();
+        let mut faces: Vec<Face> = Vec::new();
+        let mut brushes: Vec<Brush> = Vec::new();
+        let mut brush_sides: Vec<BrushSide> = Vec::new();
+        let mut materials: Vec<String> = Vec::new();
+        let mut texture_infos: Vec<TextureInfo> = Vec::new();
+        let mut texture_info_keys: Vec<(usize, usize)> = Vec::new();
+
+        // Per box, each side's plane, outward facing
+        let mut box_planes: Vec<[usize; 6]> = Vec::new();
+        for brush in self.brushes.iter() {
+            let material = match materials.iter().position(|m| m.eq_ignore_ascii_case(&brush.material)) {
+                Some(i) => i,
+                None => {
+                    materials.push(brush.material.clone());
+                    materials.len() - 1
+                },
+            };
+
+            let mut sides = [0; 6];
+            brushes.push(Brush { first_side: brush_sides.len() as i32, num_sides: 6, contents: brush.contents });
+            for (side, (axis, positive)) in [(0, true), (0, false), (1, true), (1, false), (2, true), (2, false)].iter().enumerate() {
+                let plane = plane_pair(&mut planes, *axis, *positive, if *positive { brush.maxs } else { brush.mins });
+                sides[side] = plane;
+
+                let texture_info = match texture_info_keys.iter().position(|&k| k == (material, *axis)) {
+                    Some(i) => i,
+                    None => {
+                        texture_info_keys.push((material, *axis));
+                        texture_infos.push(world_aligned(*axis, material));
+                        texture_infos.len() - 1
+                    },
+                };
+                brush_sides.push(BrushSide { plane_number: plane as u16, texture_info: texture_info as i16, displacement_info: -1, ..Default::default() });
+
+                let corners = box_side(brush.mins, brush.maxs, *axis, *positive);
+                let first_vertex = vertices.len();
+                vertices.extend_from_slice(&corners);
+                let first_edge = surface_edges.len() as i32;
+                for i in 0..4 {
+                    surface_edges.push(edges.len() as i32);
+                    edges.push(Edge { v: [(first_vertex + i) as u16, (first_vertex + (i + 1) % 4) as u16] });
+                }
+                let size = brush.maxs - brush.mins;
+                let extents = [size.x, size.y, size.z];
+                faces.push(Face {
+                    plane_number: (plane & !1) as u16,
+                    side: (plane % 2) as u8,
+                    first_edge,
+                    num_edges: 4,
+                    tex_info: texture_info as i16,
+                    disp_info: -1,
+                    surface_fog_volume_id: -1,
+                    styles: [255; 4],
+                    light_offset: -1,
+                    area: extents[(axis + 1) % 3] * extents[(axis + 2) % 3],
+                    original_face: -1,
+                    ..Default::default()
+                });
+            }
+            box_planes.push(sides);
+        }
+
+        // The tree: leaf 0 is the usual solid leaf, then one solid leaf per box, then the empty leaf
+        let empty_leaf = self.brushes.len() + 1;
+        let mut nodes: Vec<Node> = Vec::new();
+        let mut leafs = vec![Leaf { contents: CONTENTS_SOLID, cluster: -1, leaf_water_data_id: -1, ..Default::default() }];
+        let (world_mins, world_maxs) = self.bounds();
+        for (i, (brush, sides)) in self.brushes.iter().zip(box_planes.iter()).enumerate() {
+            let next_box = if i + 1 < self.brushes.len() { (nodes.len() + 6) as i32 } else { -(empty_leaf as i32) - 1 };
+            for (side, &plane) in sides.iter().enumerate() {
+                let inside = if side == 5 { -(i as i32 + 1) - 1 } else { (nodes.len() + 1) as i32 };
+                // Nodes only use the positive facing plane of each pair, so the outside
+                // of a negative side is behind it
+                let children = if plane % 2 == 0 { [next_box, inside] } else { [inside, next_box] };
+                nodes.push(Node {
+                    plane_number: (plane & !1) as i32,
+                    children,
+                    mins: to_i16(world_mins),
+                    maxs: to_i16(world_maxs),
+                    ..Default::default()
+                });
+            }
+            leafs.push(Leaf {
+                contents: brush.contents,
+                cluster: -1,
+                mins: to_i16(brush.mins),
+                maxs: to_i16(brush.maxs),
+                first_leaf_brush: i as u16,
+                num_leaf_brushes: 1,
+                leaf_water_data_id: -1,
+                ..Default::default()
+            });
+        }
+        leafs.push(Leaf {
+            contents: CONTENTS_EMPTY,
+            cluster: 0,
+            mins: to_i16(world_mins),
+            maxs: to_i16(world_maxs),
+            first_leaf_face: 0,
+            num_leaf_faces: faces.len() as u16,
+            leaf_water_data_id: -1,
+            ..Default::default()
+        });
+        // A map without brushes is all empty leaf
+        if nodes.is_empty() {
+            nodes.push(Node { children: [-(empty_leaf as i32) - 1; 2], ..Default::default() });
+        }
+
+        let mut names = Vec::new();
+        let mut name_offsets = Vec::new();
+        for material in materials.iter() {
+            name_offsets.push(names.len() as i32);
+            names.extend_from_slice(material.as_bytes());
+            names.push(0);
+        }
+        let texture_data: Vec<TextureData> = (0..materials.len()).map(|i| TextureData {
+            reflectivity: Vector::new(0.5, 0.5, 0.5),
+            name_string_table_id: i as i32,
+            width: 512,
+            height: 512,
+            view_width: 512,
+            view_height: 512,
+        }).collect();
+
+        let mut entities = vec![self.worldspawn.clone()];
+        entities.extend(self.entities.iter().cloned());
+
+        let mut writer = BspWriter::new(self.version);
+        writer.set_entities(&entities);
+        writer.set_lump(LumpIndex::Planes, &planes);
+        writer.set_lump(LumpIndex::TextureData, &texture_data);
+        writer.set_lump(LumpIndex::Vertices, &vertices);
+        // One cluster that can see and hear itself: the cluster count, its PVS and PAS offsets,
+        // then both run-length encoded bit vectors (one byte, bit 0 set)
+        let mut visibility = Vec::new();
+        for v in [1i32, 12, 13].iter() {
+            visibility.extend_from_slice(&v.to_le_bytes());
+        }
+        visibility.extend_from_slice(&[1, 1]);
+        writer.set_lump_data(LumpIndex::Visibility, visibility);
+        writer.set_lump(LumpIndex::Nodes, &nodes);
+        writer.set_lump(LumpIndex::TextureInfo, &texture_infos);
+        writer.set_lump(LumpIndex::Faces, &faces);
+        writer.set_lump_version(LumpIndex::Leafs, 1);
+        writer.set_lump(LumpIndex::Leafs, &leafs);
+        writer.set_lump(LumpIndex::Edges, &edges);
+        writer.set_lump(LumpIndex::SurfaceEdges, &surface_edges);
+        writer.set_lump(LumpIndex::Models, &[Model {
+            mins: world_mins,
+            maxs: world_maxs,
+            head_node: 0,
+            first_face: 0,
+            num_faces: faces.len() as i32,
+            ..Default::default()
+        }]);
+        writer.set_lump(LumpIndex::LeafFaces, &(0..faces.len() as u16).collect::<Vec<_>>());
+        writer.set_lump(LumpIndex::LeafBrushes, &(0..brushes.len() as u16).collect::<Vec<_>>());
+        writer.set_lump(LumpIndex::Brushes, &brushes);
+        writer.set_lump(LumpIndex::BrushSides, &brush_sides);
+        writer.set_lump(LumpIndex::TextureStringTable, &name_offsets);
+        writer.set_lump_data(LumpIndex::TextureStringData, names);
+        if !self.files.is_empty() {
+            let files: Vec<(&str, &[u8])> = self.files.iter().map(|(n, d)| (n.as_str(), d.as_slice())).collect();
+            writer.set_lump_data(LumpIndex::PakFile, write_pak_file(&files));
+        }
+        writer
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.build().write(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    // Around every brush, or nothing at the origin
+    fn bounds(&self) -> (Vector, Vector) {
+        match self.brushes.first() {
+            Some(first) => self.brushes.iter().fold((first.mins, first.maxs), |(mins, maxs), b| (mins.min(&b.mins), maxs.max(&b.maxs))),
+            None => (Vector::default(), Vector::default()),
+        }
+    }
+}
+
+// The index of the plane facing along the axis (or against it) through point,
+// added along with its opposite if it's not there yet. Like vbsp, pairs start on even indices
+// with the positive facing plane first
+fn plane_pair(planes: &mut Vec<Plane>, axis: usize, positive: bool, point: Vector) -> usize {
+    let mut normal = [0.0; 3];
+    normal[axis] = 1.0;
+    let normal = Vector::new(normal[0], normal[1], normal[2]);
+    let distance = normal.dot(&point);
+    let pair = match planes.iter().step_by(2).position(|p| p.normal == normal && p.distance == distance) {
+        Some(pair) => pair * 2,
+        None => {
+            planes.push(Plane { normal, distance, kind: axis as i32 });
+            planes.push(Plane { normal: -normal, distance: -distance, kind: axis as i32 });
+            planes.len() - 2
+        },
+    };
+    if positive { pair } else { pair + 1 }
+}
+
+// A box's side, clockwise seen from outside like the engine expects
+fn box_side(mins: Vector, maxs: Vector, axis: usize, positive: bool) -> [Vector; 4] {
+    let get = |v: Vector, i: usize| [v.x, v.y, v.z][i];
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+    let fixed = if positive { get(maxs, axis) } else { get(mins, axis) };
+    let point = |a: f32, b: f32| {
+        let mut p = [0.0; 3];
+        p[axis] = fixed;
+        p[u] = a;
+        p[v] = b;
+        Vector::new(p[0], p[1], p[2])
+    };
+    let (u0, u1, v0, v1) = (get(mins, u), get(maxs, u), get(mins, v), get(maxs, v));
+    // (u, v, axis) is right handed, so this goes anticlockwise seen from the positive side
+    let corners = [point(u0, v0), point(u1, v0), point(u1, v1), point(u0, v1)];
+    if positive {
+        [corners[3], corners[2], corners[1], corners[0]]
+    } else {
+        corners
+    }
+}
+
+// Projected straight along the axis, at Hammer's default scales
+fn world_aligned(axis: usize, material: usize) -> TextureInfo {
+    let (u, v) = match axis {
+        0 => (1, 2),
+        1 => (0, 2),
+        _ => (0, 1),
+    };
+    let mut texture_vecs = [[0.0; 4]; 2];
+    let mut lightmap_vecs = [[0.0; 4]; 2];
+    texture_vecs[0][u] = 1.0 / TEXTURE_SCALE;
+    texture_vecs[1][v] = -1.0 / TEXTURE_SCALE;
+    lightmap_vecs[0][u] = 1.0 / LIGHTMAP_SCALE;
+    lightmap_vecs[1][v] = -1.0 / LIGHTMAP_SCALE;
+    TextureInfo { texture_vecs, lightmap_vecs, flags: 0, texture_data: material as i32 }
+}
+
+fn to_i16(v: Vector) -> [i16; 3] {
+    [v.x, v.y, v.z].map(|c| c.clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+}
@@ -0,0 +1,245 @@
+// A bounding volume hierarchy over the map's visible triangles, for casting rays at what's rendered
+// (brush traces go through the BSP tree and brushes instead, and ignore displacements)
+// Faces with a displacement are replaced by the displaced mesh
+// Static props only have placements here, no models, so they aren't in it
+
+use super::displacement::Displacement;
+use super::error::*;
+use super::geometry::{optional, Geometry};
+use super::texture::*;
+use super::Bsp;
+use crate::Vector;
+
+// Faces that aren't drawn
+const INVISIBLE: i32 = SURF_NODRAW | SURF_TRIGGER | SURF_HINT | SURF_SKIP;
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BvhTriangle {
+    pub vertices: [Vector; 3],
+    pub face: usize,
+    // Index into Bsp::displacements() if it's part of a displacement
+    pub displacement: Option<usize>,
+    pub texture_info: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit<'a> {
+    pub face: usize,
+    pub displacement: Option<usize>,
+    pub position: Vector,
+    // Facing back towards where the ray came from
+    pub normal: Vector,
+    pub distance: f32,
+    pub material: Option<&'a str>,
+}
+
+// Leaves have count > 0 and cover triangles[start..start + count]
+// Other nodes have their first child right after them, and the second at start
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Node {
+    mins: Vector,
+    maxs: Vector,
+    start: usize,
+    count: usize,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Bvh {
+    pub triangles: Vec<BvhTriangle>,
+    nodes: Vec<Node>,
+    // By TextureInfo
+    materials: Vec<Option<String>>,
+}
+
+impl Bvh {
+    // Every visible face and displacement in the map, including brush entities
+    pub fn build(bsp: &mut Bsp) -> Result<Self> {
+        let geometry = Geometry::load(bsp)?;
+        let texture_infos = optional(bsp.texture_infos())?;
+        let texture_data = optional(bsp.texture_data())?;
+        let names = optional(bsp.texture_names())?;
+        let displacements = bsp.displacements()?;
+
+        let materials = texture_infos.iter().map(|info| {
+            texture_data.get(info.texture_data as usize)
+                .and_then(|data| names.get(data.name_string_table_id as usize))
+                .cloned()
+        }).collect();
+
+        let mut triangles = Vec::new();
+        for (i, face) in geometry.faces.iter().enumerate() {
+            let texture_info = Some(face.tex_info as usize).filter(|_| face.tex_info >= 0);
+            if texture_info.and_then(|t| texture_infos.get(t)).is_some_and(|info| info.flags & INVISIBLE != 0) {
+                continue;
+            }
+            let displacement = Some(face.disp_info as usize).filter(|_| face.disp_info >= 0);
+            match displacement.and_then(|d| displacements.get(d)) {
+                Some(disp) => triangles.extend(displacement_triangles(&geometry, disp).into_iter().map(|vertices| {
+                    BvhTriangle { vertices, face: i, displacement, texture_info }
+                })),
+                None => triangles.extend(geometry.triangulate(face).into_iter().map(|vertices| {
+                    BvhTriangle { vertices, face: i, displacement: None, texture_info }
+                })),
+            }
+        }
+
+        let mut bvh = Self::new(triangles);
+        bvh.materials = materials;
+        Ok(bvh)
+    }
+
+    // From any triangles, without materials
+    pub fn new(mut triangles: Vec<BvhTriangle>) -> Self {
+        let mut nodes = Vec::new();
+        if !triangles.is_empty() {
+            let count = triangles.len();
+            build_node(&mut triangles, 0, count, &mut nodes);
+        }
+        Self { triangles, nodes, materials: Vec::new() }
+    }
+
+    // The closest triangle the ray hits within max_distance
+    // Triangles are hit from either side
+    pub fn ray_cast(&self, origin: Vector, direction: Vector, max_distance: f32) -> Option<Hit<'_>> {
+        let direction = direction.normalized();
+        if self.nodes.is_empty() || direction == Vector::default() {
+            return None;
+        }
+        let inverse = Vector::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z);
+
+        let mut closest: Option<(usize, f32)> = None;
+        let mut stack = vec![0];
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            let limit = closest.map_or(max_distance, |(_, t)| t);
+            if !ray_hits_box(origin, inverse, node.mins, node.maxs, limit) {
+                continue;
+            }
+            if node.count > 0 {
+                for i in node.start..node.start + node.count {
+                    if let Some(t) = ray_triangle(origin, direction, &self.triangles[i].vertices) {
+                        if t <= closest.map_or(max_distance, |(_, t)| t) {
+                            closest = Some((i, t));
+                        }
+                    }
+                }
+            } else {
+                stack.push(node.start);
+                stack.push(n + 1);
+            }
+        }
+
+        closest.map(|(i, distance)| {
+            let triangle = &self.triangles[i];
+            let [a, b, c] = triangle.vertices;
+            let normal = (b - a).cross(&(c - a)).normalized();
+            Hit {
+                face: triangle.face,
+                displacement: triangle.displacement,
+                position: origin + direction * distance,
+                normal: if normal.dot(&direction) > 0.0 { -normal } else { normal },
+                distance,
+                material: triangle.texture_info.and_then(|t| self.materials.get(t)).and_then(|m| m.as_deref()),
+            }
+        })
+    }
+}
+
+fn displacement_triangles(geometry: &Geometry, displacement: &Displacement) -> Vec<[Vector; 3]> {
+    let face = match geometry.faces.get(displacement.info.map_face as usize) {
+        Some(face) => face,
+        None => return Vec::new(),
+    };
+    let (positions, triangles) = displacement.mesh(&geometry.face_vertices(face));
+    triangles.iter().map(|t| [positions[t[0]], positions[t[1]], positions[t[2]]]).collect()
+}
+
+fn bounds(triangles: &[BvhTriangle]) -> (Vector, Vector) {
+    let first = triangles[0].vertices[0];
+    triangles.iter().flat_map(|t| t.vertices.iter())
+        .fold((first, first), |(mins, maxs), v| (mins.min(v), maxs.max(v)))
+}
+
+fn centroid(triangle: &BvhTriangle) -> Vector {
+    let [a, b, c] = triangle.vertices;
+    (a + b + c) * (1.0 / 3.0)
+}
+
+fn axis(v: &Vector, axis: usize) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+// Splits triangles[start..start + count] in half along the longest axis of their centres
+fn build_node(triangles: &mut [BvhTriangle], start: usize, count: usize, nodes: &mut Vec<Node>) {
+    let (mins, maxs) = bounds(&triangles[start..start + count]);
+    let index = nodes.len();
+    nodes.push(Node { mins, maxs, start, count });
+    if count <= MAX_LEAF_TRIANGLES {
+        return;
+    }
+
+    let (centre_mins, centre_maxs) = triangles[start..start + count].iter()
+        .map(centroid)
+        .fold((Vector::new(f32::MAX, f32::MAX, f32::MAX), Vector::new(f32::MIN, f32::MIN, f32::MIN)),
+            |(mins, maxs), c| (mins.min(&c), maxs.max(&c)));
+    let extent = centre_maxs - centre_mins;
+    let split_axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+    if axis(&extent, split_axis) <= 0.0 {
+        // Every centre is in the same place, no point splitting
+        return;
+    }
+
+    let half = count / 2;
+    triangles[start..start + count].select_nth_unstable_by(half, |a, b| {
+        axis(&centroid(a), split_axis).total_cmp(&axis(&centroid(b), split_axis))
+    });
+    build_node(triangles, start, half, nodes);
+    let second = nodes.len();
+    build_node(triangles, start + half, count - half, nodes);
+    nodes[index] = Node { mins, maxs, start: second, count: 0 };
+}
+
+// Slab test, true if the ray enters the box before limit
+fn ray_hits_box(origin: Vector, inverse: Vector, mins: Vector, maxs: Vector, limit: f32) -> bool {
+    let mut near = 0.0f32;
+    let mut far = limit;
+    for i in 0..3 {
+        let (o, inv) = (axis(&origin, i), axis(&inverse, i));
+        let t1 = (axis(&mins, i) - o) * inv;
+        let t2 = (axis(&maxs, i) - o) * inv;
+        // NaN when the ray is parallel and starts on the slab's edge, which counts as inside
+        let (t1, t2) = (if t1.is_nan() { f32::NEG_INFINITY } else { t1 }, if t2.is_nan() { f32::INFINITY } else { t2 });
+        near = near.max(t1.min(t2));
+        far = far.min(t1.max(t2));
+    }
+    near <= far
+}
+
+// Möller-Trumbore, the distance along the ray if it hits
+fn ray_triangle(origin: Vector, direction: Vector, [a, b, c]: &[Vector; 3]) -> Option<f32> {
+    let edge1 = *b - *a;
+    let edge2 = *c - *a;
+    let p = direction.cross(&edge2);
+    let determinant = edge1.dot(&p);
+    if determinant.abs() < 1e-8 {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let s = origin - *a;
+    let u = s.dot(&p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&edge1);
+    let v = direction.dot(&q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(&q) * inverse;
+    if t >= 0.0 { Some(t) } else { None }
+}
@@ -0,0 +1,260 @@
+// What changed between two builds of a map
+// Lumps are compared by size and CRC-32, entities are paired up by hammerid
+// (or classname + targetname + origin when there's none), pakfile entries by name
+// and static props by model, then by whichever has the closest origin
+
+use super::entity::Entity;
+use super::error::*;
+use super::lump::LumpIndex;
+use super::pak::PakEntry;
+use super::static_prop::StaticProp;
+use super::crc::{crc32, Crc32};
+use super::Bsp;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BspDiff {
+    // (old, new), if the BSP version changed
+    pub version: Option<(u32, u32)>,
+    pub revision: Option<(u32, u32)>,
+    // Only the lumps that changed
+    pub lumps: Vec<LumpDiff>,
+    pub entities: Vec<EntityChange>,
+    pub pak_files: Vec<PakChange>,
+    pub static_props: Vec<StaticPropChange>,
+}
+
+impl BspDiff {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LumpDiff {
+    pub index: LumpIndex,
+    pub old_size: usize,
+    pub new_size: usize,
+    pub old_crc: u32,
+    pub new_crc: u32,
+    pub old_version: u32,
+    pub new_version: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntityChange {
+    Added(Entity),
+    Removed(Entity),
+    Modified { old: Entity, new: Entity, keys: Vec<KeyChange> },
+}
+
+// One key's value changing. Repeated keys (outputs) show up as a value removed and/or a value added
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyChange {
+    pub key: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PakChange {
+    Added(PakEntry),
+    Removed(PakEntry),
+    Changed { old: PakEntry, new: PakEntry },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StaticPropChange {
+    Added(StaticProp),
+    Removed(StaticProp),
+    Moved { old: StaticProp, new: StaticProp },
+}
+
+pub fn diff(old: &mut Bsp, new: &mut Bsp) -> Result<BspDiff> {
+    Ok(BspDiff {
+        version: Some((old.version, new.version)).filter(|(a, b)| a != b),
+        revision: Some((old.iteration, new.iteration)).filter(|(a, b)| a != b),
+        lumps: diff_lumps(old, new)?,
+        entities: diff_entities(&old.entities()?, &new.entities()?),
+        pak_files: diff_pak_files(&pak_entries(old)?, &pak_entries(new)?),
+        static_props: diff_static_props(&old.static_props()?, &new.static_props()?),
+    })
+}
+
+fn diff_lumps(old: &mut Bsp, new: &mut Bsp) -> Result<Vec<LumpDiff>> {
+    let mut lumps = Vec::new();
+    for &index in LumpIndex::ALL.iter() {
+        let (old_size, old_crc) = lump_hash(old, index)?;
+        let (new_size, new_crc) = lump_hash(new, index)?;
+        let old_version = old.lump_context(index).lump_version;
+        let new_version = new.lump_context(index).lump_version;
+        if (old_size, old_crc, old_version) != (new_size, new_crc, new_version) {
+            lumps.push(LumpDiff { index, old_size, new_size, old_crc, new_crc, old_version, new_version });
+        }
+    }
+    Ok(lumps)
+}
+
+// Size and CRC of a lump, including .lmp overrides
+// The GameLump's own offsets change whenever anything before it moves,
+// so it's hashed by its sub-lumps' contents instead
+fn lump_hash(bsp: &mut Bsp, index: LumpIndex) -> Result<(usize, u32)> {
+    if index == LumpIndex::GameLump {
+        let mut crc = Crc32::new();
+        let mut size = 0;
+        for lump in bsp.game_lumps()? {
+            crc.update(&lump.id);
+            crc.update(&lump.flags.to_le_bytes());
+            crc.update(&lump.version.to_le_bytes());
+            crc.update(&lump.data);
+            size += lump.data.len();
+        }
+        return Ok((size, crc.finish()));
+    }
+    match bsp.lump_slice(index) {
+        Ok(data) => Ok((data.len(), crc32(data))),
+        Err(Error::MissingLump(_)) => Ok((0, 0)),
+        Err(e) => Err(e),
+    }
+}
+
+fn pak_entries(bsp: &mut Bsp) -> Result<Vec<PakEntry>> {
+    match bsp.pak_file() {
+        Ok(pak) => Ok(pak.entries),
+        Err(Error::MissingLump(_)) => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn diff_entities(old: &[Entity], new: &[Entity]) -> Vec<EntityChange> {
+    let mut unmatched: Vec<Option<&Entity>> = new.iter().map(Some).collect();
+    let mut changes = Vec::new();
+    let mut removed = Vec::new();
+
+    // hammerid first, since it survives renames and moves
+    let mut pairs: Vec<(&Entity, &Entity)> = Vec::new();
+    let mut leftover = Vec::new();
+    for entity in old.iter() {
+        let found = entity.get("hammerid").and_then(|id| {
+            unmatched.iter().position(|e| e.is_some_and(|e| e.get("hammerid") == Some(id)))
+        });
+        match found {
+            Some(i) => pairs.push((entity, unmatched[i].take().unwrap())),
+            None => leftover.push(entity),
+        }
+    }
+    for entity in leftover {
+        let key = identity(entity);
+        match unmatched.iter().position(|e| e.is_some_and(|e| e.get("hammerid").is_none() && identity(e) == key)) {
+            Some(i) => pairs.push((entity, unmatched[i].take().unwrap())),
+            None => removed.push(entity),
+        }
+    }
+
+    for (old, new) in pairs {
+        let keys = diff_keys(old, new);
+        if !keys.is_empty() {
+            changes.push(EntityChange::Modified { old: old.clone(), new: new.clone(), keys });
+        }
+    }
+    changes.extend(removed.into_iter().map(|e| EntityChange::Removed(e.clone())));
+    changes.extend(unmatched.into_iter().flatten().map(|e| EntityChange::Added(e.clone())));
+    changes
+}
+
+fn identity(entity: &Entity) -> (String, String, String) {
+    let get = |key| entity.get(key).unwrap_or_default().to_ascii_lowercase();
+    (get("classname"), get("targetname"), get("origin"))
+}
+
+fn diff_keys(old: &Entity, new: &Entity) -> Vec<KeyChange> {
+    // Every key, in the order it first shows up
+    let mut keys: Vec<&str> = Vec::new();
+    for (key, _) in old.properties.iter().chain(new.properties.iter()) {
+        if !keys.iter().any(|k| k.eq_ignore_ascii_case(key)) {
+            keys.push(key);
+        }
+    }
+
+    let mut changes = Vec::new();
+    for key in keys {
+        let old_values: Vec<&str> = old.get_all(key).collect();
+        let new_values: Vec<&str> = new.get_all(key).collect();
+        if old_values == new_values {
+            continue;
+        }
+        let change = |old: Option<&str>, new: Option<&str>| KeyChange {
+            key: key.to_string(),
+            old: old.map(str::to_string),
+            new: new.map(str::to_string),
+        };
+        if old_values.len() <= 1 && new_values.len() <= 1 {
+            changes.push(change(old_values.first().copied(), new_values.first().copied()));
+            continue;
+        }
+        // Outputs and other repeated keys, matched up value by value
+        let mut added = new_values.clone();
+        for value in old_values {
+            match added.iter().position(|v| *v == value) {
+                Some(i) => { added.remove(i); },
+                None => changes.push(change(Some(value), None)),
+            }
+        }
+        changes.extend(added.into_iter().map(|value| change(None, Some(value))));
+    }
+    changes
+}
+
+pub fn diff_pak_files(old: &[PakEntry], new: &[PakEntry]) -> Vec<PakChange> {
+    let normalize = |s: &str| s.replace('\\', "/").to_lowercase();
+    let find = |entries: &[PakEntry], name: &str| entries.iter().find(|e| normalize(&e.name) == normalize(name)).cloned();
+
+    let mut changes = Vec::new();
+    for entry in old.iter() {
+        match find(new, &entry.name) {
+            Some(other) if other.crc != entry.crc || other.size != entry.size => {
+                changes.push(PakChange::Changed { old: entry.clone(), new: other });
+            },
+            Some(_) => {},
+            None => changes.push(PakChange::Removed(entry.clone())),
+        }
+    }
+    for entry in new.iter() {
+        if find(old, &entry.name).is_none() {
+            changes.push(PakChange::Added(entry.clone()));
+        }
+    }
+    changes
+}
+
+pub fn diff_static_props(old: &[StaticProp], new: &[StaticProp]) -> Vec<StaticPropChange> {
+    let mut unmatched: Vec<Option<&StaticProp>> = new.iter().map(Some).collect();
+    let mut leftover = Vec::new();
+
+    // Props that didn't change at all
+    for prop in old.iter() {
+        match unmatched.iter().position(|p| *p == Some(prop)) {
+            Some(i) => { unmatched[i] = None; },
+            None => leftover.push(prop),
+        }
+    }
+
+    // The rest moved (or rotated), to the closest remaining prop with the same model
+    let mut changes = Vec::new();
+    for prop in leftover {
+        let closest = unmatched.iter().enumerate()
+            .filter_map(|(i, p)| p.filter(|p| p.model.eq_ignore_ascii_case(&prop.model)).map(|p| (i, p)))
+            .min_by(|(_, a), (_, b)| {
+                let distance = |p: &StaticProp| (p.origin - prop.origin).length();
+                distance(a).total_cmp(&distance(b))
+            });
+        match closest {
+            Some((i, new)) => {
+                changes.push(StaticPropChange::Moved { old: prop.clone(), new: new.clone() });
+                unmatched[i] = None;
+            },
+            None => changes.push(StaticPropChange::Removed(prop.clone())),
+        }
+    }
+    changes.extend(unmatched.into_iter().flatten().map(|p| StaticPropChange::Added(p.clone())));
+    changes
+}
@@ -0,0 +1,281 @@
+// Displacements, the subdivided terrain surfaces
+// DisplacementInfo has one record per displacement, pointing into DisplacementVertices
+// (one per vertex) and, in newer games, PhysicsDisplacement and DisplacementMultiblend
+
+use super::error::*;
+use super::lump::LumpIndex;
+use super::lump_item::{LumpContext, LumpItem, LumpReader, LumpWriter};
+use super::physics::Solid;
+use crate::Vector;
+
+// Bits of DispInfo.min_tess in Alien Swarm and later, when DISP_INFO_FLAG_MAGIC is set
+pub const DISP_INFO_FLAG_HAS_MULTIBLEND: i32 = 0x40000000;
+pub const DISP_INFO_FLAG_MAGIC: i32 = 0x80000000u32 as i32;
+
+// ddispinfo_t
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DispInfo {
+    pub start_position: Vector,
+    pub disp_vert_start: i32,
+    pub disp_tri_start: i32,
+    // 2, 3 or 4, the displacement is (2^power + 1)^2 vertices
+    pub power: i32,
+    pub min_tess: i32,
+    pub smoothing_angle: f32,
+    pub contents: i32,
+    pub map_face: u16,
+    pub lightmap_alpha_start: i32,
+    pub lightmap_sample_position_start: i32,
+    // CDispNeighbor[4] and CDispCornerNeighbors[4], kept as they are
+    pub neighbors: [u8; 88],
+    pub allowed_verts: [u32; 10],
+}
+
+impl Default for DispInfo {
+    fn default() -> Self {
+        Self {
+            start_position: Vector::default(),
+            disp_vert_start: 0,
+            disp_tri_start: 0,
+            power: 0,
+            min_tess: 0,
+            smoothing_angle: 0.0,
+            contents: 0,
+            map_face: 0,
+            lightmap_alpha_start: 0,
+            lightmap_sample_position_start: 0,
+            neighbors: [0; 88],
+            allowed_verts: [0; 10],
+        }
+    }
+}
+
+impl DispInfo {
+    // Vertices along one side
+    pub fn side_length(&self) -> usize {
+        (1 << self.power.clamp(0, 16)) + 1
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.side_length() * self.side_length()
+    }
+
+    pub fn has_multiblend(&self) -> bool {
+        self.min_tess & DISP_INFO_FLAG_MAGIC != 0 && self.min_tess & DISP_INFO_FLAG_HAS_MULTIBLEND != 0
+    }
+}
+
+impl LumpItem for DispInfo {
+    fn size(_: &LumpContext) -> usize { 176 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        let mut info = Self {
+            start_position: r.vector(),
+            disp_vert_start: r.i32(),
+            disp_tri_start: r.i32(),
+            power: r.i32(),
+            min_tess: r.i32(),
+            smoothing_angle: r.f32(),
+            contents: r.i32(),
+            map_face: r.u16(),
+            ..Default::default()
+        };
+        r.skip(2); // padding
+        info.lightmap_alpha_start = r.i32();
+        info.lightmap_sample_position_start = r.i32();
+        info.neighbors.copy_from_slice(r.bytes(88));
+        for v in info.allowed_verts.iter_mut() {
+            *v = r.u32();
+        }
+        info
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.vector(&self.start_position);
+        w.i32(self.disp_vert_start);
+        w.i32(self.disp_tri_start);
+        w.i32(self.power);
+        w.i32(self.min_tess);
+        w.f32(self.smoothing_angle);
+        w.i32(self.contents);
+        w.u16(self.map_face);
+        w.u16(0);
+        w.i32(self.lightmap_alpha_start);
+        w.i32(self.lightmap_sample_position_start);
+        w.bytes(&self.neighbors);
+        for v in self.allowed_verts.iter() {
+            w.u32(*v);
+        }
+    }
+}
+
+// dDispVert
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DispVert {
+    // Direction from the flat surface, the vertex is at flat + vector * distance
+    pub vector: Vector,
+    pub distance: f32,
+    pub alpha: f32,
+}
+
+impl LumpItem for DispVert {
+    fn size(_: &LumpContext) -> usize { 20 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self { vector: r.vector(), distance: r.f32(), alpha: r.f32() }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.vector(&self.vector);
+        w.f32(self.distance);
+        w.f32(self.alpha);
+    }
+}
+
+// dDispMultiBlend_t, one per vertex of a displacement with DISP_INFO_FLAG_HAS_MULTIBLEND
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MultiBlend {
+    // How much of each of the four textures shows
+    pub blend: [f32; 4],
+    pub alpha_blend: [f32; 4],
+    pub colours: [Vector; 4],
+}
+
+impl LumpItem for MultiBlend {
+    fn size(_: &LumpContext) -> usize { 80 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self {
+            blend: [r.f32(), r.f32(), r.f32(), r.f32()],
+            alpha_blend: [r.f32(), r.f32(), r.f32(), r.f32()],
+            colours: [r.vector(), r.vector(), r.vector(), r.vector()],
+        }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        for v in self.blend.iter().chain(self.alpha_blend.iter()) {
+            w.f32(*v);
+        }
+        for colour in self.colours.iter() {
+            w.vector(colour);
+        }
+    }
+}
+
+// A displacement with everything that belongs to it
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Displacement {
+    pub info: DispInfo,
+    pub vertices: Vec<DispVert>,
+    // From PhysicsDisplacement, None if it has no collision (or the map has no lump)
+    pub collision: Option<Solid>,
+    // From DisplacementMultiblend, one per vertex
+    pub multiblend: Option<Vec<MultiBlend>>,
+}
+
+impl Displacement {
+    // The displaced surface in world space, from the corners of its face (Geometry::face_vertices)
+    // Vertices are row by row, rows going from the corner nearest start_position to the next corner
+    // Returns no triangles if the face isn't a quad
+    pub fn mesh(&self, corners: &[Vector]) -> (Vec<Vector>, Vec<[usize; 3]>) {
+        if corners.len() != 4 || self.vertices.len() != self.info.vertex_count() {
+            return (Vec::new(), Vec::new());
+        }
+        let start = (0..4).min_by(|&a, &b| {
+            let distance = |i: usize| (corners[i] - self.info.start_position).length();
+            distance(a).total_cmp(&distance(b))
+        }).unwrap();
+        let corner = |i: usize| corners[(start + i) % 4];
+        let lerp = |a: Vector, b: Vector, t: f32| a + (b - a) * t;
+
+        let n = self.info.side_length();
+        let step = 1.0 / (n - 1) as f32;
+        let mut positions = Vec::with_capacity(n * n);
+        for row in 0..n {
+            let left = lerp(corner(0), corner(1), row as f32 * step);
+            let right = lerp(corner(3), corner(2), row as f32 * step);
+            for column in 0..n {
+                let vertex = &self.vertices[row * n + column];
+                positions.push(lerp(left, right, column as f32 * step) + vertex.vector * vertex.distance);
+            }
+        }
+
+        let mut triangles = Vec::with_capacity((n - 1) * (n - 1) * 2);
+        for row in 0..n - 1 {
+            for column in 0..n - 1 {
+                let a = row * n + column;
+                triangles.push([a, a + n, a + 1]);
+                triangles.push([a + 1, a + n, a + n + 1]);
+            }
+        }
+        (positions, triangles)
+    }
+}
+
+// The PhysicsDisplacement lump:
+//   u16 count, u16 sizes[count], then each displacement's collision data
+// A size of 0xFFFF means that displacement has no collision
+pub fn read_physics_displacements(data: &[u8], context: LumpContext) -> Result<Vec<Option<Solid>>> {
+    let mut r = LumpReader::new(data, context);
+    if r.remaining() < 2 {
+        return Err(Error::UnexpectedEof);
+    }
+    let count = r.u16() as usize;
+    if r.remaining() < count * 2 {
+        return Err(Error::UnexpectedEof);
+    }
+    let sizes: Vec<u16> = (0..count).map(|_| r.u16()).collect();
+
+    let mut solids = Vec::with_capacity(count);
+    for size in sizes {
+        if size == 0xFFFF || size == 0 {
+            solids.push(None);
+            continue;
+        }
+        if r.remaining() < size as usize {
+            return Err(Error::UnexpectedEof);
+        }
+        solids.push(Some(Solid::read(r.bytes(size as usize), context)?));
+    }
+    Ok(solids)
+}
+
+// Put each displacement together with its vertices, collision and multiblend data
+// Multiblend data is packed: only displacements flagged with it take up entries.
+// Maps without the flags have one entry per displacement vertex instead
+pub fn assemble_displacements(
+    infos: &[DispInfo],
+    vertices: &[DispVert],
+    collision: Vec<Option<Solid>>,
+    multiblend: &[MultiBlend],
+) -> Result<Vec<Displacement>> {
+    let flagged = infos.iter().any(|info| info.min_tess & DISP_INFO_FLAG_MAGIC != 0);
+    let mut collision = collision.into_iter();
+    let mut next_multiblend = 0;
+
+    infos.iter().map(|info| {
+        let count = info.vertex_count();
+        let start = info.disp_vert_start.max(0) as usize;
+        let disp_vertices = vertices.get(start..start + count)
+            .ok_or(Error::IndexOutOfRange { lump: LumpIndex::DisplacementVertices, index: start + count - 1 })?
+            .to_vec();
+
+        let multiblend_start = match (flagged, info.has_multiblend()) {
+            (true, true) => {
+                next_multiblend += count;
+                Some(next_multiblend - count)
+            },
+            (true, false) => None,
+            (false, _) if !multiblend.is_empty() => Some(start),
+            (false, _) => None,
+        };
+        let multiblend = match multiblend_start {
+            Some(first) => Some(multiblend.get(first..first + count)
+                .ok_or(Error::IndexOutOfRange { lump: LumpIndex::DisplacementMultiblend, index: first + count - 1 })?
+                .to_vec()),
+            None => None,
+        };
+
+        Ok(Displacement { info: *info, vertices: disp_vertices, collision: collision.next().flatten(), multiblend })
+    }).collect()
+}
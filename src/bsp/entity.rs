@@ -0,0 +1,202 @@
+// https://developer.valvesoftware.com/wiki/Source_BSP_File_Format#Entity
+// The Entities lump is a list of blocks like
+// {
+// "classname" "worldspawn"
+// "skyname" "sky_day01_01"
+// }
+// Similar to KeyValues, but flat, and keys can repeat (every output is its own "OnTrigger" key)
+// so entities keep their keys in order in a Vec instead of a map
+
+use super::error::*;
+use crate::Vector;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Entity {
+    pub properties: Vec<(String, String)>,
+}
+
+impl Entity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The first value for a key. Keys are case-insensitive, like in the engine
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.properties.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    // Every value for a key, in order
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.properties.iter()
+            .filter(move |(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn classname(&self) -> Option<&str> {
+        self.get("classname")
+    }
+
+    pub fn targetname(&self) -> Option<&str> {
+        self.get("targetname")
+    }
+
+    // "x y z", None if it's missing or not three numbers
+    pub fn origin(&self) -> Option<Vector> {
+        let parts: Vec<f32> = self.get("origin")?.split_whitespace().map(|p| p.parse().ok()).collect::<Option<_>>()?;
+        match parts[..] {
+            [x, y, z] => Some(Vector::new(x, y, z)),
+            _ => None,
+        }
+    }
+
+    // Replace the first value for a key, or add it if it's not there
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.properties.iter_mut().find(|(k, _)| k.eq_ignore_ascii_case(key)) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.add(key, value),
+        }
+    }
+
+    // Add another value, even if the key is already there
+    pub fn add(&mut self, key: &str, value: &str) {
+        self.properties.push((key.to_string(), value.to_string()));
+    }
+
+    // Remove every value for a key
+    pub fn remove(&mut self, key: &str) {
+        self.properties.retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+    }
+
+    // Every output, in order. Any key whose value looks like a connection counts
+    pub fn connections(&self) -> Vec<Connection> {
+        self.properties.iter().filter_map(|(k, v)| Connection::parse(k, v)).collect()
+    }
+}
+
+// One output of an entity, stored as "OnTrigger" "target,Input,parameter,delay,times to fire"
+// Newer games separate the fields with ESC (0x1B) instead of commas, so parameters can have commas
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Connection {
+    pub output: String,
+    pub target: String,
+    pub input: String,
+    pub parameter: String,
+    pub delay: f32,
+    // -1 is forever
+    pub times_to_fire: i32,
+}
+
+impl Connection {
+    pub fn parse(output: &str, value: &str) -> Option<Self> {
+        let separator = if value.contains('\x1b') { '\x1b' } else { ',' };
+        let fields: Vec<&str> = value.split(separator).collect();
+        match fields.as_slice() {
+            [target, input, parameter, delay, times_to_fire] if !target.is_empty() && !input.is_empty() => Some(Self {
+                output: output.to_string(),
+                target: target.to_string(),
+                input: input.to_string(),
+                parameter: parameter.to_string(),
+                delay: delay.trim().parse().ok()?,
+                times_to_fire: times_to_fire.trim().parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Entity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "{{")?;
+        for (key, value) in self.properties.iter() {
+            writeln!(f, "\"{}\" \"{}\"", key, value)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+// Parse the text of an Entities lump (without the trailing null)
+pub fn parse_entities(source: &str) -> Result<Vec<Entity>> {
+    let mut entities = Vec::new();
+    let mut tokens = Tokens { chars: source.chars().peekable(), line: 1 };
+    let mut current: Option<Entity> = None;
+
+    while let Some((token, quoted)) = tokens.next() {
+        match (token.as_str(), quoted, current.as_mut()) {
+            ("{", false, None) => current = Some(Entity::new()),
+            ("}", false, Some(_)) => entities.push(current.take().unwrap()),
+            (_, _, Some(entity)) if quoted || (token != "{" && token != "}") => {
+                match tokens.next() {
+                    Some((value, true)) => entity.add(&token, &value),
+                    Some((value, false)) if value != "{" && value != "}" => entity.add(&token, &value),
+                    _ => return Err(Error::EntitySyntax { line: tokens.line }),
+                }
+            },
+            _ => return Err(Error::EntitySyntax { line: tokens.line }),
+        }
+    }
+
+    if current.is_some() {
+        return Err(Error::EntitySyntax { line: tokens.line });
+    }
+    Ok(entities)
+}
+
+// Turn entities back into the text of an Entities lump (without the trailing null)
+pub fn entities_to_string(entities: &[Entity]) -> String {
+    entities.iter().map(|e| e.to_string()).collect()
+}
+
+// The bytes of an Entities lump, with the trailing null
+pub fn entities_to_lump_data(entities: &[Entity]) -> Vec<u8> {
+    let mut data = entities_to_string(entities).into_bytes();
+    data.push(0);
+    data
+}
+
+struct Tokens<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+}
+
+impl Tokens<'_> {
+    // (token, was it in quotes)
+    fn next(&mut self) -> Option<(String, bool)> {
+        while let Some(&c) = self.chars.peek() {
+            if c == '\n' {
+                self.line += 1;
+            }
+            if !c.is_whitespace() && c != '\0' {
+                break;
+            }
+            self.chars.next();
+        }
+
+        match self.chars.next()? {
+            '"' => {
+                let mut s = String::new();
+                for c in &mut self.chars {
+                    match c {
+                        '"' => break,
+                        '\n' => { self.line += 1; s.push(c) },
+                        _ => s.push(c),
+                    }
+                }
+                Some((s, true))
+            },
+            c @ '{' | c @ '}' => Some((c.to_string(), false)),
+            c => {
+                let mut s = c.to_string();
+                while let Some(&c) = self.chars.peek() {
+                    if c.is_whitespace() || c == '"' || c == '{' || c == '}' {
+                        break;
+                    }
+                    s.push(c);
+                    self.chars.next();
+                }
+                Some((s, false))
+            },
+        }
+    }
+}
@@ -1,14 +1,107 @@
+use super::lump::LumpIndex;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
 pub enum Error {
     InvalidIdentifier(u32),
+    InvalidLumpIndex(u32),
+    // A GoldSrc/Quake map with a version other than 29 or 30
+    UnsupportedVersion(u32),
+    // The lump has no data in this map
+    MissingLump(LumpIndex),
+    // The header says the lump is somewhere past the end of the file
+    LumpOutOfBounds { index: LumpIndex, offset: u32, length: u32, file_size: u64 },
+    // Same, for one of the 15 lumps in a GoldSrc/Quake map
+    GoldSrcLumpOutOfBounds { index: usize, offset: u32, length: u32, file_size: u64 },
+    // The data ended before something that should be there
     UnexpectedEof,
+    InvalidUtf8(std::string::FromUtf8Error),
+    // The Entities lump isn't made of { "key" "value" } blocks
+    EntitySyntax { line: usize },
+    // The lump means something else in this BSP version (like lumps 22-25)
+    LumpNotInVersion { index: LumpIndex, version: u32 },
+    // A record points at another record that isn't there
+    IndexOutOfRange { lump: LumpIndex, index: usize },
+    // The text in a PhysicsCollision model isn't valid KeyValues
+    KeyValuesSyntax(crate::keyvalues::Error),
+    // A physics solid that isn't made of convex polygons (MOPP, which nothing ships)
+    UnsupportedCollision(i16),
+    // The PakFile lump isn't a zip archive, or a broken one
+    InvalidZip(&'static str),
+    // A compressed lump that isn't valid LZMA
+    InvalidLzma(&'static str),
+    UnsupportedCompression { name: String, method: u16 },
+    // An option that can't work, like a nav cell size of 0
+    InvalidOption(&'static str),
+    // Asked the PakFile for a file that isn't in it
+    MissingFile(String),
     IoError(std::io::Error),
 }
 
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
-        Self::IoError(e)
+        // read_exact() running out of file is a problem with the file, not the disk
+        if e.kind() == std::io::ErrorKind::UnexpectedEof {
+            Self::UnexpectedEof
+        } else {
+            Self::IoError(e)
+        }
+    }
+}
+
+impl From<std::string::FromUtf8Error> for Error {
+    fn from(e: std::string::FromUtf8Error) -> Self {
+        Self::InvalidUtf8(e)
     }
-}
\ No newline at end of file
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidUtf8(e) => Some(e),
+            Error::IoError(e) => Some(e),
+            Error::KeyValuesSyntax(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidIdentifier(id @ (29 | 30)) => write!(f,
+                "Invalid identifier, this looks like a version {} GoldSrc/Quake map (see bsp::goldsrc)", id),
+            Error::InvalidIdentifier(id) => write!(f,
+                "Invalid identifier {:?}, not a VBSP file",
+                String::from_utf8_lossy(&id.to_le_bytes())),
+            Error::InvalidLumpIndex(index) => write!(f, "Invalid lump index {}", index),
+            Error::UnsupportedVersion(version) => write!(f,
+                "BSP version {} isn't a GoldSrc (30) or Quake (29) map", version),
+            Error::MissingLump(index) => write!(f, "Lump {:?} ({}) is empty", index, *index as usize),
+            Error::LumpOutOfBounds { index, offset, length, file_size } => write!(f,
+                "Lump {:?} ({}) at offset {} with length {} goes past the end of the file ({} bytes)",
+                index, *index as usize, offset, length, file_size),
+            Error::GoldSrcLumpOutOfBounds { index, offset, length, file_size } => write!(f,
+                "Lump {} at offset {} with length {} goes past the end of the file ({} bytes)",
+                index, offset, length, file_size),
+            Error::UnexpectedEof => write!(f, "Unexpected end of file"),
+            Error::InvalidUtf8(e) => write!(f, "Invalid UTF-8: {}", e),
+            Error::EntitySyntax { line } => write!(f, "Invalid entity syntax on line {}", line),
+            Error::LumpNotInVersion { index, version } => write!(f,
+                "Lump {} doesn't hold {:?} in BSP version {}", *index as usize, index, version),
+            Error::IndexOutOfRange { lump, index } => write!(f,
+                "Index {} is past the end of lump {:?} ({})", index, lump, *lump as usize),
+            Error::KeyValuesSyntax(e) => write!(f, "Invalid physics KeyValues: {}", e),
+            Error::UnsupportedCollision(model_type) => write!(f,
+                "Unsupported physics collision model type {}", model_type),
+            Error::InvalidZip(reason) => write!(f, "Invalid zip archive: {}", reason),
+            Error::InvalidLzma(reason) => write!(f, "Invalid LZMA lump: {}", reason),
+            Error::InvalidOption(reason) => write!(f, "Invalid option: {}", reason),
+            Error::UnsupportedCompression { name, method } => write!(f,
+                "Can't extract {}, compression method {} isn't supported", name, method),
+            Error::MissingFile(name) => write!(f, "No file named {} in the pakfile", name),
+            Error::IoError(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+// https://developer.valvesoftware.com/wiki/Source_BSP_File_Format#Game_lump
+// The GameLump (35) is a directory of more lumps, identified by a 4 character code
+// ('sprp' for static props, 'dprp' for detail props, ...)
+// Unlike the lumps in the header, their offsets are from the start of the .bsp file,
+// not from the start of the GameLump, so they have to be fixed up whenever the GameLump moves
+
+use super::error::*;
+use super::lump_item::{Endian, LumpContext, LumpReader, LumpWriter};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameLump {
+    pub id: [u8; 4], // stored as a little-endian int, so 'sprp' is "prps" in the file
+    pub flags: u16, // 1 = LZMA compressed
+    pub version: u16,
+    pub data: Vec<u8>,
+}
+
+// dgamelump_t: id, flags, version, offset, length
+const ENTRY_SIZE: usize = 16;
+
+impl GameLump {
+    // The id as it reads in code, like "sprp"
+    pub fn name(&self) -> String {
+        self.id.iter().rev().map(|&c| c as char).collect()
+    }
+
+    pub fn id_from_name(name: &str) -> [u8; 4] {
+        let mut id = [0; 4];
+        for (i, c) in name.bytes().rev().take(4).enumerate() {
+            id[i] = c;
+        }
+        id
+    }
+
+    // Parse the GameLump's data
+    // lump_offset is where the GameLump itself sits in the file
+    pub fn read_all(data: &[u8], lump_offset: u32, endian: Endian) -> Result<Vec<GameLump>> {
+        let mut r = LumpReader::new(data, LumpContext { endian, ..Default::default() });
+        if r.remaining() < 4 {
+            return Ok(Vec::new());
+        }
+        let count = r.i32().max(0) as usize;
+        if r.remaining() < count * ENTRY_SIZE {
+            return Err(Error::UnexpectedEof);
+        }
+
+        let mut lumps = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut id = [0; 4];
+            id.copy_from_slice(r.bytes(4));
+            if endian == Endian::Big {
+                id.reverse();
+            }
+            let flags = r.u16();
+            let version = r.u16();
+            let offset = r.u32();
+            let length = r.u32() as usize;
+
+            let start = offset.wrapping_sub(lump_offset) as usize;
+            let data = match data.get(start..start.saturating_add(length)) {
+                Some(data) => data.to_vec(),
+                None if length == 0 => Vec::new(),
+                None => return Err(Error::UnexpectedEof),
+            };
+            lumps.push(GameLump { id, flags, version, data });
+        }
+        Ok(lumps)
+    }
+
+    // Build the GameLump's data, for a GameLump that will be written at lump_offset
+    pub fn write_all(lumps: &[GameLump], lump_offset: u32, endian: Endian) -> Vec<u8> {
+        let mut w = LumpWriter::new(LumpContext { endian, ..Default::default() });
+        w.i32(lumps.len() as i32);
+
+        let mut offset = lump_offset as usize + 4 + lumps.len() * ENTRY_SIZE;
+        for lump in lumps {
+            let mut id = lump.id;
+            if endian == Endian::Big {
+                id.reverse();
+            }
+            w.bytes(&id);
+            w.u16(lump.flags);
+            w.u16(lump.version);
+            w.u32(offset as u32);
+            w.u32(lump.data.len() as u32);
+            offset += lump.data.len();
+        }
+        for lump in lumps {
+            w.bytes(&lump.data);
+        }
+        w.into_bytes()
+    }
+}
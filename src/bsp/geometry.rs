@@ -0,0 +1,233 @@
+// Turning faces into polygons and triangles
+// A face's outline is a run of SurfaceEdges, each one an index into Edges
+// (negative means the edge is walked backwards), and each edge is two Vertices
+
+use super::error::*;
+use super::lump::LumpIndex;
+use super::lump_item::{LumpContext, LumpItem, LumpReader, LumpWriter};
+use super::Bsp;
+use crate::{Edge, Face, Plane, Vector};
+
+use std::collections::HashMap;
+
+// dprimitive_t
+// VBSP splits faces into primitives when a plain fan would leave T-junctions
+// (a vertex of a neighbouring face sitting on this face's edge), which show up as cracks
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Primitive {
+    pub kind: u8, // PRIM_TRILIST or PRIM_TRISTRIP
+    pub first_index: u16, // into PrimitiveIndices
+    pub index_count: u16,
+    pub first_vertex: u16, // into PrimitiveVertices
+    pub vertex_count: u16, // 0 means the indices are into the face's own vertices
+}
+
+pub const PRIM_TRILIST: u8 = 0;
+pub const PRIM_TRISTRIP: u8 = 1;
+
+impl LumpItem for Primitive {
+    fn size(_: &LumpContext) -> usize { 10 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        let kind = r.u8();
+        r.skip(1); // padding
+        Self {
+            kind,
+            first_index: r.u16(),
+            index_count: r.u16(),
+            first_vertex: r.u16(),
+            vertex_count: r.u16(),
+        }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.u8(self.kind);
+        w.u8(0);
+        w.u16(self.first_index);
+        w.u16(self.index_count);
+        w.u16(self.first_vertex);
+        w.u16(self.vertex_count);
+    }
+}
+
+impl Face {
+    // The top bit of the primitive count is a flag for disabling dynamic shadows
+    pub fn primitive_count(&self) -> usize {
+        (self.num_primitives & 0x7FFF) as usize
+    }
+}
+
+// Everything needed to build face meshes, loaded once
+// Primitive and vertex normal lumps are often missing, so they're allowed to be empty
+#[derive(Debug, Default, Clone)]
+pub struct Geometry {
+    pub planes: Vec<Plane>,
+    pub vertices: Vec<Vector>,
+    pub edges: Vec<Edge>,
+    pub surface_edges: Vec<i32>,
+    pub faces: Vec<Face>,
+    pub primitives: Vec<Primitive>,
+    pub primitive_vertices: Vec<Vector>,
+    pub primitive_indices: Vec<u16>,
+    pub vertex_normals: Vec<Vector>,
+    // One per face vertex, for every face in order
+    pub vertex_normal_indices: Vec<u16>,
+}
+
+impl Geometry {
+    pub fn load(bsp: &mut Bsp) -> Result<Self> {
+        Ok(Self {
+            planes: bsp.planes()?,
+            vertices: bsp.vertices()?,
+            edges: bsp.edges()?,
+            surface_edges: bsp.surface_edges()?,
+            faces: bsp.faces()?,
+            primitives: optional(bsp.get_lump(LumpIndex::Primitives))?,
+            primitive_vertices: optional(bsp.get_lump(LumpIndex::PrimitiveVertices))?,
+            primitive_indices: optional(bsp.get_lump(LumpIndex::PrimitiveIndices))?,
+            vertex_normals: optional(bsp.get_lump(LumpIndex::VertexNormals))?,
+            vertex_normal_indices: optional(bsp.get_lump(LumpIndex::VertexNormalIndices))?,
+        })
+    }
+
+    // Indices into vertices going around the face, in edge order
+    // Anything out of range is skipped
+    pub fn face_vertex_indices(&self, face: &Face) -> Vec<usize> {
+        let first = face.first_edge.max(0) as usize;
+        let count = face.num_edges.max(0) as usize;
+        self.surface_edges.iter().skip(first).take(count).filter_map(|&surface_edge| {
+            let edge = self.edges.get(surface_edge.unsigned_abs() as usize)?;
+            Some(if surface_edge >= 0 { edge.v[0] } else { edge.v[1] } as usize)
+        }).filter(|&v| v < self.vertices.len()).collect()
+    }
+
+    // The face's outline
+    pub fn face_vertices(&self, face: &Face) -> Vec<Vector> {
+        self.face_vertex_indices(face).into_iter().map(|v| self.vertices[v]).collect()
+    }
+
+    // Split a face into triangles
+    // Uses the face's primitives when it has them, otherwise a fan from the first vertex
+    // Triangles keep the winding of the face's edges
+    pub fn triangulate(&self, face: &Face) -> Vec<[Vector; 3]> {
+        let outline = self.face_vertices(face);
+        let first = face.first_primitive_id as usize;
+        let primitives = self.primitives.get(first..first + face.primitive_count()).unwrap_or_default();
+
+        if primitives.is_empty() {
+            return fan(&outline);
+        }
+
+        let mut triangles = Vec::new();
+        for primitive in primitives {
+            let first_vertex = primitive.first_vertex as usize;
+            let points: &[Vector] = if primitive.vertex_count == 0 {
+                &outline
+            } else {
+                self.primitive_vertices
+                    .get(first_vertex..first_vertex + primitive.vertex_count as usize)
+                    .unwrap_or_default()
+            };
+
+            let first_index = primitive.first_index as usize;
+            let indices = self.primitive_indices
+                .get(first_index..first_index + primitive.index_count as usize)
+                .unwrap_or_default();
+
+            for [a, b, c] in primitive_triangles(primitive.kind, indices) {
+                if let (Some(a), Some(b), Some(c)) = (points.get(a), points.get(b), points.get(c)) {
+                    triangles.push([*a, *b, *c]);
+                }
+            }
+        }
+        triangles
+    }
+}
+
+// Normals
+impl Geometry {
+    // The direction the face points, from its plane
+    pub fn face_normal(&self, face: &Face) -> Vector {
+        let normal = self.planes.get(face.plane_number as usize).map(|p| p.normal).unwrap_or_default();
+        if face.side != 0 { -normal } else { normal }
+    }
+
+    // For every face, the normal the engine uses at each of its vertices
+    // (in the same order as face_vertex_indices())
+    // Read from VertexNormals/VertexNormalIndices when the map has them,
+    // otherwise generated with smoothed_normals()
+    pub fn vertex_normals(&self) -> Vec<Vec<Vector>> {
+        let total: usize = self.faces.iter().map(|f| f.num_edges.max(0) as usize).sum();
+        if self.vertex_normal_indices.len() < total || self.vertex_normals.is_empty() {
+            return self.smoothed_normals();
+        }
+
+        // VRAD writes num_edges indices for each face, one face after another
+        let mut next = 0;
+        self.faces.iter().map(|face| {
+            let count = face.num_edges.max(0) as usize;
+            let normals = self.vertex_normal_indices[next..next + count].iter()
+                .map(|&i| self.vertex_normals.get(i as usize).copied().unwrap_or_else(|| self.face_normal(face)))
+                .collect();
+            next += count;
+            normals
+        }).collect()
+    }
+
+    // Generate vertex normals the way VRAD does:
+    // at each vertex, average the normals of every face touching that vertex
+    // that shares a smoothing group with this face
+    // Faces with no smoothing groups are flat shaded
+    pub fn smoothed_normals(&self) -> Vec<Vec<Vector>> {
+        let face_vertices: Vec<Vec<usize>> = self.faces.iter().map(|f| self.face_vertex_indices(f)).collect();
+        let face_normals: Vec<Vector> = self.faces.iter().map(|f| self.face_normal(f)).collect();
+
+        // vertex -> faces touching it
+        let mut touching: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (face, vertices) in face_vertices.iter().enumerate() {
+            for &v in vertices {
+                touching.entry(v).or_default().push(face);
+            }
+        }
+
+        self.faces.iter().enumerate().map(|(i, face)| {
+            face_vertices[i].iter().map(|v| {
+                let mut normal = face_normals[i];
+                if face.smoothing_group != 0 {
+                    for &other in touching[v].iter() {
+                        if other != i && self.faces[other].smoothing_group & face.smoothing_group != 0 {
+                            normal += face_normals[other];
+                        }
+                    }
+                }
+                normal.normalized()
+            }).collect()
+        }).collect()
+    }
+}
+
+// Triangles as index triples, from a list or a strip
+fn primitive_triangles(kind: u8, indices: &[u16]) -> Vec<[usize; 3]> {
+    let i = |n: usize| indices[n] as usize;
+    if kind == PRIM_TRISTRIP {
+        (2..indices.len())
+            .map(|n| if n % 2 == 0 { [i(n - 2), i(n - 1), i(n)] } else { [i(n - 1), i(n - 2), i(n)] })
+            // Strips use repeated indices to restart, which make zero-area triangles
+            .filter(|[a, b, c]| a != b && b != c && a != c)
+            .collect()
+    } else {
+        indices.chunks_exact(3).map(|t| [t[0] as usize, t[1] as usize, t[2] as usize]).collect()
+    }
+}
+
+fn fan(outline: &[Vector]) -> Vec<[Vector; 3]> {
+    (2..outline.len()).map(|i| [outline[0], outline[i - 1], outline[i]]).collect()
+}
+
+// Missing lumps are just empty
+pub(crate) fn optional<T>(result: Result<Vec<T>>) -> Result<Vec<T>> {
+    match result {
+        Err(Error::MissingLump(_)) => Ok(Vec::new()),
+        result => result,
+    }
+}
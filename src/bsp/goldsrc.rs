@@ -0,0 +1,373 @@
+// Half-Life 1 (v30) and Quake (v29) maps
+// There's no identifier, the file starts with the version and then 15 lumps of (offset, length)
+// Planes, vertices, edges and surface edges are laid out the same as in VBSP, so they're shared
+// Textures are stored in the map as miptex: 4 mip levels of 8-bit indices, and in v30 a palette after them
+// Quake's textures use the game's palette.lmp instead, so v29 textures have no palette
+
+use super::entity::{parse_entities, Entity};
+use super::error::*;
+use super::lump_item::*;
+use crate::{Edge, Plane, Vector};
+
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::Read;
+
+pub const GOLDSRC_VERSION: u32 = 30;
+pub const QUAKE_VERSION: u32 = 29;
+pub const LUMP_COUNT: usize = 15;
+
+const MIPTEX_NAME_SIZE: usize = 16;
+// name + width + height + 4 offsets
+const MIPTEX_HEADER_SIZE: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LumpIndex {
+    Entities = 0,
+    Planes = 1,
+    Textures = 2,
+    Vertices = 3,
+    Visibility = 4,
+    Nodes = 5,
+    TextureInfo = 6,
+    Faces = 7,
+    Lighting = 8,
+    ClipNodes = 9,
+    Leaves = 10,
+    MarkSurfaces = 11,
+    Edges = 12,
+    SurfaceEdges = 13,
+    Models = 14,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Lump {
+    pub offset: u32,
+    pub length: u32,
+}
+
+// dface_t, much smaller than VBSP's
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Face {
+    pub plane: u16,
+    // Non-zero if the face points the opposite way to its plane
+    pub side: u16,
+    pub first_edge: i32,
+    pub num_edges: u16,
+    pub texture_info: u16,
+    pub styles: [u8; 4],
+    // Byte offset into the Lighting lump, -1 for none
+    pub light_offset: i32,
+}
+
+impl LumpItem for Face {
+    fn size(_: &LumpContext) -> usize { 20 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self {
+            plane: r.u16(),
+            side: r.u16(),
+            first_edge: r.i32(),
+            num_edges: r.u16(),
+            texture_info: r.u16(),
+            styles: [r.u8(), r.u8(), r.u8(), r.u8()],
+            light_offset: r.i32(),
+        }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.u16(self.plane);
+        w.u16(self.side);
+        w.i32(self.first_edge);
+        w.u16(self.num_edges);
+        w.u16(self.texture_info);
+        for &style in self.styles.iter() {
+            w.u8(style);
+        }
+        w.i32(self.light_offset);
+    }
+}
+
+// texinfo_t, like VBSP's but without the lightmap vectors and pointing straight at a miptex
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TextureInfo {
+    // s and t, each (x, y, z, offset)
+    pub texture_vecs: [[f32; 4]; 2],
+    // Index into GoldSrcBsp::textures()
+    pub miptex: i32,
+    pub flags: i32,
+}
+
+impl LumpItem for TextureInfo {
+    fn size(_: &LumpContext) -> usize { 40 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        let mut texture_vecs = [[0.0; 4]; 2];
+        for v in texture_vecs.iter_mut().flat_map(|v| v.iter_mut()) {
+            *v = r.f32();
+        }
+        Self { texture_vecs, miptex: r.i32(), flags: r.i32() }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        for &v in self.texture_vecs.iter().flat_map(|v| v.iter()) {
+            w.f32(v);
+        }
+        w.i32(self.miptex);
+        w.i32(self.flags);
+    }
+}
+
+// dmodel_t, the world and brush entities
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Model {
+    pub mins: Vector,
+    pub maxs: Vector,
+    pub origin: Vector,
+    // Node 0 for drawing, the rest are clipnode hulls for collision
+    pub head_nodes: [i32; 4],
+    pub vis_leafs: i32,
+    pub first_face: i32,
+    pub num_faces: i32,
+}
+
+impl LumpItem for Model {
+    fn size(_: &LumpContext) -> usize { 64 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self {
+            mins: r.vector(),
+            maxs: r.vector(),
+            origin: r.vector(),
+            head_nodes: [r.i32(), r.i32(), r.i32(), r.i32()],
+            vis_leafs: r.i32(),
+            first_face: r.i32(),
+            num_faces: r.i32(),
+        }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.vector(&self.mins);
+        w.vector(&self.maxs);
+        w.vector(&self.origin);
+        for &node in self.head_nodes.iter() {
+            w.i32(node);
+        }
+        w.i32(self.vis_leafs);
+        w.i32(self.first_face);
+        w.i32(self.num_faces);
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MipTexture {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    // Palette indices for each mip level, None when the texture lives in a WAD
+    pub mips: Option<[Vec<u8>; 4]>,
+    // Only in v30 maps
+    pub palette: Option<Vec<[u8; 3]>>,
+}
+
+impl MipTexture {
+    // Stored in a .wad file next to the map, only the name is in the BSP
+    pub fn is_external(&self) -> bool {
+        self.mips.is_none()
+    }
+
+    // Textures starting with '{' are cut out, palette index 255 is see-through
+    pub fn is_transparent(&self) -> bool {
+        self.name.starts_with('{')
+    }
+
+    pub fn mip_size(&self, mip: usize) -> (u32, u32) {
+        let shift = mip.try_into().unwrap_or(u32::MAX);
+        let shrink = |n: u32| n.checked_shr(shift).unwrap_or(0).max(1);
+        (shrink(self.width), shrink(self.height))
+    }
+
+    // Decode a mip level with the texture's own palette
+    // None for external textures, Quake textures, or mip > 3
+    pub fn to_rgba8(&self, mip: usize) -> Option<Vec<u8>> {
+        self.to_rgba8_with_palette(mip, self.palette.as_ref()?)
+    }
+
+    // Decode a mip level with some other palette, like Quake's palette.lmp
+    pub fn to_rgba8_with_palette(&self, mip: usize, palette: &[[u8; 3]]) -> Option<Vec<u8>> {
+        let indices = self.mips.as_ref()?.get(mip)?;
+        let transparent = self.is_transparent();
+        Some(indices.iter().flat_map(|&i| {
+            let [r, g, b] = palette.get(i as usize).copied().unwrap_or_default();
+            let a = if transparent && i == 255 { 0 } else { 255 };
+            [r, g, b, a]
+        }).collect())
+    }
+}
+
+#[derive(Debug)]
+pub struct GoldSrcBsp {
+    pub version: u32,
+    pub lumps: [Lump; LUMP_COUNT],
+    data: Vec<u8>,
+}
+
+impl GoldSrcBsp {
+    pub fn from_file(path: &str) -> Result<Self> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+        Self::from_bytes(data)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        if data.len() < 4 + LUMP_COUNT * 8 {
+            return Err(Error::UnexpectedEof);
+        }
+        let mut r = LumpReader::new(&data, LumpContext::default());
+        let version = r.u32();
+        if version != GOLDSRC_VERSION && version != QUAKE_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let mut lumps = [Lump::default(); LUMP_COUNT];
+        for (index, lump) in lumps.iter_mut().enumerate() {
+            *lump = Lump { offset: r.u32(), length: r.u32() };
+            if lump.offset as u64 + lump.length as u64 > data.len() as u64 {
+                return Err(Error::GoldSrcLumpOutOfBounds {
+                    index,
+                    offset: lump.offset,
+                    length: lump.length,
+                    file_size: data.len() as u64,
+                });
+            }
+        }
+        Ok(Self { version, lumps, data })
+    }
+
+    // Checked when the file was opened, so this can't fail
+    pub fn lump_slice(&self, index: LumpIndex) -> &[u8] {
+        let lump = self.lumps[index as usize];
+        &self.data[lump.offset as usize..(lump.offset + lump.length) as usize]
+    }
+
+    fn items<T: LumpItem>(&self, index: LumpIndex) -> Vec<T> {
+        read_items(self.lump_slice(index), LumpContext { bsp_version: self.version, ..Default::default() })
+    }
+
+    pub fn entity_lump_as_string(&self) -> Result<String> {
+        let data = self.lump_slice(LumpIndex::Entities);
+        let end = data.iter().position(|&c| c == 0).unwrap_or(data.len());
+        Ok(String::from_utf8(data[..end].to_vec())?)
+    }
+
+    pub fn entities(&self) -> Result<Vec<Entity>> {
+        parse_entities(&self.entity_lump_as_string()?)
+    }
+
+    pub fn planes(&self) -> Vec<Plane> {
+        self.items(LumpIndex::Planes)
+    }
+
+    pub fn vertices(&self) -> Vec<Vector> {
+        self.items(LumpIndex::Vertices)
+    }
+
+    pub fn edges(&self) -> Vec<Edge> {
+        self.items(LumpIndex::Edges)
+    }
+
+    pub fn surface_edges(&self) -> Vec<i32> {
+        self.items(LumpIndex::SurfaceEdges)
+    }
+
+    pub fn faces(&self) -> Vec<Face> {
+        self.items(LumpIndex::Faces)
+    }
+
+    pub fn texture_infos(&self) -> Vec<TextureInfo> {
+        self.items(LumpIndex::TextureInfo)
+    }
+
+    pub fn models(&self) -> Vec<Model> {
+        self.items(LumpIndex::Models)
+    }
+
+    // The face's outline, following its surface edges
+    pub fn face_vertices(&self, face: &Face) -> Vec<Vector> {
+        let (vertices, edges, surface_edges) = (self.vertices(), self.edges(), self.surface_edges());
+        surface_edges.iter().skip(face.first_edge.max(0) as usize).take(face.num_edges as usize).filter_map(|&surface_edge| {
+            let edge = edges.get(surface_edge.unsigned_abs() as usize)?;
+            vertices.get(if surface_edge >= 0 { edge.v[0] } else { edge.v[1] } as usize).copied()
+        }).collect()
+    }
+
+    // Every miptex, indexed the same as TextureInfo::miptex
+    // Missing entries (offset -1) come back as an unnamed external texture so the indices still line up
+    pub fn textures(&self) -> Result<Vec<MipTexture>> {
+        let data = self.lump_slice(LumpIndex::Textures);
+        if data.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut r = LumpReader::new(data, LumpContext::default());
+        if r.remaining() < 4 {
+            return Err(Error::UnexpectedEof);
+        }
+        let count = r.i32().max(0) as usize;
+        if r.remaining() < count.saturating_mul(4) {
+            return Err(Error::UnexpectedEof);
+        }
+        let offsets: Vec<i32> = (0..count).map(|_| r.i32()).collect();
+        offsets.into_iter().map(|offset| {
+            if offset < 0 {
+                return Ok(MipTexture::default());
+            }
+            read_miptex(data, offset as usize, self.version == GOLDSRC_VERSION)
+        }).collect()
+    }
+}
+
+// Quick check for whether some bytes look like a GoldSrc or Quake map rather than a VBSP
+pub fn is_goldsrc(data: &[u8]) -> bool {
+    data.len() >= 4 && matches!(u32::from_le_bytes([data[0], data[1], data[2], data[3]]), GOLDSRC_VERSION | QUAKE_VERSION)
+}
+
+fn read_miptex(data: &[u8], start: usize, has_palette: bool) -> Result<MipTexture> {
+    if data.len() < start.saturating_add(MIPTEX_HEADER_SIZE) {
+        return Err(Error::UnexpectedEof);
+    }
+    let mut r = LumpReader::new(data, LumpContext::default());
+    r.seek(start);
+    let name = r.bytes(MIPTEX_NAME_SIZE);
+    let end = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+    let mut texture = MipTexture {
+        name: String::from_utf8_lossy(&name[..end]).into_owned(),
+        width: r.u32(),
+        height: r.u32(),
+        ..Default::default()
+    };
+    let offsets = [r.u32(), r.u32(), r.u32(), r.u32()];
+    if offsets[0] == 0 {
+        return Ok(texture);
+    }
+
+    let slice = |offset: u32, length: usize| {
+        let offset = start + offset as usize;
+        data.get(offset..offset.saturating_add(length)).ok_or(Error::UnexpectedEof)
+    };
+    let mut mips: [Vec<u8>; 4] = Default::default();
+    let mut end = 0;
+    for (mip, indices) in mips.iter_mut().enumerate() {
+        let (width, height) = texture.mip_size(mip);
+        let length = width as usize * height as usize;
+        *indices = slice(offsets[mip], length)?.to_vec();
+        end = offsets[mip] + length as u32;
+    }
+    if has_palette {
+        // u16 colour count, then that many RGB triples
+        let count = slice(end, 2)?;
+        let count = u16::from_le_bytes([count[0], count[1]]) as usize;
+        let colours = slice(end + 2, count * 3)?;
+        texture.palette = Some(colours.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect());
+    }
+    texture.mips = Some(mips);
+    Ok(texture)
+}
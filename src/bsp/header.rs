@@ -1,60 +1,114 @@
 use super::error::*;
-use super::lump::Lump;
+use super::lump::{Lump, LumpLayout};
+use super::lump_item::{Endian, LumpContext, LumpReader, LumpWriter};
 
-use std::io::Read;
+use std::io::{Read, Write};
 
 pub const VBSP_HEADER: u32 = 0x50534256;
+// VBSP_HEADER as read from a big-endian console map ("PSBV")
+pub const VBSP_HEADER_SWAPPED: u32 = 0x56425350;
+
+// identifier + version + 64 lumps + iteration
+pub const HEADER_SIZE: u32 = 4 + 4 + 64 * 16 + 4;
 
 #[derive(Debug)]
 pub struct Header {
     pub version: u32,
     pub lumps: [Lump; 64],
     pub iteration: u32,
+    pub layout: LumpLayout,
+    pub endian: Endian,
 }
 
 impl Header {
+    // Read a header, figuring out the lump layout from the lump offsets
     pub fn read<T: Read>(file: &mut T) -> Result<Self> {
-        read_identifier(file)?;
+        Self::read_inner(file, None)
+    }
+
+    // Read a header with a known lump layout, skipping detection
+    pub fn read_with_layout<T: Read>(file: &mut T, layout: LumpLayout) -> Result<Self> {
+        Self::read_inner(file, Some(layout))
+    }
 
-        let version = read_version(file)?;
+    fn read_inner<T: Read>(file: &mut T, layout: Option<LumpLayout>) -> Result<Self> {
+        let endian = read_identifier(file)?;
 
+        let version = read_u32(file, endian)?;
+
+        let mut raw = [[0; 16]; 64];
+        for bytes in raw.iter_mut() {
+            file.read_exact(bytes)?;
+        }
+
+        let layout = layout.unwrap_or_else(|| detect_layout(&raw, endian));
         let mut lumps = [Lump::default(); 64];
-        for i in 0..64 {
-            lumps[i] = Lump::read(file)?;
+        for (lump, bytes) in lumps.iter_mut().zip(raw.iter()) {
+            *lump = Lump::from_bytes(bytes, layout, endian);
         }
 
-        let mut iteration = [0; 4];
-        file.read(&mut iteration)?;
-        let iteration = u32::from_le_bytes(iteration);
+        let iteration = read_u32(file, endian)?;
 
-        Ok(Self { version, lumps, iteration})
+        Ok(Self { version, lumps, iteration, layout, endian })
     }
-}
 
-fn read_identifier<T: Read>(file: &mut T) -> Result<()> {
-    // Every valid Source Engine BSP starts with "VBSP" as an unsigned 4-byte integer
-    // If it's not present, the file may be corrupt or just not actually a BSP
-    fn is_valid_identifier(id: u32) -> bool {
-        id == VBSP_HEADER
+    pub fn write<T: Write>(&self, file: &mut T) -> Result<()> {
+        let mut w = LumpWriter::new(LumpContext { endian: self.endian, ..Default::default() });
+        w.u32(VBSP_HEADER);
+        w.u32(self.version);
+        for lump in self.lumps.iter() {
+            w.bytes(&lump.to_bytes(self.layout, self.endian));
+        }
+        w.u32(self.iteration);
+        file.write_all(&w.into_bytes())?;
+        Ok(())
     }
+}
 
-    // Read the first 4 bytes
-    let mut id: [u8; 4] = [0; 4];
-    file.read(&mut id)?;
+// Left 4 Dead 2 uses the same version number as other branches,
+// so the only way to tell the layouts apart is to check which one
+// gives sensible offsets. Lump data can never start inside the header,
+// and a lump with no data has both its offset and length zeroed.
+// Read as Standard, an L4D2 lump's "offset" is really its version,
+// which is either 0 (looks like a missing lump) or a small number (inside the header)
+fn detect_layout(raw: &[[u8; 16]; 64], endian: Endian) -> LumpLayout {
+    // Some(number of lumps with data) if every lump looks valid
+    let score = |layout: LumpLayout| -> Option<usize> {
+        let mut present = 0;
+        for bytes in raw.iter() {
+            let lump = Lump::from_bytes(bytes, layout, endian);
+            if lump.offset == 0 && lump.length == 0 {
+                continue;
+            }
+            if lump.offset < HEADER_SIZE {
+                return None;
+            }
+            present += 1;
+        }
+        Some(present)
+    };
 
-    // Convert to a u32
-    let id = u32::from_le_bytes(id);
+    match (score(LumpLayout::Standard), score(LumpLayout::Left4Dead2)) {
+        (Some(standard), Some(l4d2)) if l4d2 > standard => LumpLayout::Left4Dead2,
+        (None, Some(_)) => LumpLayout::Left4Dead2,
+        _ => LumpLayout::Standard,
+    }
+}
 
-    if !is_valid_identifier(id) {
-        Err(Error::InvalidIdentifier(id))
-    } else {
-        Ok(())
+// Every valid Source Engine BSP starts with "VBSP" as an unsigned 4-byte integer
+// Console maps store it big-endian, so it reads as "PSBV", and so does everything after it
+// If neither is present, the file may be corrupt or just not actually a BSP
+fn read_identifier<T: Read>(file: &mut T) -> Result<Endian> {
+    let id = read_u32(file, Endian::Little)?;
+    match id {
+        VBSP_HEADER => Ok(Endian::Little),
+        VBSP_HEADER_SWAPPED => Ok(Endian::Big),
+        _ => Err(Error::InvalidIdentifier(id)),
     }
 }
 
-fn read_version<T: Read>(file: &mut T) -> Result<u32> {
-    let mut version: [u8; 4] = [0; 4];
-    file.read(&mut version)?;
-    let version = u32::from_le_bytes(version);
-    Ok(version)
-}
\ No newline at end of file
+fn read_u32<T: Read>(file: &mut T, endian: Endian) -> Result<u32> {
+    let mut bytes = [0; 4];
+    file.read_exact(&mut bytes)?;
+    Ok(LumpReader::new(&bytes, LumpContext { endian, ..Default::default() }).u32())
+}
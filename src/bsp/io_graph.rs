@@ -0,0 +1,265 @@
+// The map's logic as a graph: every entity output is an edge to the entities it targets
+// Targets are matched the way the engine does it:
+//   targetname (case-insensitive, a trailing * matches any suffix), then classname if nothing had that name
+//   !self is the entity firing the output, other ! names (!activator, !player, ...) are only known in-game
+
+use super::entity::{Connection, Entity};
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IoTarget {
+    // Indices into the entity list
+    Entities(Vec<usize>),
+    // !activator, !caller, !player, ... lowercased
+    Special(String),
+    // Nothing in the map has that name
+    Missing,
+}
+
+// One output, from entity `from`
+#[derive(Debug, Clone, PartialEq)]
+pub struct IoEdge {
+    pub from: usize,
+    pub connection: Connection,
+    pub target: IoTarget,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct IoNode {
+    pub classname: String,
+    pub targetname: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct IoGraph {
+    // One per entity, in the same order
+    pub nodes: Vec<IoNode>,
+    pub edges: Vec<IoEdge>,
+}
+
+// Logic entities that only do something when they get an input
+const INPUT_DRIVEN: [&str; 2] = ["logic_", "math_"];
+// ...except these, which fire outputs by themselves
+const SELF_FIRING: [&str; 5] = ["logic_auto", "logic_timer", "logic_eventlistener", "logic_playerproxy", "logic_gameevent_listener"];
+
+impl IoGraph {
+    pub fn new(entities: &[Entity]) -> Self {
+        let nodes = entities.iter().map(|e| IoNode {
+            classname: e.classname().unwrap_or_default().to_string(),
+            targetname: e.targetname().map(str::to_string),
+        }).collect();
+
+        let mut edges = Vec::new();
+        for (from, entity) in entities.iter().enumerate() {
+            for connection in entity.connections() {
+                let target = resolve(entities, from, &connection.target);
+                edges.push(IoEdge { from, connection, target });
+            }
+        }
+        Self { nodes, edges }
+    }
+
+    // Outputs pointing at a name nothing in the map has
+    pub fn dangling(&self) -> impl Iterator<Item = &IoEdge> {
+        self.edges.iter().filter(|e| e.target == IoTarget::Missing)
+    }
+
+    // Outputs going into an entity
+    pub fn inputs_to(&self, entity: usize) -> impl Iterator<Item = &IoEdge> {
+        self.edges.iter().filter(move |e| matches!(&e.target, IoTarget::Entities(t) if t.contains(&entity)))
+    }
+
+    // Logic entities (logic_relay, math_counter, ...) that nothing can ever trigger,
+    // following outputs from everything that can fire on its own (triggers, buttons, logic_auto, ...)
+    pub fn unreachable(&self) -> Vec<usize> {
+        let adjacency = self.adjacency();
+        let input_driven: Vec<bool> = self.nodes.iter().map(|n| {
+            let classname = n.classname.to_ascii_lowercase();
+            INPUT_DRIVEN.iter().any(|p| classname.starts_with(p)) && !SELF_FIRING.contains(&classname.as_str())
+        }).collect();
+
+        let mut reached: Vec<bool> = input_driven.iter().map(|d| !d).collect();
+        let mut queue: VecDeque<usize> = (0..self.nodes.len()).filter(|&i| reached[i]).collect();
+        while let Some(v) = queue.pop_front() {
+            for &w in adjacency[v].iter() {
+                if !reached[w] {
+                    reached[w] = true;
+                    queue.push_back(w);
+                }
+            }
+        }
+        (0..self.nodes.len()).filter(|&i| !reached[i]).collect()
+    }
+
+    // Groups of entities that can trigger each other in a loop, each sorted
+    // An entity that targets itself is a cycle of one
+    pub fn cycles(&self) -> Vec<Vec<usize>> {
+        let adjacency = self.adjacency();
+        let mut cycles: Vec<Vec<usize>> = strongly_connected(&adjacency).into_iter()
+            .filter(|c| c.len() > 1 || adjacency[c[0]].contains(&c[0]))
+            .map(|mut c| { c.sort_unstable(); c })
+            .collect();
+        cycles.sort();
+        cycles
+    }
+
+    // Graphviz DOT, only entities with connections are included
+    // Missing targets are red, special targets are boxes
+    pub fn to_dot(&self) -> String {
+        let mut used = vec![false; self.nodes.len()];
+        for edge in self.edges.iter() {
+            used[edge.from] = true;
+            if let IoTarget::Entities(targets) = &edge.target {
+                for &t in targets.iter() {
+                    used[t] = true;
+                }
+            }
+        }
+
+        let mut dot = String::from("digraph io {\n");
+        for (i, node) in self.nodes.iter().enumerate().filter(|(i, _)| used[*i]) {
+            let label = match &node.targetname {
+                Some(name) => format!("{}\n{}", name, node.classname),
+                None => node.classname.clone(),
+            };
+            writeln!(dot, "\te{} [label=\"{}\"];", i, escape(&label)).unwrap();
+        }
+        let mut extra: Vec<String> = Vec::new();
+        for edge in self.edges.iter() {
+            let c = &edge.connection;
+            let label = escape(&format!("{} > {}{}", c.output, c.input,
+                if c.parameter.is_empty() { String::new() } else { format!("({})", c.parameter) }));
+            match &edge.target {
+                IoTarget::Entities(targets) => {
+                    for t in targets.iter() {
+                        writeln!(dot, "\te{} -> e{} [label=\"{}\"];", edge.from, t, label).unwrap();
+                    }
+                },
+                IoTarget::Special(name) => {
+                    writeln!(dot, "\te{} -> \"{}\" [label=\"{}\"];", edge.from, escape(name), label).unwrap();
+                    if !extra.contains(name) {
+                        writeln!(dot, "\t\"{}\" [shape=box];", escape(name)).unwrap();
+                        extra.push(name.clone());
+                    }
+                },
+                IoTarget::Missing => {
+                    let node = format!("missing:{}", c.target);
+                    writeln!(dot, "\te{} -> \"{}\" [label=\"{}\", color=red];", edge.from, escape(&node), label).unwrap();
+                    if !extra.contains(&node) {
+                        writeln!(dot, "\t\"{}\" [label=\"{}\", color=red, style=dashed];", escape(&node), escape(&c.target)).unwrap();
+                        extra.push(node);
+                    }
+                },
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    // Entity -> entities it sends inputs to, without repeats
+    fn adjacency(&self) -> Vec<Vec<usize>> {
+        let mut adjacency = vec![Vec::new(); self.nodes.len()];
+        for edge in self.edges.iter() {
+            if let IoTarget::Entities(targets) = &edge.target {
+                for &t in targets.iter() {
+                    if !adjacency[edge.from].contains(&t) {
+                        adjacency[edge.from].push(t);
+                    }
+                }
+            }
+        }
+        adjacency
+    }
+}
+
+fn resolve(entities: &[Entity], from: usize, target: &str) -> IoTarget {
+    if target.eq_ignore_ascii_case("!self") {
+        return IoTarget::Entities(vec![from]);
+    }
+    if target.starts_with('!') {
+        return IoTarget::Special(target.to_ascii_lowercase());
+    }
+    let find = |key: &str| -> Vec<usize> {
+        entities.iter().enumerate()
+            .filter(|(_, e)| e.get(key).is_some_and(|name| name_matches(target, name)))
+            .map(|(i, _)| i)
+            .collect()
+    };
+    let mut found = find("targetname");
+    if found.is_empty() {
+        found = find("classname");
+    }
+    if found.is_empty() { IoTarget::Missing } else { IoTarget::Entities(found) }
+}
+
+// The engine only supports * at the end
+fn name_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.get(..prefix.len()).is_some_and(|start| start.eq_ignore_ascii_case(prefix)),
+        None => pattern.eq_ignore_ascii_case(name),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// Tarjan's algorithm, without recursion so huge maps don't overflow the stack
+fn strongly_connected(adjacency: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    const UNVISITED: usize = usize::MAX;
+    let mut index = vec![UNVISITED; adjacency.len()];
+    let mut low = vec![0; adjacency.len()];
+    let mut on_stack = vec![false; adjacency.len()];
+    let mut stack = Vec::new();
+    let mut next = 0;
+    let mut components = Vec::new();
+
+    for root in 0..adjacency.len() {
+        if index[root] != UNVISITED {
+            continue;
+        }
+        // (node, how many of its children have been looked at)
+        let mut work = vec![(root, 0)];
+        index[root] = next;
+        low[root] = next;
+        next += 1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        while let Some(&(v, child)) = work.last() {
+            if let Some(&w) = adjacency[v].get(child) {
+                work.last_mut().unwrap().1 += 1;
+                if index[w] == UNVISITED {
+                    index[w] = next;
+                    low[w] = next;
+                    next += 1;
+                    stack.push(w);
+                    on_stack[w] = true;
+                    work.push((w, 0));
+                } else if on_stack[w] {
+                    low[v] = low[v].min(index[w]);
+                }
+                continue;
+            }
+
+            work.pop();
+            if let Some(&(parent, _)) = work.last() {
+                low[parent] = low[parent].min(low[v]);
+            }
+            if low[v] == index[v] {
+                let mut component = Vec::new();
+                while let Some(w) = stack.pop() {
+                    on_stack[w] = false;
+                    component.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+        }
+    }
+    components
+}
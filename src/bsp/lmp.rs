@@ -0,0 +1,102 @@
+// https://developer.valvesoftware.com/wiki/Lump_file_format
+// A .lmp file replaces a single lump of a .bsp without recompiling the map
+// The engine looks for mapname_l_0.lmp, mapname_l_1.lmp, ... next to the .bsp
+// and stops at the first one that doesn't exist
+// Mostly used to ship entity patches
+
+use super::error::*;
+use super::entity::{entities_to_lump_data, Entity};
+use super::lump::LumpIndex;
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::convert::TryInto;
+use std::path::{Path, PathBuf};
+
+// offset + index + version + length + map revision
+pub const LMP_HEADER_SIZE: u32 = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LumpFile {
+    pub offset: u32, // where the data starts in the .lmp, right after the header
+    pub index: u32, // which lump of the .bsp this replaces
+    pub version: u32,
+    pub length: u32,
+    pub map_revision: u32, // the .bsp's iteration this was made for
+    pub data: Vec<u8>,
+}
+
+impl LumpFile {
+    pub fn new(index: LumpIndex, version: u32, map_revision: u32, data: Vec<u8>) -> Self {
+        Self {
+            offset: LMP_HEADER_SIZE,
+            index: index as u32,
+            version,
+            length: data.len() as u32,
+            map_revision,
+            data,
+        }
+    }
+
+    // An entity patch for the given revision of a map (Bsp.iteration)
+    pub fn from_entities(entities: &[Entity], map_revision: u32) -> Self {
+        Self::new(LumpIndex::Entities, 0, map_revision, entities_to_lump_data(entities))
+    }
+
+    pub fn from_file(path: &str) -> Result<Self> {
+        Self::read(&mut File::open(path)?)
+    }
+
+    pub fn read<T: Read>(file: &mut T) -> Result<Self> {
+        let mut bytes = [0; LMP_HEADER_SIZE as usize];
+        file.read_exact(&mut bytes)?;
+
+        let field = |i: usize| u32::from_le_bytes(bytes[i*4..i*4+4].try_into().unwrap());
+        let offset = field(0);
+        let index = field(1);
+        let version = field(2);
+        let length = field(3);
+        let map_revision = field(4);
+
+        if index >= 64 {
+            return Err(Error::InvalidLumpIndex(index));
+        }
+
+        // The data should follow the header directly, but skip ahead if it doesn't
+        // Both come from the file, so read through take() instead of allocating
+        // whatever the header claims up front
+        if offset > LMP_HEADER_SIZE {
+            let padding = (offset - LMP_HEADER_SIZE) as u64;
+            if std::io::copy(&mut file.by_ref().take(padding), &mut std::io::sink())? != padding {
+                return Err(Error::UnexpectedEof);
+            }
+        }
+
+        let mut data = Vec::new();
+        file.take(length as u64).read_to_end(&mut data)?;
+        if data.len() != length as usize {
+            return Err(Error::UnexpectedEof);
+        }
+
+        Ok(Self { offset, index, version, length, map_revision, data })
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        self.write(&mut File::create(path)?)
+    }
+
+    pub fn write<T: Write>(&self, file: &mut T) -> Result<()> {
+        for field in [LMP_HEADER_SIZE, self.index, self.version, self.data.len() as u32, self.map_revision].iter() {
+            file.write_all(&field.to_le_bytes())?;
+        }
+        file.write_all(&self.data)?;
+        Ok(())
+    }
+}
+
+// mapname.bsp -> mapname_l_<number>.lmp, in the same directory
+pub fn lump_file_path<P: AsRef<Path>>(bsp_path: P, number: usize) -> PathBuf {
+    let bsp_path = bsp_path.as_ref();
+    let stem = bsp_path.file_stem().unwrap_or_default().to_string_lossy();
+    bsp_path.with_file_name(format!("{}_l_{}.lmp", stem, number))
+}
@@ -1,7 +1,8 @@
 use super::error::*;
 
+use super::lump_item::{Endian, LumpContext, LumpReader, LumpWriter};
+
 use std::io::Read;
-use std::convert::TryInto;
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct Lump {
@@ -11,19 +12,57 @@ pub struct Lump {
     pub indent_code: [u8; 4],
 }
 
+// The order of the fields in each lump_t
+// Most games store offset, length, version, fourCC
+// but Left 4 Dead 2 (and Contagion) moved the version to the front
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum LumpLayout {
+    #[default]
+    Standard,   // offset, length, version, fourCC
+    Left4Dead2, // version, offset, length, fourCC
+}
+
 impl Lump {
+    // A standard little-endian lump_t, like every PC map has
     pub fn read<T: Read>(file: &mut T) -> Result<Self> {
+        Self::read_with_layout(file, LumpLayout::Standard, Endian::Little)
+    }
+
+    pub fn read_with_layout<T: Read>(file: &mut T, layout: LumpLayout, endian: Endian) -> Result<Self> {
         // 1x 16 byte read is better than 4x 4 byte reads
         let mut bytes = [0; 16];
-        file.read(&mut bytes)?;
+        file.read_exact(&mut bytes)?;
+        Ok(Self::from_bytes(&bytes, layout, endian))
+    }
+
+    pub fn from_bytes(bytes: &[u8; 16], layout: LumpLayout, endian: Endian) -> Self {
+        let mut r = LumpReader::new(bytes, LumpContext { endian, ..Default::default() });
+        let field = [r.u32(), r.u32(), r.u32()];
+        let mut indent_code = [0; 4];
+        indent_code.copy_from_slice(r.bytes(4));
 
-        // Nothing wrong with unwrapping hardcoded values
-        let offset = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
-        let length = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
-        let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
-        let indent_code: [u8; 4] = bytes[12..16].try_into().unwrap();
+        let (offset, length, version) = match layout {
+            LumpLayout::Standard => (field[0], field[1], field[2]),
+            LumpLayout::Left4Dead2 => (field[1], field[2], field[0]),
+        };
 
-        Ok(Self { offset, length, version, indent_code })
+        Self { offset, length, version, indent_code }
+    }
+
+    pub fn to_bytes(&self, layout: LumpLayout, endian: Endian) -> [u8; 16] {
+        let fields = match layout {
+            LumpLayout::Standard => [self.offset, self.length, self.version],
+            LumpLayout::Left4Dead2 => [self.version, self.offset, self.length],
+        };
+        let mut w = LumpWriter::new(LumpContext { endian, ..Default::default() });
+        for field in fields.iter() {
+            w.u32(*field);
+        }
+        w.bytes(&self.indent_code);
+
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&w.into_bytes());
+        bytes
     }
 
     pub fn exists(&self) -> bool {
@@ -40,7 +79,7 @@ impl Lump {
 // but the data is usually somewhere towards the end of the file
 // Different games/engine branches can have different formats
 // the differences are commented
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LumpIndex {
     Entities        = 0,
     Planes          = 1,
@@ -65,7 +104,7 @@ pub enum LumpIndex {
     Areas           = 20,
     AreaPortals     = 21,
 
-    // 22-25: unused in BSP Version 20+
+    // 22-25: portals before BSP Version 20, unused or static prop collision after (see portal.rs)
     Portals         = 22, // UNUSED0 in 2007(TF2), PROPCOLLISION in 2009(L4D)
     Clusters        = 23, // TF2: UNUSED1, L4D: PROPHULLS
     PortalVerts     = 24, // TF2: UNUSED2, L4D: PROPHULLVERTS
@@ -119,3 +158,24 @@ pub enum LumpIndex {
     PhysicsLevel            = 62, // Source 2009
     DisplacementMultiblend  = 63, // Source 2010 (Alien Swarm)
 }
+
+impl LumpIndex {
+    // Every lump, in header order, so LumpIndex::ALL[i] is the lump at header.lumps[i]
+    pub const ALL: [LumpIndex; 64] = {
+        use LumpIndex::*;
+        [
+            Entities, Planes, TextureData, Vertices, Visibility, Nodes, TextureInfo, Faces,
+            Lighting, Occlusion, Leafs, FaceIds, Edges, SurfaceEdges, Models, WorldLights,
+            LeafFaces, LeafBrushes, Brushes, BrushSides, Areas, AreaPortals, Portals, Clusters,
+            PortalVerts, ClusterPortals, DisplacementInfo, OriginalFaces, PhysicsDisplacement,
+            PhysicsCollision, VertexNormals, VertexNormalIndices, DisplacementLightmapAlphas,
+            DisplacementVertices, DisplacementLightmapSamplePositions, GameLump, LeafWaterData,
+            Primitives, PrimitiveVertices, PrimitiveIndices, PakFile, ClipPortalVertices, Cubemaps,
+            TextureStringData, TextureStringTable, Overlays, LeafDistanceToWater,
+            FaceMacroTextureInfo, DisplacementTris, PhysicsCollideSurface, WaterOverlays,
+            LightMapPages, LightMapPageInfo, LightingHdr, WorldLightsHdr, LeafAmbientLightingHdr,
+            LeafAmbientLighting, XZipPakFile, FacesHdr, MapFlags, OverlayFades,
+            OverlaySystemLevels, PhysicsLevel, DisplacementMultiblend,
+        ]
+    };
+}
@@ -0,0 +1,324 @@
+// Typed access to the records stored in lumps
+// Most lumps are just a packed array of one C struct (dplane_t, dface_t, ...)
+// LumpItem describes how to read and write one of those structs
+
+use crate::{Edge, Face, Plane, Vector};
+
+use std::convert::TryInto;
+
+// PC maps are little-endian, console ports (Xbox 360, PS3) are big-endian
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+// Everything a record might need to know to decode itself
+// Some structs change layout between BSP versions or lump versions
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LumpContext {
+    pub bsp_version: u32,
+    pub lump_version: u32,
+    pub endian: Endian,
+}
+
+pub trait LumpItem: Sized {
+    // Size of one record in bytes
+    fn size(context: &LumpContext) -> usize;
+    fn read(r: &mut LumpReader) -> Self;
+    fn write(&self, w: &mut LumpWriter);
+}
+
+// Decode as many whole records as fit in the data
+pub fn read_items<T: LumpItem>(data: &[u8], context: LumpContext) -> Vec<T> {
+    let size = T::size(&context);
+    let count = data.len().checked_div(size).unwrap_or(0);
+    let mut r = LumpReader::new(&data[..count * size], context);
+    (0..count).map(|_| T::read(&mut r)).collect()
+}
+
+pub fn write_items<T: LumpItem>(items: &[T], context: LumpContext) -> Vec<u8> {
+    let mut w = LumpWriter::new(context);
+    for item in items {
+        item.write(&mut w);
+    }
+    w.into_bytes()
+}
+
+macro_rules! from_bytes {
+    ($reader:expr, $t:ty) => {{
+        let bytes = $reader.array();
+        match $reader.context.endian {
+            Endian::Little => <$t>::from_le_bytes(bytes),
+            Endian::Big => <$t>::from_be_bytes(bytes),
+        }
+    }};
+}
+
+macro_rules! to_bytes {
+    ($writer:expr, $v:expr) => {{
+        let bytes = match $writer.context.endian {
+            Endian::Little => $v.to_le_bytes(),
+            Endian::Big => $v.to_be_bytes(),
+        };
+        $writer.bytes(&bytes);
+    }};
+}
+
+// Walks through lump data, reading values in the context's byte order
+// Panics when reading past the end, so check remaining() for variable-length data
+pub struct LumpReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    pub context: LumpContext,
+}
+
+impl<'a> LumpReader<'a> {
+    pub fn new(bytes: &'a [u8], context: LumpContext) -> Self {
+        Self { bytes, position: 0, context }
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.position)
+    }
+
+    pub fn seek(&mut self, position: usize) {
+        self.position = position;
+    }
+
+    pub fn skip(&mut self, count: usize) {
+        self.position += count;
+    }
+
+    pub fn bytes(&mut self, count: usize) -> &'a [u8] {
+        let bytes = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        bytes
+    }
+
+    fn array<const N: usize>(&mut self) -> [u8; N] {
+        self.bytes(N).try_into().unwrap()
+    }
+
+    pub fn u8(&mut self) -> u8 {
+        self.array::<1>()[0]
+    }
+
+    pub fn i8(&mut self) -> i8 {
+        self.u8() as i8
+    }
+
+    pub fn u16(&mut self) -> u16 {
+        from_bytes!(self, u16)
+    }
+
+    pub fn i16(&mut self) -> i16 {
+        from_bytes!(self, i16)
+    }
+
+    pub fn u32(&mut self) -> u32 {
+        from_bytes!(self, u32)
+    }
+
+    pub fn i32(&mut self) -> i32 {
+        from_bytes!(self, i32)
+    }
+
+    pub fn f32(&mut self) -> f32 {
+        from_bytes!(self, f32)
+    }
+
+    pub fn vector(&mut self) -> Vector {
+        Vector { x: self.f32(), y: self.f32(), z: self.f32() }
+    }
+}
+
+// The opposite of LumpReader
+pub struct LumpWriter {
+    bytes: Vec<u8>,
+    pub context: LumpContext,
+}
+
+impl LumpWriter {
+    pub fn new(context: LumpContext) -> Self {
+        Self { bytes: Vec::new(), context }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn u8(&mut self, v: u8) {
+        self.bytes.push(v);
+    }
+
+    pub fn i8(&mut self, v: i8) {
+        self.u8(v as u8);
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        to_bytes!(self, v)
+    }
+
+    pub fn i16(&mut self, v: i16) {
+        to_bytes!(self, v)
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        to_bytes!(self, v)
+    }
+
+    pub fn i32(&mut self, v: i32) {
+        to_bytes!(self, v)
+    }
+
+    pub fn f32(&mut self, v: f32) {
+        to_bytes!(self, v)
+    }
+
+    pub fn vector(&mut self, v: &Vector) {
+        self.f32(v.x);
+        self.f32(v.y);
+        self.f32(v.z);
+    }
+}
+
+// Vertices (3), VertexNormals (30), ...
+impl LumpItem for Vector {
+    fn size(_: &LumpContext) -> usize { 12 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        r.vector()
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.vector(self);
+    }
+}
+
+// SurfaceEdges (13)
+impl LumpItem for i32 {
+    fn size(_: &LumpContext) -> usize { 4 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        r.i32()
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.i32(*self);
+    }
+}
+
+// LeafFaces (16), LeafBrushes (17), VertexNormalIndices (31), ...
+impl LumpItem for u16 {
+    fn size(_: &LumpContext) -> usize { 2 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        r.u16()
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.u16(*self);
+    }
+}
+
+// dplane_t
+impl LumpItem for Plane {
+    fn size(_: &LumpContext) -> usize { 20 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self {
+            normal: r.vector(),
+            distance: r.f32(),
+            kind: r.i32(),
+        }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.vector(&self.normal);
+        w.f32(self.distance);
+        w.i32(self.kind);
+    }
+}
+
+// dedge_t
+impl LumpItem for Edge {
+    fn size(_: &LumpContext) -> usize { 4 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self { v: [r.u16(), r.u16()] }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.u16(self.v[0]);
+        w.u16(self.v[1]);
+    }
+}
+
+// dface_t
+impl LumpItem for Face {
+    fn size(_: &LumpContext) -> usize { 56 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self {
+            plane_number: r.u16(),
+            side: r.u8(),
+            is_on_node: r.u8() != 0,
+            first_edge: r.i32(),
+            num_edges: r.i16(),
+            tex_info: r.i16(),
+            disp_info: r.i16(),
+            surface_fog_volume_id: r.i16(),
+            styles: r.array(),
+            light_offset: r.i32(),
+            area: r.f32(),
+            lightmap_texture_mins_in_luxels: [r.i32(), r.i32()],
+            lightmap_texture_size_in_luxels: [r.i32(), r.i32()],
+            original_face: r.i32(),
+            num_primitives: r.u16(),
+            first_primitive_id: r.u16(),
+            smoothing_group: r.u32(),
+        }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.u16(self.plane_number);
+        w.u8(self.side);
+        w.u8(self.is_on_node as u8);
+        w.i32(self.first_edge);
+        w.i16(self.num_edges);
+        w.i16(self.tex_info);
+        w.i16(self.disp_info);
+        w.i16(self.surface_fog_volume_id);
+        w.bytes(&self.styles);
+        w.i32(self.light_offset);
+        w.f32(self.area);
+        for v in self.lightmap_texture_mins_in_luxels.iter() {
+            w.i32(*v);
+        }
+        for v in self.lightmap_texture_size_in_luxels.iter() {
+            w.i32(*v);
+        }
+        w.i32(self.original_face);
+        w.u16(self.num_primitives);
+        w.u16(self.first_primitive_id);
+        w.u32(self.smoothing_group);
+    }
+}
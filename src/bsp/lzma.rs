@@ -0,0 +1,575 @@
+// LZMA, the way Source stores compressed lumps:
+//   u32 id ("LZMA"), u32 actual size, u32 compressed size, u8 properties[5]
+// then the raw LZMA stream, with no end marker since the size is known
+// The lump's fourCC in the header holds the uncompressed size
+//
+// The encoder is simple: literals and greedy matches found through a hash chain, no repeat matches
+// or optimal parsing, so it compresses worse than Valve's tools but decodes with any LZMA decoder
+// The decoder handles everything a full encoder can produce
+
+use super::error::*;
+
+// "LZMA" as a little-endian u32
+pub const LZMA_ID: u32 = u32::from_le_bytes(*b"LZMA");
+pub const LZMA_HEADER_SIZE: usize = 17;
+
+// lc = 3, lp = 0, pb = 2, the defaults everything uses, packed as (pb * 5 + lp) * 9 + lc
+const LC: u32 = 3;
+const PB_MASK: usize = 3;
+const PROPERTIES: u8 = 0x5D;
+const DICTIONARY_SIZE: u32 = 1 << 24;
+// How much bigger than the compressed stream the output is assumed to be up front
+const RESERVE_RATIO: usize = 16;
+
+const MIN_MATCH: usize = 2;
+// Matches are found by hashing 3 bytes, so shorter ones are never found
+const MIN_FOUND_MATCH: usize = 3;
+const MAX_MATCH: usize = 273;
+const HASH_BITS: u32 = 16;
+const CHAIN_DEPTH: usize = 48;
+
+const PROB_INIT: u16 = 1024;
+const STATES: usize = 12;
+// Up to pb = 4, the encoder only uses 4 of them
+const POS_STATES: usize = 16;
+const LEN_TO_POS_STATES: usize = 4;
+const END_POS_MODEL_INDEX: u32 = 14;
+const FULL_DISTANCES: usize = 128;
+const ALIGN_BITS: u32 = 4;
+
+// Compress a lump's data, header included
+pub fn compress_lump(data: &[u8]) -> Vec<u8> {
+    let mut encoder = Encoder::new();
+    encoder.encode(data);
+    let stream = encoder.range.finish();
+
+    let mut out = Vec::with_capacity(LZMA_HEADER_SIZE + stream.len());
+    out.extend_from_slice(&LZMA_ID.to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(&(stream.len() as u32).to_le_bytes());
+    out.push(PROPERTIES);
+    out.extend_from_slice(&DICTIONARY_SIZE.to_le_bytes());
+    out.extend_from_slice(&stream);
+    out
+}
+
+// Undo compress_lump() (or Valve's compressor)
+pub fn decompress_lump(data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < LZMA_HEADER_SIZE {
+        return Err(Error::UnexpectedEof);
+    }
+    let u32_at = |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+    if u32_at(0) != LZMA_ID {
+        return Err(Error::InvalidLzma("no LZMA header"));
+    }
+    let size = u32_at(4) as usize;
+    let stream_size = u32_at(8) as usize;
+    let properties = data[12];
+    if properties >= 9 * 5 * 5 {
+        return Err(Error::InvalidLzma("invalid properties"));
+    }
+    let stream = data.get(LZMA_HEADER_SIZE..LZMA_HEADER_SIZE.saturating_add(stream_size)).ok_or(Error::UnexpectedEof)?;
+    let lc = (properties % 9) as u32;
+    let lp = (properties / 9 % 5) as u32;
+    let pb = (properties / 45) as u32;
+    Decoder::new(stream, lc, lp, pb)?.decode(size)
+}
+
+fn update_state_literal(state: usize) -> usize {
+    if state < 4 { 0 } else if state < 10 { state - 3 } else { state - 6 }
+}
+
+fn pos_slot(distance: u32) -> u32 {
+    if distance < 4 {
+        return distance;
+    }
+    let top = 31 - distance.leading_zeros();
+    2 * top + ((distance >> (top - 1)) & 1)
+}
+
+struct RangeEncoder {
+    low: u64,
+    range: u32,
+    cache: u8,
+    cache_size: u64,
+    out: Vec<u8>,
+}
+
+impl RangeEncoder {
+    fn new() -> Self {
+        Self { low: 0, range: 0xFFFF_FFFF, cache: 0, cache_size: 1, out: Vec::new() }
+    }
+
+    fn shift_low(&mut self) {
+        if (self.low as u32) < 0xFF00_0000 || self.low >> 32 != 0 {
+            let carry = (self.low >> 32) as u8;
+            let mut byte = self.cache;
+            loop {
+                self.out.push(byte.wrapping_add(carry));
+                byte = 0xFF;
+                self.cache_size -= 1;
+                if self.cache_size == 0 {
+                    break;
+                }
+            }
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.cache_size += 1;
+        self.low = (self.low & 0x00FF_FFFF) << 8;
+    }
+
+    fn bit(&mut self, prob: &mut u16, bit: u32) {
+        let bound = (self.range >> 11) * *prob as u32;
+        if bit == 0 {
+            self.range = bound;
+            *prob += (2048 - *prob) >> 5;
+        } else {
+            self.low += bound as u64;
+            self.range -= bound;
+            *prob -= *prob >> 5;
+        }
+        while self.range < 1 << 24 {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    fn direct_bits(&mut self, value: u32, count: u32) {
+        for i in (0..count).rev() {
+            self.range >>= 1;
+            if (value >> i) & 1 != 0 {
+                self.low += self.range as u64;
+            }
+            while self.range < 1 << 24 {
+                self.range <<= 8;
+                self.shift_low();
+            }
+        }
+    }
+
+    fn bit_tree(&mut self, probs: &mut [u16], bits: u32, symbol: u32) {
+        let mut m = 1;
+        for i in (0..bits).rev() {
+            let bit = (symbol >> i) & 1;
+            self.bit(&mut probs[m], bit);
+            m = (m << 1) | bit as usize;
+        }
+    }
+
+    // Reverse trees are stored from index 0, the normal ones from 1
+    fn reverse_bit_tree(&mut self, probs: &mut [u16], bits: u32, mut symbol: u32) {
+        let mut m = 1;
+        for _ in 0..bits {
+            let bit = symbol & 1;
+            symbol >>= 1;
+            self.bit(&mut probs[m - 1], bit);
+            m = (m << 1) | bit as usize;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+        self.out
+    }
+}
+
+// The probabilities both sides keep for one kind of length
+struct LengthModel {
+    choice: u16,
+    choice2: u16,
+    low: [[u16; 8]; POS_STATES],
+    mid: [[u16; 8]; POS_STATES],
+    high: [u16; 256],
+}
+
+impl LengthModel {
+    fn new() -> Self {
+        Self { choice: PROB_INIT, choice2: PROB_INIT, low: [[PROB_INIT; 8]; POS_STATES], mid: [[PROB_INIT; 8]; POS_STATES], high: [PROB_INIT; 256] }
+    }
+
+    fn encode(&mut self, rc: &mut RangeEncoder, length: u32, pos_state: usize) {
+        if length < 8 {
+            rc.bit(&mut self.choice, 0);
+            rc.bit_tree(&mut self.low[pos_state], 3, length);
+        } else if length < 16 {
+            rc.bit(&mut self.choice, 1);
+            rc.bit(&mut self.choice2, 0);
+            rc.bit_tree(&mut self.mid[pos_state], 3, length - 8);
+        } else {
+            rc.bit(&mut self.choice, 1);
+            rc.bit(&mut self.choice2, 1);
+            rc.bit_tree(&mut self.high, 8, length - 16);
+        }
+    }
+
+    fn decode(&mut self, rc: &mut RangeDecoder, pos_state: usize) -> Result<u32> {
+        if rc.bit(&mut self.choice)? == 0 {
+            rc.bit_tree(&mut self.low[pos_state], 3)
+        } else if rc.bit(&mut self.choice2)? == 0 {
+            Ok(8 + rc.bit_tree(&mut self.mid[pos_state], 3)?)
+        } else {
+            Ok(16 + rc.bit_tree(&mut self.high, 8)?)
+        }
+    }
+}
+
+// Everything else both sides keep
+struct Model {
+    literals: Vec<u16>,
+    is_match: [[u16; POS_STATES]; STATES],
+    is_rep: [u16; STATES],
+    is_rep_g0: [u16; STATES],
+    is_rep_g1: [u16; STATES],
+    is_rep_g2: [u16; STATES],
+    is_rep0_long: [[u16; POS_STATES]; STATES],
+    pos_slots: [[u16; 64]; LEN_TO_POS_STATES],
+    pos_special: [u16; FULL_DISTANCES - END_POS_MODEL_INDEX as usize],
+    align: [u16; 1 << ALIGN_BITS],
+    lengths: LengthModel,
+    rep_lengths: LengthModel,
+}
+
+impl Model {
+    fn new(literal_bits: u32) -> Self {
+        Self {
+            literals: vec![PROB_INIT; 0x300 << literal_bits],
+            is_match: [[PROB_INIT; POS_STATES]; STATES],
+            is_rep: [PROB_INIT; STATES],
+            is_rep_g0: [PROB_INIT; STATES],
+            is_rep_g1: [PROB_INIT; STATES],
+            is_rep_g2: [PROB_INIT; STATES],
+            is_rep0_long: [[PROB_INIT; POS_STATES]; STATES],
+            pos_slots: [[PROB_INIT; 64]; LEN_TO_POS_STATES],
+            pos_special: [PROB_INIT; FULL_DISTANCES - END_POS_MODEL_INDEX as usize],
+            align: [PROB_INIT; 1 << ALIGN_BITS],
+            lengths: LengthModel::new(),
+            rep_lengths: LengthModel::new(),
+        }
+    }
+}
+
+struct Encoder {
+    range: RangeEncoder,
+    model: Model,
+    state: usize,
+    // The last match's distance - 1
+    rep0: u32,
+}
+
+impl Encoder {
+    fn new() -> Self {
+        Self { range: RangeEncoder::new(), model: Model::new(LC), state: 0, rep0: 0 }
+    }
+
+    fn encode(&mut self, data: &[u8]) {
+        let hash = |pos: usize| {
+            let v = data[pos] as u32 | (data[pos + 1] as u32) << 8 | (data[pos + 2] as u32) << 16;
+            (v.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+        };
+        // The most recent position for each hash, and the one before each position with the same hash
+        let mut heads = vec![usize::MAX; 1 << HASH_BITS];
+        let mut chain = vec![usize::MAX; data.len()];
+        let insert = |pos: usize, heads: &mut [usize], chain: &mut [usize]| {
+            if pos + 3 <= data.len() {
+                let h = hash(pos);
+                chain[pos] = heads[h];
+                heads[h] = pos;
+            }
+        };
+
+        let mut pos = 0;
+        while pos < data.len() {
+            // Longest earlier match
+            let (mut best_length, mut best_distance) = (0, 0);
+            if pos + 3 <= data.len() {
+                let max = (data.len() - pos).min(MAX_MATCH);
+                let mut candidate = heads[hash(pos)];
+                let mut depth = 0;
+                while candidate != usize::MAX && depth < CHAIN_DEPTH && pos - candidate <= DICTIONARY_SIZE as usize {
+                    let length = (0..max).take_while(|&i| data[candidate + i] == data[pos + i]).count();
+                    if length > best_length {
+                        best_length = length;
+                        best_distance = pos - candidate;
+                        if length == max {
+                            break;
+                        }
+                    }
+                    candidate = chain[candidate];
+                    depth += 1;
+                }
+            }
+
+            if best_length >= MIN_FOUND_MATCH {
+                self.encode_match(pos, best_length, best_distance as u32 - 1);
+                for p in pos..pos + best_length {
+                    insert(p, &mut heads, &mut chain);
+                }
+                pos += best_length;
+            } else {
+                self.encode_literal(data, pos);
+                insert(pos, &mut heads, &mut chain);
+                pos += 1;
+            }
+        }
+    }
+
+    fn encode_literal(&mut self, data: &[u8], pos: usize) {
+        let pos_state = pos & PB_MASK;
+        self.range.bit(&mut self.model.is_match[self.state][pos_state], 0);
+        let previous = if pos > 0 { data[pos - 1] } else { 0 };
+        let base = 0x300 * (previous >> (8 - LC)) as usize;
+        let probs = &mut self.model.literals[base..base + 0x300];
+
+        let mut symbol = data[pos] as u32 | 0x100;
+        if self.state >= 7 {
+            // After a match, the byte at the match distance helps predict this one
+            let mut match_byte = data[pos - self.rep0 as usize - 1] as u32;
+            let mut offset = 0x100;
+            while symbol < 0x10000 {
+                match_byte <<= 1;
+                self.range.bit(&mut probs[(offset + (match_byte & offset) + (symbol >> 8)) as usize], (symbol >> 7) & 1);
+                symbol <<= 1;
+                offset &= !(match_byte ^ symbol);
+            }
+        } else {
+            while symbol < 0x10000 {
+                self.range.bit(&mut probs[(symbol >> 8) as usize], (symbol >> 7) & 1);
+                symbol <<= 1;
+            }
+        }
+        self.state = update_state_literal(self.state);
+    }
+
+    fn encode_match(&mut self, pos: usize, length: usize, distance: u32) {
+        let pos_state = pos & PB_MASK;
+        let m = &mut self.model;
+        self.range.bit(&mut m.is_match[self.state][pos_state], 1);
+        self.range.bit(&mut m.is_rep[self.state], 0);
+        let length = (length - MIN_MATCH) as u32;
+        m.lengths.encode(&mut self.range, length, pos_state);
+
+        let slot = pos_slot(distance);
+        self.range.bit_tree(&mut m.pos_slots[(length as usize).min(LEN_TO_POS_STATES - 1)], 6, slot);
+        if slot >= 4 {
+            let footer_bits = (slot >> 1) - 1;
+            let base = (2 | (slot & 1)) << footer_bits;
+            let reduced = distance - base;
+            if slot < END_POS_MODEL_INDEX {
+                let start = (base - slot) as usize;
+                self.range.reverse_bit_tree(&mut m.pos_special[start..], footer_bits, reduced);
+            } else {
+                self.range.direct_bits(reduced >> ALIGN_BITS, footer_bits - ALIGN_BITS);
+                self.range.reverse_bit_tree(&mut m.align, ALIGN_BITS, reduced & ((1 << ALIGN_BITS) - 1));
+            }
+        }
+        self.rep0 = distance;
+        self.state = if self.state < 7 { 7 } else { 10 };
+    }
+}
+
+struct RangeDecoder<'a> {
+    data: &'a [u8],
+    position: usize,
+    range: u32,
+    code: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    fn new(data: &'a [u8]) -> Result<Self> {
+        if data.len() < 5 {
+            return Err(Error::UnexpectedEof);
+        }
+        let code = u32::from_be_bytes([data[1], data[2], data[3], data[4]]);
+        Ok(Self { data, position: 5, range: 0xFFFF_FFFF, code })
+    }
+
+    fn normalize(&mut self) -> Result<()> {
+        if self.range < 1 << 24 {
+            let byte = *self.data.get(self.position).ok_or(Error::UnexpectedEof)?;
+            self.position += 1;
+            self.range <<= 8;
+            self.code = (self.code << 8) | byte as u32;
+        }
+        Ok(())
+    }
+
+    fn bit(&mut self, prob: &mut u16) -> Result<u32> {
+        let bound = (self.range >> 11) * *prob as u32;
+        let bit = if self.code < bound {
+            self.range = bound;
+            *prob += (2048 - *prob) >> 5;
+            0
+        } else {
+            self.code -= bound;
+            self.range -= bound;
+            *prob -= *prob >> 5;
+            1
+        };
+        self.normalize()?;
+        Ok(bit)
+    }
+
+    fn direct_bits(&mut self, count: u32) -> Result<u32> {
+        let mut value = 0;
+        for _ in 0..count {
+            self.range >>= 1;
+            let bit = if self.code >= self.range {
+                self.code -= self.range;
+                1
+            } else {
+                0
+            };
+            value = (value << 1) | bit;
+            self.normalize()?;
+        }
+        Ok(value)
+    }
+
+    fn bit_tree(&mut self, probs: &mut [u16], bits: u32) -> Result<u32> {
+        let mut m = 1;
+        for _ in 0..bits {
+            m = (m << 1) | self.bit(&mut probs[m])? as usize;
+        }
+        Ok((m - (1 << bits)) as u32)
+    }
+
+    fn reverse_bit_tree(&mut self, probs: &mut [u16], bits: u32) -> Result<u32> {
+        let (mut m, mut symbol) = (1, 0);
+        for i in 0..bits {
+            let bit = self.bit(&mut probs[m - 1])?;
+            m = (m << 1) | bit as usize;
+            symbol |= bit << i;
+        }
+        Ok(symbol)
+    }
+}
+
+struct Decoder<'a> {
+    range: RangeDecoder<'a>,
+    model: Model,
+    lc: u32,
+    lp_mask: usize,
+    pb_mask: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(stream: &'a [u8], lc: u32, lp: u32, pb: u32) -> Result<Self> {
+        Ok(Self {
+            range: RangeDecoder::new(stream)?,
+            model: Model::new(lc + lp),
+            lc,
+            lp_mask: (1 << lp) - 1,
+            pb_mask: (1 << pb) - 1,
+        })
+    }
+
+    fn decode(mut self, size: usize) -> Result<Vec<u8>> {
+        // The size comes from the file, so only reserve what a typical ratio would give,
+        // the Vec still grows if the stream really does unpack to that much
+        let mut out: Vec<u8> = Vec::with_capacity(size.min(self.range.data.len().saturating_mul(RESERVE_RATIO)));
+        let mut state = 0;
+        let mut reps = [0u32; 4];
+        while out.len() < size {
+            let pos_state = out.len() & self.pb_mask;
+            let m = &mut self.model;
+            let rc = &mut self.range;
+
+            if rc.bit(&mut m.is_match[state][pos_state])? == 0 {
+                let previous = out.last().copied().unwrap_or(0);
+                let literal_state = ((out.len() & self.lp_mask) << self.lc) + (previous >> (8 - self.lc)) as usize;
+                let probs = &mut m.literals[0x300 * literal_state..0x300 * (literal_state + 1)];
+                let mut symbol = 1usize;
+                if state >= 7 {
+                    let mut match_byte = *out.len().checked_sub(reps[0] as usize + 1).and_then(|i| out.get(i))
+                        .ok_or(Error::InvalidLzma("distance past the start"))? as usize;
+                    while symbol < 0x100 {
+                        let match_bit = (match_byte >> 7) & 1;
+                        match_byte <<= 1;
+                        let bit = rc.bit(&mut probs[((1 + match_bit) << 8) + symbol])? as usize;
+                        symbol = (symbol << 1) | bit;
+                        if match_bit != bit {
+                            break;
+                        }
+                    }
+                }
+                while symbol < 0x100 {
+                    symbol = (symbol << 1) | rc.bit(&mut probs[symbol])? as usize;
+                }
+                out.push(symbol as u8);
+                state = update_state_literal(state);
+                continue;
+            }
+
+            let length;
+            if rc.bit(&mut m.is_rep[state])? == 1 {
+                if out.is_empty() {
+                    return Err(Error::InvalidLzma("repeat before any data"));
+                }
+                if rc.bit(&mut m.is_rep_g0[state])? == 0 {
+                    if rc.bit(&mut m.is_rep0_long[state][pos_state])? == 0 {
+                        // One byte from rep0
+                        state = if state < 7 { 9 } else { 11 };
+                        let byte = *out.len().checked_sub(reps[0] as usize + 1).and_then(|i| out.get(i))
+                            .ok_or(Error::InvalidLzma("distance past the start"))?;
+                        out.push(byte);
+                        continue;
+                    }
+                } else {
+                    let distance = if rc.bit(&mut m.is_rep_g1[state])? == 0 {
+                        reps[1]
+                    } else {
+                        let distance = if rc.bit(&mut m.is_rep_g2[state])? == 0 {
+                            reps[2]
+                        } else {
+                            let distance = reps[3];
+                            reps[3] = reps[2];
+                            distance
+                        };
+                        reps[2] = reps[1];
+                        distance
+                    };
+                    reps[1] = reps[0];
+                    reps[0] = distance;
+                }
+                length = m.rep_lengths.decode(rc, pos_state)?;
+                state = if state < 7 { 8 } else { 11 };
+            } else {
+                reps = [0, reps[0], reps[1], reps[2]];
+                length = m.lengths.decode(rc, pos_state)?;
+                state = if state < 7 { 7 } else { 10 };
+
+                let slot = rc.bit_tree(&mut m.pos_slots[(length as usize).min(LEN_TO_POS_STATES - 1)], 6)?;
+                reps[0] = if slot < 4 {
+                    slot
+                } else {
+                    let footer_bits = (slot >> 1) - 1;
+                    let base = (2 | (slot & 1)) << footer_bits;
+                    if slot < END_POS_MODEL_INDEX {
+                        base + rc.reverse_bit_tree(&mut m.pos_special[(base - slot) as usize..], footer_bits)?
+                    } else {
+                        let high = rc.direct_bits(footer_bits - ALIGN_BITS)? << ALIGN_BITS;
+                        base.wrapping_add(high).wrapping_add(rc.reverse_bit_tree(&mut m.align, ALIGN_BITS)?)
+                    }
+                };
+                if reps[0] == u32::MAX {
+                    // End marker
+                    break;
+                }
+            }
+
+            let distance = reps[0] as usize + 1;
+            if distance > out.len() {
+                return Err(Error::InvalidLzma("distance past the start"));
+            }
+            for _ in 0..(length as usize + MIN_MATCH).min(size - out.len()) {
+                out.push(out[out.len() - distance]);
+            }
+        }
+        if out.len() != size {
+            return Err(Error::UnexpectedEof);
+        }
+        Ok(out)
+    }
+}
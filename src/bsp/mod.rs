@@ -1,82 +1,686 @@
 mod lump;
 mod error;
 mod header;
+mod lmp;
+mod lump_item;
+mod game_lump;
+mod writer;
+mod lzma;
+mod tree;
+mod texture;
+mod entity;
+mod crc;
+mod geometry;
+mod xbox;
+mod pak;
+mod water;
+mod occlusion;
+mod physics;
+mod displacement;
+mod brush;
+mod portal;
+mod radar;
+mod io_graph;
+mod static_prop;
+mod diff;
+mod bvh;
+mod trace;
+mod nav;
+
+pub mod validate;
+pub mod vmf;
+pub mod builder;
+pub mod goldsrc;
 
 pub use lump::*;
 pub use error::*;
 pub use header::*;
+pub use lmp::*;
+pub use lump_item::*;
+pub use game_lump::*;
+pub use writer::*;
+pub use lzma::*;
+pub use tree::*;
+pub use texture::*;
+pub use entity::*;
+pub use crc::*;
+pub use geometry::*;
+pub use xbox::*;
+pub use pak::*;
+pub use water::*;
+pub use occlusion::*;
+pub use physics::*;
+pub use displacement::*;
+pub use brush::*;
+pub use portal::*;
+pub use radar::*;
+pub use io_graph::*;
+pub use static_prop::*;
+pub use diff::*;
+pub use bvh::*;
+pub use trace::*;
+pub use nav::*;
 
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::fs::File;
+use std::io::BufReader;
+use std::io::Cursor;
 use std::io::Read;
 use std::io::Seek;
 use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+
+use crate::{Edge, Face, Plane, Vector};
+
+// Anything a map can be read from
+pub trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
 
-use std::string::FromUtf8Error;
+// Where the lump data comes from
+// Readers are read from lazily, bytes are borrowed without copying
+enum Source<'a> {
+    Reader(Box<dyn ReadSeek + 'a>),
+    Bytes(&'a [u8]),
+}
+
+impl std::fmt::Debug for Source<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Source::Reader(_) => write!(f, "Reader"),
+            Source::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+        }
+    }
+}
 
 #[derive(Debug)]
-pub struct Bsp {
+pub struct Bsp<'a> {
     pub version: u32,
     pub lumps: [Lump; 64],
     pub iteration: u32,
-    file: File,
+    pub layout: LumpLayout,
+    pub endian: Endian,
+    source: Source<'a>,
+    file_size: u64,
+    path: Option<PathBuf>,
+    lump_files: HashMap<usize, LumpFile>,
+    // Lumps that have already been read from the reader
+    cache: HashMap<usize, Vec<u8>>,
+    // Compressed lumps that have already been decompressed
+    decompressed: HashMap<usize, Vec<u8>>,
 }
 
-impl Bsp {
+impl Bsp<'static> {
     pub fn from_file(path: &str) -> Result<Self> {
-        let mut file = File::open(path)?;
-        let header = Header::read(&mut file)?;
-        
-        Ok(Self {
+        let mut bsp = Self::from_reader(BufReader::new(File::open(path)?))?;
+        bsp.path = Some(PathBuf::from(path));
+        Ok(bsp)
+    }
+}
+
+impl<'a> Bsp<'a> {
+    // Only the header is read up front, lumps are read when they're first asked for
+    pub fn from_reader<R: Read + Seek + 'a>(mut reader: R) -> Result<Self> {
+        let file_size = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        let header = Header::read(&mut reader)?;
+        Ok(Self::new(header, Source::Reader(Box::new(reader)), file_size))
+    }
+
+    // A map that's already in memory, like one pulled out of a VPK
+    // Lumps are slices of the given bytes, nothing gets copied
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self> {
+        let header = Header::read(&mut Cursor::new(bytes))?;
+        Ok(Self::new(header, Source::Bytes(bytes), bytes.len() as u64))
+    }
+
+    fn new(header: Header, source: Source<'a>, file_size: u64) -> Self {
+        Self {
             version: header.version,
             lumps: header.lumps,
             iteration: header.iteration,
-            file
-        })
+            layout: header.layout,
+            endian: header.endian,
+            source,
+            file_size,
+            path: None,
+            lump_files: HashMap::new(),
+            cache: HashMap::new(),
+            decompressed: HashMap::new(),
+        }
     }
 
-    pub fn get_lump_data(&mut self, index: LumpIndex) -> Option<Vec<u8>> {
-        let lump = self.lumps[index as usize];
-        if lump.exists() {
-            let mut v = Vec::with_capacity(lump.length as usize);
-            for _ in 0..v.capacity() {
-                v.push(0);
+    // Apply every mapname_l_N.lmp next to the .bsp, like the engine does on load
+    // Only works for maps opened with from_file(), see load_lump_files_from()
+    pub fn load_lump_files(&mut self) -> Result<usize> {
+        match self.path.clone() {
+            Some(path) => self.load_lump_files_from(path),
+            None => Ok(0),
+        }
+    }
+
+    // Apply the lump files that belong next to bsp_path
+    // Lump files made for a different map revision are skipped
+    // Returns how many were applied
+    pub fn load_lump_files_from<P: AsRef<Path>>(&mut self, bsp_path: P) -> Result<usize> {
+        let mut applied = 0;
+        for number in 0.. {
+            let path = lump_file_path(&bsp_path, number);
+            if !path.is_file() {
+                break;
+            }
+            let lump_file = LumpFile::read(&mut File::open(path)?)?;
+            if lump_file.map_revision == self.iteration {
+                self.apply_lump_file(lump_file);
+                applied += 1;
             }
-            if let Ok(_new_pos) = self.file.seek(SeekFrom::Start(lump.offset as u64)) {
-                if let Ok(_bytes_read) = self.file.read(&mut v) {
-                    Some(v)
-                } else {
-                    None
+        }
+        Ok(applied)
+    }
+
+    // Use the data in a .lmp in place of the lump it replaces
+    // Later lump files for the same lump win
+    pub fn apply_lump_file(&mut self, lump_file: LumpFile) {
+        self.lump_files.insert(lump_file.index as usize, lump_file);
+    }
+
+    pub fn lump_file(&self, index: LumpIndex) -> Option<&LumpFile> {
+        self.lump_files.get(&(index as usize))
+    }
+
+    // Where a lump's data starts in the file it's read from, the .lmp for overridden lumps
+    // GameLump offsets are relative to this
+    pub fn lump_data_offset(&self, index: LumpIndex) -> u32 {
+        match self.lump_file(index) {
+            Some(lump_file) => lump_file.offset,
+            None => self.lumps[index as usize].offset,
+        }
+    }
+
+    // Size of the whole .bsp in bytes
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    // Whether a lump is LZMA compressed in the .bsp
+    // The fourCC holds the uncompressed size for those, and is 0 otherwise
+    pub fn is_lump_compressed(&self, index: LumpIndex) -> bool {
+        self.lumps[index as usize].indent_code != [0; 4]
+    }
+
+    // Borrow a lump's data, decompressed if it's compressed
+    // From a reader, the lump is read once and kept around for next time
+    pub fn lump_slice(&mut self, index: LumpIndex) -> Result<&[u8]> {
+        let i = index as usize;
+        if self.lump_files.contains_key(&i) {
+            return Ok(&self.lump_files[&i].data);
+        }
+        if !self.is_lump_compressed(index) {
+            return self.file_lump_slice(index);
+        }
+        if !self.decompressed.contains_key(&i) {
+            let data = decompress_lump(self.file_lump_slice(index)?)?;
+            self.decompressed.insert(i, data);
+        }
+        Ok(&self.decompressed[&i])
+    }
+
+    // A lump's data as it is in the .bsp, ignoring .lmp files and still compressed
+    fn file_lump_slice(&mut self, index: LumpIndex) -> Result<&[u8]> {
+        let i = index as usize;
+        let lump = self.lumps[i];
+        if !lump.exists() {
+            return Err(Error::MissingLump(index));
+        }
+        if lump.offset as u64 + lump.length as u64 > self.file_size {
+            return Err(Error::LumpOutOfBounds {
+                index,
+                offset: lump.offset,
+                length: lump.length,
+                file_size: self.file_size,
+            });
+        }
+        let start = lump.offset as usize;
+        let end = start + lump.length as usize;
+
+        match &mut self.source {
+            Source::Bytes(bytes) => Ok(&bytes[start..end]),
+            Source::Reader(reader) => {
+                if let Entry::Vacant(entry) = self.cache.entry(i) {
+                    let mut v = vec![0; lump.length as usize];
+                    reader.seek(SeekFrom::Start(lump.offset as u64))?;
+                    reader.read_exact(&mut v)?;
+                    entry.insert(v);
                 }
-            } else {
-                None
+                Ok(&self.cache[&i])
+            },
+        }
+    }
+
+    // Forget every lump read so far
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+        self.decompressed.clear();
+    }
+
+    pub fn get_lump_data(&mut self, index: LumpIndex) -> Result<Vec<u8>> {
+        self.lump_slice(index).map(|data| data.to_vec())
+    }
+
+    // The version of a lump's data, taking .lmp overrides into account
+    pub fn lump_version(&self, index: LumpIndex) -> u32 {
+        match self.lump_file(index) {
+            Some(lump_file) => lump_file.version,
+            None => self.lumps[index as usize].version,
+        }
+    }
+
+    pub fn lump_context(&self, index: LumpIndex) -> LumpContext {
+        LumpContext {
+            bsp_version: self.version,
+            lump_version: self.lump_version(index),
+            endian: self.endian,
+        }
+    }
+
+    // Decode a lump as an array of records, like get_lump::<Plane>(LumpIndex::Planes)
+    pub fn get_lump<T: LumpItem>(&mut self, index: LumpIndex) -> Result<Vec<T>> {
+        let context = self.lump_context(index);
+        Ok(read_items(self.lump_slice(index)?, context))
+    }
+
+    pub fn planes(&mut self) -> Result<Vec<Plane>> {
+        self.get_lump(LumpIndex::Planes)
+    }
+
+    pub fn vertices(&mut self) -> Result<Vec<Vector>> {
+        self.get_lump(LumpIndex::Vertices)
+    }
+
+    pub fn edges(&mut self) -> Result<Vec<Edge>> {
+        self.get_lump(LumpIndex::Edges)
+    }
+
+    pub fn surface_edges(&mut self) -> Result<Vec<i32>> {
+        self.get_lump(LumpIndex::SurfaceEdges)
+    }
+
+    pub fn faces(&mut self) -> Result<Vec<Face>> {
+        self.get_lump(LumpIndex::Faces)
+    }
+
+    pub fn texture_infos(&mut self) -> Result<Vec<TextureInfo>> {
+        self.get_lump(LumpIndex::TextureInfo)
+    }
+
+    pub fn texture_data(&mut self) -> Result<Vec<TextureData>> {
+        self.get_lump(LumpIndex::TextureData)
+    }
+
+    // Material names, indexed by TextureData.name_string_table_id
+    pub fn texture_names(&mut self) -> Result<Vec<String>> {
+        let table: Vec<i32> = self.get_lump(LumpIndex::TextureStringTable)?;
+        let data = self.lump_slice(LumpIndex::TextureStringData)?;
+        Ok(texture_names(data, &table))
+    }
+
+    pub fn nodes(&mut self) -> Result<Vec<Node>> {
+        self.get_lump(LumpIndex::Nodes)
+    }
+
+    pub fn leafs(&mut self) -> Result<Vec<Leaf>> {
+        self.get_lump(LumpIndex::Leafs)
+    }
+
+    pub fn models(&mut self) -> Result<Vec<Model>> {
+        self.get_lump(LumpIndex::Models)
+    }
+
+    // An empty Occlusion if the map has no occluders
+    pub fn occlusion(&mut self) -> Result<Occlusion> {
+        let context = self.lump_context(LumpIndex::Occlusion);
+        match self.lump_slice(LumpIndex::Occlusion) {
+            Ok(data) => Occlusion::read(data, context),
+            Err(Error::MissingLump(_)) => Ok(Occlusion::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Every func_occluder's polygons, in world space
+    pub fn occluders(&mut self) -> Result<Vec<Occluder>> {
+        let occlusion = self.occlusion()?;
+        occlusion.occluders(&geometry::optional(self.vertices())?)
+    }
+
+    // Collision meshes of the world and brush entities
+    pub fn physics_models(&mut self) -> Result<Vec<PhysicsModel>> {
+        let context = self.lump_context(LumpIndex::PhysicsCollision);
+        match self.lump_slice(LumpIndex::PhysicsCollision) {
+            Ok(data) => PhysicsModel::read_all(data, context),
+            Err(Error::MissingLump(_)) => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn displacement_infos(&mut self) -> Result<Vec<DispInfo>> {
+        self.get_lump(LumpIndex::DisplacementInfo)
+    }
+
+    pub fn displacement_vertices(&mut self) -> Result<Vec<DispVert>> {
+        self.get_lump(LumpIndex::DisplacementVertices)
+    }
+
+    // Alien Swarm and later
+    pub fn displacement_multiblend(&mut self) -> Result<Vec<MultiBlend>> {
+        self.get_lump(LumpIndex::DisplacementMultiblend)
+    }
+
+    // Each displacement's collision mesh, in DisplacementInfo order
+    pub fn physics_displacements(&mut self) -> Result<Vec<Option<Solid>>> {
+        let context = self.lump_context(LumpIndex::PhysicsDisplacement);
+        match self.lump_slice(LumpIndex::PhysicsDisplacement) {
+            Ok(data) => read_physics_displacements(data, context),
+            Err(Error::MissingLump(_)) => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Every displacement with its vertices, collision and multiblend data
+    pub fn displacements(&mut self) -> Result<Vec<Displacement>> {
+        let infos = geometry::optional(self.displacement_infos())?;
+        let vertices = geometry::optional(self.displacement_vertices())?;
+        let collision = self.physics_displacements()?;
+        let multiblend = geometry::optional(self.displacement_multiblend())?;
+        assemble_displacements(&infos, &vertices, collision, &multiblend)
+    }
+
+    pub fn leaf_water_data(&mut self) -> Result<Vec<LeafWaterData>> {
+        self.get_lump(LumpIndex::LeafWaterData)
+    }
+
+    // One distance per leaf, in units
+    pub fn leaf_distances_to_water(&mut self) -> Result<Vec<u16>> {
+        self.get_lump(LumpIndex::LeafDistanceToWater)
+    }
+
+    // The material name a TextureInfo uses, if it has one
+    pub fn material_name(&mut self, texture_info: usize) -> Result<Option<String>> {
+        let texture_infos = self.texture_infos()?;
+        let texture_data = self.texture_data()?;
+        let names = self.texture_names()?;
+        Ok(texture_infos.get(texture_info)
+            .and_then(|info| texture_data.get(info.texture_data as usize))
+            .and_then(|data| names.get(data.name_string_table_id as usize))
+            .cloned())
+    }
+
+    // Which leaf of the world a point is in, by walking down the tree from the world's head node
+    pub fn leaf_at(&mut self, point: &Vector) -> Result<usize> {
+        let planes = self.planes()?;
+        let nodes = self.nodes()?;
+        let head = geometry::optional(self.models())?.first().map_or(0, |world| world.head_node);
+
+        let mut child = Child::from_index(head);
+        // Each step goes one node deeper, so more steps than nodes means a loop
+        for _ in 0..=nodes.len() {
+            let node = match child {
+                Child::Leaf(leaf) => return Ok(leaf),
+                Child::Node(node) => nodes.get(node)
+                    .ok_or(Error::IndexOutOfRange { lump: LumpIndex::Nodes, index: node })?,
+            };
+            let plane = planes.get(node.plane_number as usize)
+                .ok_or(Error::IndexOutOfRange { lump: LumpIndex::Planes, index: node.plane_number as usize })?;
+            let side = if plane.normal.dot(point) - plane.distance >= 0.0 { 0 } else { 1 };
+            child = node.child(side);
+        }
+        Err(Error::IndexOutOfRange { lump: LumpIndex::Nodes, index: nodes.len() })
+    }
+
+    // The water a point is in, or None if it's not in any
+    pub fn water_at(&mut self, point: &Vector) -> Result<Option<Water>> {
+        let leaf = self.leaf_at(point)?;
+        let leafs = self.leafs()?;
+        let id = match leafs.get(leaf) {
+            Some(l) if l.contents & (CONTENTS_WATER | CONTENTS_SLIME) != 0 => l.leaf_water_data_id,
+            _ => return Ok(None),
+        };
+        let water_data = geometry::optional(self.leaf_water_data())?;
+        let data = match water_data.get(id as usize) {
+            Some(data) if id >= 0 && point.z <= data.surface_z => *data,
+            _ => return Ok(None),
+        };
+        let material = match self.material_name(data.surface_texture_info as usize) {
+            Ok(material) if data.surface_texture_info >= 0 => material,
+            Ok(_) | Err(Error::MissingLump(_)) => None,
+            Err(e) => return Err(e),
+        };
+        Ok(Some(Water { leaf, surface_z: data.surface_z, min_z: data.min_z, material }))
+    }
+
+    // A top-down overview image of the world, see radar.rs
+    pub fn radar(&mut self, options: &RadarOptions) -> Result<Radar> {
+        radar::render_radar(self, options)
+    }
+
+    // Walkable areas for bots, see nav.rs
+    pub fn nav_mesh(&mut self, options: &NavOptions) -> Result<NavMesh> {
+        nav::generate_nav_mesh(self, options)
+    }
+
+    pub fn brushes(&mut self) -> Result<Vec<Brush>> {
+        self.get_lump(LumpIndex::Brushes)
+    }
+
+    pub fn brush_sides(&mut self) -> Result<Vec<BrushSide>> {
+        self.get_lump(LumpIndex::BrushSides)
+    }
+
+    // Indices into Brushes, indexed by Leaf.first_leaf_brush
+    pub fn leaf_brushes(&mut self) -> Result<Vec<u16>> {
+        self.get_lump(LumpIndex::LeafBrushes)
+    }
+
+    // Lumps 22-25 before version 20, see portal.rs
+    fn portal_lump<T: LumpItem>(&mut self, index: LumpIndex) -> Result<Vec<T>> {
+        if self.version >= PORTAL_LUMPS_BEFORE_VERSION {
+            return Err(Error::LumpNotInVersion { index, version: self.version });
+        }
+        self.get_lump(index)
+    }
+
+    // Lumps 22-25 from version 20 on
+    fn prop_collision_lump<T: LumpItem>(&mut self, index: LumpIndex) -> Result<Vec<T>> {
+        if self.version < PORTAL_LUMPS_BEFORE_VERSION {
+            return Err(Error::LumpNotInVersion { index, version: self.version });
+        }
+        self.get_lump(index)
+    }
+
+    pub fn portals(&mut self) -> Result<Vec<Portal>> {
+        self.portal_lump(LumpIndex::Portals)
+    }
+
+    pub fn clusters(&mut self) -> Result<Vec<Cluster>> {
+        self.portal_lump(LumpIndex::Clusters)
+    }
+
+    // Indices into Vertices
+    pub fn portal_vertices(&mut self) -> Result<Vec<u16>> {
+        self.portal_lump(LumpIndex::PortalVerts)
+    }
+
+    // Indices into Portals
+    pub fn cluster_portals(&mut self) -> Result<Vec<u16>> {
+        self.portal_lump(LumpIndex::ClusterPortals)
+    }
+
+    // Every portal's polygon, in Portals order
+    pub fn portal_polygons(&mut self) -> Result<Vec<PortalPolygon>> {
+        let portals = self.portals()?;
+        let indices = self.portal_vertices()?;
+        let vertices = self.vertices()?;
+        portals.iter().map(|portal| {
+            let first = portal.first_portal_vert.max(0) as usize;
+            let vertices = (first..first + portal.num_portal_verts.max(0) as usize).map(|i| {
+                let vertex = *indices.get(i)
+                    .ok_or(Error::IndexOutOfRange { lump: LumpIndex::PortalVerts, index: i })? as usize;
+                vertices.get(vertex).copied()
+                    .ok_or(Error::IndexOutOfRange { lump: LumpIndex::Vertices, index: vertex })
+            }).collect::<Result<Vec<_>>>()?;
+            Ok(PortalPolygon { plane_number: portal.plane_number, clusters: portal.cluster, vertices })
+        }).collect()
+    }
+
+    // The portals (indices into Portals) around each cluster
+    pub fn cluster_portal_lists(&mut self) -> Result<Vec<Vec<usize>>> {
+        let clusters = self.clusters()?;
+        let cluster_portals = self.cluster_portals()?;
+        clusters.iter().map(|cluster| {
+            let first = cluster.first_portal.max(0) as usize;
+            let end = first + cluster.num_portals.max(0) as usize;
+            cluster_portals.get(first..end)
+                .map(|portals| portals.iter().map(|&p| p as usize).collect())
+                .ok_or(Error::IndexOutOfRange { lump: LumpIndex::ClusterPortals, index: end.saturating_sub(1) })
+        }).collect()
+    }
+
+    // Left 4 Dead and later, one per static prop model
+    pub fn prop_collisions(&mut self) -> Result<Vec<PropCollision>> {
+        self.prop_collision_lump(LumpIndex::Portals)
+    }
+
+    pub fn prop_hulls(&mut self) -> Result<Vec<PropHull>> {
+        self.prop_collision_lump(LumpIndex::Clusters)
+    }
+
+    pub fn prop_hull_vertices(&mut self) -> Result<Vec<Vector>> {
+        self.prop_collision_lump(LumpIndex::PortalVerts)
+    }
+
+    pub fn prop_tris(&mut self) -> Result<Vec<PropTris>> {
+        self.prop_collision_lump(LumpIndex::ClusterPortals)
+    }
+
+    // Each static prop model's collision hulls with their vertices
+    pub fn prop_collision_hulls(&mut self) -> Result<Vec<Vec<PropHullMesh>>> {
+        let collisions = self.prop_collisions()?;
+        let hulls = self.prop_hulls()?;
+        let vertices = self.prop_hull_vertices()?;
+        let tris = geometry::optional(self.prop_tris())?;
+        collisions.iter().map(|collision| {
+            let first = collision.hull_start.max(0) as usize;
+            (first..first + collision.hull_count.max(0) as usize).map(|h| {
+                let hull = hulls.get(h).ok_or(Error::IndexOutOfRange { lump: LumpIndex::Clusters, index: h })?;
+                let start = hull.vert_start.max(0) as usize;
+                let end = start + hull.vert_count.max(0) as usize;
+                let hull_vertices = vertices.get(start..end)
+                    .ok_or(Error::IndexOutOfRange { lump: LumpIndex::PortalVerts, index: end.saturating_sub(1) })?;
+                Ok(PropHullMesh {
+                    surface_prop: hull.surface_prop,
+                    contents: hull.contents,
+                    vertices: hull_vertices.to_vec(),
+                    tris: tris.get(h).copied(),
+                })
+            }).collect()
+        }).collect()
+    }
+
+    pub fn entities(&mut self) -> Result<Vec<Entity>> {
+        parse_entities(&self.entity_lump_as_string()?)
+    }
+
+    // Every entity's outputs, see io_graph.rs
+    pub fn io_graph(&mut self) -> Result<IoGraph> {
+        Ok(IoGraph::new(&self.entities()?))
+    }
+
+    // Console maps only, see xbox.rs
+    pub fn lightmap_pages(&mut self) -> Result<Vec<LightmapPage>> {
+        self.get_lump(LumpIndex::LightMapPages)
+    }
+
+    pub fn lightmap_page_infos(&mut self) -> Result<Vec<LightmapPageInfo>> {
+        self.get_lump(LumpIndex::LightMapPageInfo)
+    }
+
+    // The zip archive of files packed into the map
+    pub fn pak_file(&mut self) -> Result<PakFile<'_>> {
+        PakFile::read(self.lump_slice(LumpIndex::PakFile)?)
+    }
+
+    // The Xbox's pakfile, an Xbox-specific "xZip" archive instead of a plain zip
+    pub fn xzip_pak_file(&mut self) -> Result<&[u8]> {
+        self.lump_slice(LumpIndex::XZipPakFile)
+    }
+
+    // The sub-lumps in the GameLump, with their data
+    pub fn game_lumps(&mut self) -> Result<Vec<GameLump>> {
+        let offset = self.lump_data_offset(LumpIndex::GameLump);
+        let endian = self.endian;
+        match self.lump_slice(LumpIndex::GameLump) {
+            Ok(data) => GameLump::read_all(data, offset, endian),
+            Err(Error::MissingLump(_)) => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Every prop_static, empty if the map has none
+    pub fn static_props(&mut self) -> Result<Vec<StaticProp>> {
+        let endian = self.endian;
+        match self.game_lumps()?.iter().find(|lump| lump.name() == "sprp") {
+            Some(lump) => read_static_props(lump, endian),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    // The static prop model dictionary, which prop_collision_hulls() lines up with
+    pub fn static_prop_models(&mut self) -> Result<Vec<String>> {
+        let endian = self.endian;
+        match self.game_lumps()?.iter().find(|lump| lump.name() == "sprp") {
+            Some(lump) => read_static_prop_models(lump, endian),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    // The checksum servers and clients compare to make sure they have the same map
+    // Same as the engine's CRC_MapFile(): a CRC-32 of every lump in header order,
+    // except the Entities lump, so entity edits (and .lmp files) don't change it
+    pub fn map_crc(&mut self) -> Result<u32> {
+        let mut crc = Crc32::new();
+        for &index in LumpIndex::ALL.iter() {
+            if index == LumpIndex::Entities {
+                continue;
+            }
+            match self.file_lump_slice(index) {
+                Ok(data) => crc.update(data),
+                Err(Error::MissingLump(_)) => {},
+                Err(e) => return Err(e),
             }
-        } else {
-            None
         }
+        Ok(crc.finish())
     }
 
     // Convert the data in the Entity lump to a new String
     // Returns an empty string if the lump doesn't exist
     //  | VBSP guarantees that at least one entity, "worldspawn", exists
     //  | so this should really never happen (but it CAN happen!)
-    // Returns a FromUtf8Error if there was a problem making a UTF-8 String
-    //  | 102% of the time, it's because the lump is LZMA compressed
-    //  | TF2, and probably more, do this (TODO: Decompression)
-    //  | Otherwise this lump should be valid ASCII, so... good luck if this happens
-    pub fn entity_lump_as_string(&mut self) -> std::result::Result<String, FromUtf8Error> {
-        if let Some(data) = self.get_lump_data(LumpIndex::Entities) {
-            let mut s = String::from_utf8(data)?;
-            // Remove the trailing null
-            s.pop();
-            Ok(s)
-        } else {
-            Ok("".to_string())
+    // Returns Error::InvalidUtf8 if there was a problem making a UTF-8 String
+    //  | This lump should be valid ASCII, so... good luck if this happens
+    pub fn entity_lump_as_string(&mut self) -> Result<String> {
+        match self.get_lump_data(LumpIndex::Entities) {
+            Ok(data) => {
+                let mut s = String::from_utf8(data)?;
+                // Remove the trailing null
+                s.pop();
+                Ok(s)
+            },
+            Err(Error::MissingLump(_)) => Ok("".to_string()),
+            Err(e) => Err(e),
         }
-
     }
 }
 
-impl std::fmt::Display for Bsp {
+impl std::fmt::Display for Bsp<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
         write!(f,
 "BSP Version: {}, Map Iteration: {}",
@@ -0,0 +1,323 @@
+// Walkable areas for bots, worked out from the map's collision
+// The tops of solids (brushes, displacement triangles and static prop boxes) are sampled on an XY grid,
+// then each sample is kept if:
+//   - the surface is flat enough to stand on (normal.z >= min_normal_z)
+//   - a player hull fits standing there, ignoring anything lower than a step (see trace.rs)
+//   - it can be walked to from a spawn point, moving between neighbouring samples that are
+//     within a step of each other with nothing in the way
+// What's left is merged into rectangles (in XY, the corners can be at different heights)
+// This is much simpler than the game's nav generation: no jumping, crouching, ladders or water
+
+use super::error::*;
+use super::brush::clip_brush;
+use super::trace::*;
+use super::Bsp;
+use crate::Vector;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavOptions {
+    // Size of the sampling grid, in units
+    pub cell_size: f32,
+    // The flattest slope a player slides off, 0.7 is the engine's
+    pub min_normal_z: f32,
+    pub step_height: f32,
+    // The standing player hull, relative to their feet
+    pub hull_mins: Vector,
+    pub hull_maxs: Vector,
+    // Only keep what can be reached from info_player_* entities (when the map has any)
+    pub flood_from_spawns: bool,
+}
+
+impl Default for NavOptions {
+    fn default() -> Self {
+        Self {
+            cell_size: 16.0,
+            min_normal_z: 0.7,
+            step_height: 18.0,
+            hull_mins: Vector::new(-16.0, -16.0, 0.0),
+            hull_maxs: Vector::new(16.0, 16.0, 72.0),
+            flood_from_spawns: true,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NavArea {
+    // X and Y are the area's edges, Z is the lowest and highest ground in it
+    pub mins: Vector,
+    pub maxs: Vector,
+    // Ground height in the corner cells: (mins.x, mins.y), (maxs.x, mins.y), (maxs.x, maxs.y), (mins.x, maxs.y)
+    pub corners: [f32; 4],
+    // Areas that can be walked to from this one, both ways
+    pub connections: Vec<usize>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NavMesh {
+    pub cell_size: f32,
+    pub areas: Vec<NavArea>,
+}
+
+impl NavMesh {
+    // The area someone standing at point is in, the highest one at or below their feet (give or take a step)
+    pub fn area_at(&self, point: Vector, step_height: f32) -> Option<usize> {
+        self.areas.iter().enumerate()
+            .filter(|(_, a)| point.x >= a.mins.x && point.x <= a.maxs.x && point.y >= a.mins.y && point.y <= a.maxs.y)
+            .filter(|(_, a)| a.mins.z <= point.z + step_height)
+            .max_by(|(_, a), (_, b)| a.mins.z.total_cmp(&b.mins.z))
+            .map(|(i, _)| i)
+    }
+
+    // A plain text format, one line per area and per connection:
+    //   nav 1 <cell size>
+    //   area <index> <min x> <min y> <max x> <max y> <4 corner heights>
+    //   connect <area> <area>
+    // Each connection is written once, with the lower index first
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "nav 1 {}", self.cell_size).unwrap();
+        for (i, area) in self.areas.iter().enumerate() {
+            let [a, b, c, d] = area.corners;
+            writeln!(out, "area {} {} {} {} {} {} {} {} {}", i, area.mins.x, area.mins.y, area.maxs.x, area.maxs.y, a, b, c, d).unwrap();
+        }
+        for (i, area) in self.areas.iter().enumerate() {
+            for &other in area.connections.iter().filter(|&&other| other > i) {
+                writeln!(out, "connect {} {}", i, other).unwrap();
+            }
+        }
+        out
+    }
+}
+
+// Neighbour directions, +x -x +y -y
+const DIRECTIONS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+// Samples this close together in one cell are the same ground
+const MERGE_DISTANCE: f32 = 1.0;
+// How much the rise from one cell to the next can change within an area
+const BEND_TOLERANCE: f32 = 4.0;
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    cell: (i32, i32),
+    z: f32,
+    // The connected sample in each direction
+    links: [Option<usize>; 4],
+    area: Option<usize>,
+}
+
+pub(crate) fn generate_nav_mesh(bsp: &mut Bsp, options: &NavOptions) -> Result<NavMesh> {
+    // The grid is walked cell by cell, so a cell size of 0 (or NaN) would never finish
+    if !(options.cell_size.is_finite() && options.cell_size > 0.0) {
+        return Err(Error::InvalidOption("cell size has to be more than 0"));
+    }
+    if !(options.step_height.is_finite() && options.step_height > 0.0) {
+        return Err(Error::InvalidOption("step height has to be more than 0"));
+    }
+    let world = CollisionWorld::build(bsp, MASK_PLAYERSOLID)?;
+    let cell = options.cell_size;
+
+    // Everything that could be ground
+    let mut triangles = Vec::new();
+    for solid in world.solids.iter() {
+        for (plane, polygon) in solid.planes.iter().zip(clip_brush(&solid.planes)) {
+            if plane.normal.z >= options.min_normal_z {
+                triangles.extend((1..polygon.len().saturating_sub(1)).map(|i| [polygon[0], polygon[i], polygon[i + 1]]));
+            }
+        }
+    }
+
+    // Ground heights in each cell
+    let mut heights: HashMap<(i32, i32), Vec<f32>> = HashMap::new();
+    for triangle in triangles.iter() {
+        rasterize(triangle, cell, &mut heights);
+    }
+
+    // Keep the ones a player can stand on
+    let standing_mins = Vector::new(options.hull_mins.x, options.hull_mins.y, options.hull_mins.z + options.step_height);
+    let stands = |position: Vector| {
+        // Not buried under something else's top, and room for the player above the step
+        let (zero, lifted) = (Vector::default(), position + Vector::new(0.0, 0.0, MERGE_DISTANCE));
+        !world.trace_box(lifted, lifted, zero, zero).start_solid
+            && !world.trace_box(position, position, standing_mins, options.hull_maxs).start_solid
+    };
+    let mut samples = Vec::new();
+    let mut cells: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+    let mut keys: Vec<(i32, i32)> = heights.keys().copied().collect();
+    keys.sort_unstable_by_key(|&(x, y)| (y, x));
+    for key in keys {
+        let mut zs = heights.remove(&key).unwrap_or_default();
+        zs.sort_unstable_by(|a, b| b.total_cmp(a));
+        zs.dedup_by(|lower, higher| *higher - *lower < MERGE_DISTANCE);
+        for z in zs {
+            if stands(cell_position(key, z, cell)) {
+                cells.entry(key).or_default().push(samples.len());
+                samples.push(Sample { cell: key, z, links: [None; 4], area: None });
+            }
+        }
+    }
+
+    // Link up neighbours a player can walk between
+    for i in 0..samples.len() {
+        for direction in [0, 2] {
+            let (dx, dy) = DIRECTIONS[direction];
+            let (x, y) = samples[i].cell;
+            let from = cell_position(samples[i].cell, samples[i].z, cell);
+            let neighbour = cells.get(&(x + dx, y + dy)).into_iter().flatten().copied()
+                .filter(|&j| (samples[j].z - samples[i].z).abs() <= options.step_height)
+                .min_by(|&a, &b| (samples[a].z - samples[i].z).abs().total_cmp(&(samples[b].z - samples[i].z).abs()));
+            if let Some(j) = neighbour {
+                // Sweep the hull across, stepping up first
+                let to = cell_position(samples[j].cell, samples[j].z, cell);
+                let lift = Vector::new(0.0, 0.0, options.step_height);
+                let mins = options.hull_mins;
+                let maxs = Vector::new(options.hull_maxs.x, options.hull_maxs.y, options.hull_maxs.z - options.step_height);
+                if world.trace_box(from + lift, to + lift, mins, maxs).fraction >= 1.0 {
+                    samples[i].links[direction] = Some(j);
+                    samples[j].links[direction + 1].get_or_insert(i);
+                }
+            }
+        }
+    }
+
+    // Drop what can't be reached
+    let spawns: Vec<Vector> = if options.flood_from_spawns {
+        bsp.entities()?.iter()
+            .filter(|e| e.classname().is_some_and(|c| c.starts_with("info_player_")))
+            .filter_map(|e| e.origin())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let mut reachable = vec![spawns.is_empty(); samples.len()];
+    let mut queue = VecDeque::new();
+    for spawn in spawns {
+        let key = ((spawn.x / cell).floor() as i32, (spawn.y / cell).floor() as i32);
+        let below = cells.get(&key).into_iter().flatten().copied()
+            .filter(|&i| samples[i].z <= spawn.z + options.step_height)
+            .max_by(|&a, &b| samples[a].z.total_cmp(&samples[b].z));
+        if let Some(i) = below {
+            reachable[i] = true;
+            queue.push_back(i);
+        }
+    }
+    while let Some(i) = queue.pop_front() {
+        for j in samples[i].links.iter().flatten().copied() {
+            if !reachable[j] {
+                reachable[j] = true;
+                queue.push_back(j);
+            }
+        }
+    }
+
+    // Grow rectangles, along +x as far as possible and then +y while every column can follow
+    // The slope can't change much inside an area, so it stops at the edges of steps and ramps
+    let free = |samples: &[Sample], i: usize| reachable[i] && samples[i].area.is_none();
+    let mut areas = Vec::new();
+    let mut members: Vec<Vec<usize>> = Vec::new();
+    for start in 0..samples.len() {
+        if !free(&samples, start) {
+            continue;
+        }
+        let mut row = vec![start];
+        while let Some(next) = samples[*row.last().unwrap()].links[0].filter(|&j| free(&samples, j)) {
+            if let [.., before, last] = row[..] {
+                if bends(&samples, [before, last], [last, next]) {
+                    break;
+                }
+            }
+            row.push(next);
+        }
+        let mut rows = vec![row];
+        'grow: loop {
+            let previous = rows.last().unwrap();
+            let mut next_row: Vec<usize> = Vec::with_capacity(previous.len());
+            for (column, &above) in previous.iter().enumerate() {
+                let next = match samples[above].links[2].filter(|&j| free(&samples, j)) {
+                    Some(next) => next,
+                    None => break 'grow,
+                };
+                if rows.len() >= 2 && bends(&samples, [rows[rows.len() - 2][column], above], [above, next]) {
+                    break 'grow;
+                }
+                if column > 0 && (samples[next_row[column - 1]].links[0] != Some(next)
+                    || bends(&samples, [previous[column - 1], above], [next_row[column - 1], next])) {
+                    break 'grow;
+                }
+                next_row.push(next);
+            }
+            rows.push(next_row);
+        }
+
+        let index = areas.len();
+        let all: Vec<usize> = rows.iter().flatten().copied().collect();
+        for &i in all.iter() {
+            samples[i].area = Some(index);
+        }
+        let (first_row, last_row) = (&rows[0], rows.last().unwrap());
+        let (x0, y0) = samples[first_row[0]].cell;
+        let (x1, y1) = samples[*last_row.last().unwrap()].cell;
+        let (low, high) = all.iter().fold((f32::MAX, f32::MIN), |(low, high), &i| (low.min(samples[i].z), high.max(samples[i].z)));
+        areas.push(NavArea {
+            mins: Vector::new(x0 as f32 * cell, y0 as f32 * cell, low),
+            maxs: Vector::new((x1 + 1) as f32 * cell, (y1 + 1) as f32 * cell, high),
+            corners: [
+                samples[first_row[0]].z,
+                samples[*first_row.last().unwrap()].z,
+                samples[*last_row.last().unwrap()].z,
+                samples[last_row[0]].z,
+            ],
+            connections: Vec::new(),
+        });
+        members.push(all);
+    }
+
+    // Connect areas wherever their samples link across the edge
+    for (index, area_samples) in members.iter().enumerate() {
+        let connected: HashSet<usize> = area_samples.iter()
+            .flat_map(|&i| samples[i].links.iter().flatten())
+            .filter_map(|&j| samples[j].area)
+            .filter(|&other| other != index)
+            .collect();
+        let mut connected: Vec<usize> = connected.into_iter().collect();
+        connected.sort_unstable();
+        areas[index].connections = connected;
+    }
+
+    Ok(NavMesh { cell_size: cell, areas })
+}
+
+// Whether the height changes by a different amount from a[0] to a[1] than from b[0] to b[1]
+fn bends(samples: &[Sample], a: [usize; 2], b: [usize; 2]) -> bool {
+    let rise = |[from, to]: [usize; 2]| samples[to].z - samples[from].z;
+    (rise(a) - rise(b)).abs() > BEND_TOLERANCE
+}
+
+// The middle of a cell, at height z
+fn cell_position((x, y): (i32, i32), z: f32, cell: f32) -> Vector {
+    Vector::new((x as f32 + 0.5) * cell, (y as f32 + 0.5) * cell, z)
+}
+
+// The triangle's height at the middle of every cell it covers
+fn rasterize([a, b, c]: &[Vector; 3], cell: f32, heights: &mut HashMap<(i32, i32), Vec<f32>>) {
+    let area = (b.x - a.x) * (c.y - a.y) - (c.x - a.x) * (b.y - a.y);
+    if area.abs() < 1e-3 {
+        return;
+    }
+    let mins = a.min(b).min(c);
+    let maxs = a.max(b).max(c);
+    for x in (mins.x / cell).floor() as i32..=(maxs.x / cell).floor() as i32 {
+        for y in (mins.y / cell).floor() as i32..=(maxs.y / cell).floor() as i32 {
+            let p = cell_position((x, y), 0.0, cell);
+            // Barycentric weights in XY, counting the edges as inside so neighbouring triangles don't leave gaps
+            let wa = ((b.x - p.x) * (c.y - p.y) - (c.x - p.x) * (b.y - p.y)) / area;
+            let wb = ((c.x - p.x) * (a.y - p.y) - (a.x - p.x) * (c.y - p.y)) / area;
+            let wc = 1.0 - wa - wb;
+            if wa >= -1e-4 && wb >= -1e-4 && wc >= -1e-4 {
+                heights.entry((x, y)).or_default().push(a.z * wa + b.z * wb + c.z * wc);
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+// The Occlusion lump, polygons from func_occluder brushes
+// Unlike other lumps it's three counted arrays in a row:
+//   int count, doccluderdata_t[count]
+//   int count, doccluderpolydata_t[count]
+//   int count, int vertex_indices[count] (into the Vertices lump)
+
+use super::error::*;
+use super::lump::LumpIndex;
+use super::lump_item::{LumpContext, LumpItem, LumpReader, LumpWriter};
+use crate::Vector;
+
+// Bits of OccluderData.flags
+pub const OCCLUDER_FLAGS_INACTIVE: i32 = 0x1;
+
+// doccluderdata_t
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct OccluderData {
+    pub flags: i32,
+    pub first_poly: i32,
+    pub poly_count: i32,
+    pub mins: Vector,
+    pub maxs: Vector,
+    // The map area it's in, only in version 2+ of the lump
+    pub area: i32,
+}
+
+impl LumpItem for OccluderData {
+    fn size(context: &LumpContext) -> usize {
+        if context.lump_version >= 2 { 40 } else { 36 }
+    }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self {
+            flags: r.i32(),
+            first_poly: r.i32(),
+            poly_count: r.i32(),
+            mins: r.vector(),
+            maxs: r.vector(),
+            area: if r.context.lump_version >= 2 { r.i32() } else { 0 },
+        }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.i32(self.flags);
+        w.i32(self.first_poly);
+        w.i32(self.poly_count);
+        w.vector(&self.mins);
+        w.vector(&self.maxs);
+        if w.context.lump_version >= 2 {
+            w.i32(self.area);
+        }
+    }
+}
+
+// doccluderpolydata_t
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct OccluderPolyData {
+    pub first_vertex_index: i32,
+    pub vertex_count: i32,
+    pub plane_number: i32,
+}
+
+impl LumpItem for OccluderPolyData {
+    fn size(_: &LumpContext) -> usize { 12 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self {
+            first_vertex_index: r.i32(),
+            vertex_count: r.i32(),
+            plane_number: r.i32(),
+        }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.i32(self.first_vertex_index);
+        w.i32(self.vertex_count);
+        w.i32(self.plane_number);
+    }
+}
+
+// The whole lump, as stored
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Occlusion {
+    pub occluders: Vec<OccluderData>,
+    pub polygons: Vec<OccluderPolyData>,
+    pub vertex_indices: Vec<i32>,
+}
+
+// One occluder with its polygons in world space
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Occluder {
+    pub flags: i32,
+    pub area: i32,
+    pub polygons: Vec<Vec<Vector>>,
+    // Bounds of the polygons, not the ones stored in the lump
+    pub mins: Vector,
+    pub maxs: Vector,
+}
+
+impl Occlusion {
+    pub fn read(data: &[u8], context: LumpContext) -> Result<Self> {
+        let mut r = LumpReader::new(data, context);
+        Ok(Self {
+            occluders: read_counted(&mut r)?,
+            polygons: read_counted(&mut r)?,
+            vertex_indices: read_counted(&mut r)?,
+        })
+    }
+
+    pub fn write(&self, context: LumpContext) -> Vec<u8> {
+        let mut w = LumpWriter::new(context);
+        w.i32(self.occluders.len() as i32);
+        for occluder in self.occluders.iter() {
+            occluder.write(&mut w);
+        }
+        w.i32(self.polygons.len() as i32);
+        for polygon in self.polygons.iter() {
+            polygon.write(&mut w);
+        }
+        w.i32(self.vertex_indices.len() as i32);
+        for index in self.vertex_indices.iter() {
+            w.i32(*index);
+        }
+        w.into_bytes()
+    }
+
+    // Look up every occluder's polygons in the map's vertices
+    pub fn occluders(&self, vertices: &[Vector]) -> Result<Vec<Occluder>> {
+        self.occluders.iter().map(|data| {
+            let first = data.first_poly.max(0) as usize;
+            let polygons = (first..first + data.poly_count.max(0) as usize)
+                .map(|p| self.polygon(p, vertices))
+                .collect::<Result<Vec<_>>>()?;
+
+            let mut points = polygons.iter().flatten();
+            let (mins, maxs) = match points.next() {
+                Some(first) => points.fold((*first, *first), |(mins, maxs), v| (mins.min(v), maxs.max(v))),
+                None => (Vector::default(), Vector::default()),
+            };
+            Ok(Occluder { flags: data.flags, area: data.area, polygons, mins, maxs })
+        }).collect()
+    }
+
+    fn polygon(&self, index: usize, vertices: &[Vector]) -> Result<Vec<Vector>> {
+        let out_of_range = |index| Error::IndexOutOfRange { lump: LumpIndex::Occlusion, index };
+        let polygon = self.polygons.get(index).ok_or_else(|| out_of_range(index))?;
+        let first = polygon.first_vertex_index.max(0) as usize;
+        (first..first + polygon.vertex_count.max(0) as usize).map(|i| {
+            let vertex = *self.vertex_indices.get(i).ok_or_else(|| out_of_range(i))? as usize;
+            vertices.get(vertex).copied()
+                .ok_or(Error::IndexOutOfRange { lump: LumpIndex::Vertices, index: vertex })
+        }).collect()
+    }
+}
+
+impl Occluder {
+    pub fn is_active(&self) -> bool {
+        self.flags & OCCLUDER_FLAGS_INACTIVE == 0
+    }
+
+    // Total area of the polygons, in square units
+    pub fn surface_area(&self) -> f32 {
+        self.polygons.iter().map(|polygon| {
+            let mut sum = Vector::default();
+            for i in 1..polygon.len().saturating_sub(1) {
+                sum += (polygon[i] - polygon[0]).cross(&(polygon[i + 1] - polygon[0]));
+            }
+            sum.length() / 2.0
+        }).sum()
+    }
+}
+
+// An int count and then that many records
+fn read_counted<T: LumpItem>(r: &mut LumpReader) -> Result<Vec<T>> {
+    if r.remaining() < 4 {
+        return Err(Error::UnexpectedEof);
+    }
+    let count = r.i32().max(0) as usize;
+    let size = T::size(&r.context) * count;
+    if r.remaining() < size {
+        return Err(Error::UnexpectedEof);
+    }
+    Ok((0..count).map(|_| T::read(r)).collect())
+}
@@ -0,0 +1,182 @@
+// The PakFile lump is a plain zip archive of files packed into the map
+// (custom materials, models, sounds, cubemaps...)
+// Valve's tools only ever store files uncompressed, but some newer games use LZMA
+// Offsets in the zip are relative to the start of the lump
+
+use super::crc::crc32;
+use super::error::*;
+use super::lump_item::{LumpContext, LumpReader, LumpWriter};
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const CENTRAL_DIRECTORY_ENTRY: u32 = 0x02014b50;
+const LOCAL_FILE_HEADER: u32 = 0x04034b50;
+
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+const CENTRAL_DIRECTORY_ENTRY_SIZE: usize = 46;
+const LOCAL_FILE_HEADER_SIZE: usize = 30;
+
+pub const COMPRESSION_STORED: u16 = 0;
+pub const COMPRESSION_LZMA: u16 = 14;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PakEntry {
+    pub name: String,
+    pub compression: u16,
+    pub crc: u32,
+    pub compressed_size: u32,
+    pub size: u32,
+    local_header_offset: u32,
+}
+
+#[derive(Debug)]
+pub struct PakFile<'a> {
+    data: &'a [u8],
+    // Added to every offset in the zip, for archives that were written somewhere else first
+    base: usize,
+    pub entries: Vec<PakEntry>,
+}
+
+impl<'a> PakFile<'a> {
+    pub fn read(data: &'a [u8]) -> Result<Self> {
+        let eocd = find_end_of_central_directory(data)
+            .ok_or(Error::InvalidZip("no end of central directory"))?;
+
+        let mut r = reader(data);
+        r.seek(eocd + 10);
+        let count = r.u16() as usize;
+        let directory_size = r.u32() as usize;
+        let directory_offset = r.u32() as usize;
+
+        // The central directory sits right before the end record,
+        // so anything else means the offsets are off by some amount
+        let directory_start = eocd.checked_sub(directory_size)
+            .ok_or(Error::InvalidZip("central directory is bigger than the archive"))?;
+        let base = directory_start.wrapping_sub(directory_offset);
+
+        let mut entries = Vec::with_capacity(count);
+        r.seek(directory_start);
+        for _ in 0..count {
+            if r.remaining() < CENTRAL_DIRECTORY_ENTRY_SIZE || r.u32() != CENTRAL_DIRECTORY_ENTRY {
+                return Err(Error::InvalidZip("bad central directory entry"));
+            }
+            r.skip(6); // version made by, version needed, flags
+            let compression = r.u16();
+            r.skip(4); // modification time and date
+            let crc = r.u32();
+            let compressed_size = r.u32();
+            let size = r.u32();
+            let name_length = r.u16() as usize;
+            let extra_length = r.u16() as usize;
+            let comment_length = r.u16() as usize;
+            r.skip(8); // disk number, internal and external attributes
+            let local_header_offset = r.u32();
+
+            if r.remaining() < name_length + extra_length + comment_length {
+                return Err(Error::UnexpectedEof);
+            }
+            let name = String::from_utf8_lossy(r.bytes(name_length)).into_owned();
+            r.skip(extra_length + comment_length);
+
+            entries.push(PakEntry { name, compression, crc, compressed_size, size, local_header_offset });
+        }
+
+        Ok(Self { data, base, entries })
+    }
+
+    // Case-insensitive, and / and \ are the same, like the engine's filesystem
+    pub fn find(&self, name: &str) -> Option<&PakEntry> {
+        let normalize = |s: &str| s.replace('\\', "/").to_lowercase();
+        let name = normalize(name);
+        self.entries.iter().find(|e| normalize(&e.name) == name)
+    }
+
+    // The contents of a stored (uncompressed) file
+    pub fn entry_data(&self, entry: &PakEntry) -> Result<&'a [u8]> {
+        let header = self.base.wrapping_add(entry.local_header_offset as usize);
+        if header.checked_add(LOCAL_FILE_HEADER_SIZE).map_or(true, |end| end > self.data.len()) {
+            return Err(Error::UnexpectedEof);
+        }
+        let mut r = reader(self.data);
+        r.seek(header);
+        if r.u32() != LOCAL_FILE_HEADER {
+            return Err(Error::InvalidZip("bad local file header"));
+        }
+        if entry.compression != COMPRESSION_STORED {
+            return Err(Error::UnsupportedCompression { name: entry.name.clone(), method: entry.compression });
+        }
+
+        // The local header has its own name and extra field lengths
+        r.seek(header + 26);
+        let skip = r.u16() as usize + r.u16() as usize;
+        let start = header + LOCAL_FILE_HEADER_SIZE + skip;
+        self.data.get(start..start + entry.compressed_size as usize).ok_or(Error::UnexpectedEof)
+    }
+
+    pub fn file(&self, name: &str) -> Result<&'a [u8]> {
+        match self.find(name) {
+            Some(entry) => self.entry_data(entry),
+            None => Err(Error::MissingFile(name.to_string())),
+        }
+    }
+}
+
+fn reader(data: &[u8]) -> LumpReader<'_> {
+    // Zips are little-endian, even in console maps
+    LumpReader::new(data, LumpContext::default())
+}
+
+// Searching backwards since the end record can be followed by a comment
+fn find_end_of_central_directory(data: &[u8]) -> Option<usize> {
+    let last = data.len().checked_sub(END_OF_CENTRAL_DIRECTORY_SIZE)?;
+    let first = last.saturating_sub(u16::MAX as usize);
+    (first..=last).rev().find(|&i| data[i..i + 4] == END_OF_CENTRAL_DIRECTORY.to_le_bytes())
+}
+
+// A zip of stored files, laid out the way vbsp and bspzip do it
+pub fn write_pak_file(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut zip = LumpWriter::new(LumpContext::default());
+    let mut directory = LumpWriter::new(LumpContext::default());
+    for (name, data) in files.iter() {
+        let offset = zip.len() as u32;
+        // Everything from the version needed to extract up to the extra field length,
+        // which the local header and the directory entry share
+        let fields = |w: &mut LumpWriter| {
+            w.u16(10); // version needed, 1.0
+            w.u16(0); // flags
+            w.u16(COMPRESSION_STORED);
+            w.u32(0); // time and date
+            w.u32(crc32(data));
+            w.u32(data.len() as u32);
+            w.u32(data.len() as u32);
+            w.u16(name.len() as u16);
+            w.u16(0); // extra field
+        };
+        zip.u32(LOCAL_FILE_HEADER);
+        fields(&mut zip);
+        zip.bytes(name.as_bytes());
+        zip.bytes(data);
+
+        directory.u32(CENTRAL_DIRECTORY_ENTRY);
+        directory.u16(10); // version made by
+        fields(&mut directory);
+        directory.u16(0); // comment
+        directory.u16(0); // disk
+        directory.u16(0); // internal attributes
+        directory.u32(0); // external attributes
+        directory.u32(offset);
+        directory.bytes(name.as_bytes());
+    }
+
+    let directory_offset = zip.len() as u32;
+    let directory_size = directory.len() as u32;
+    zip.bytes(&directory.into_bytes());
+    zip.u32(END_OF_CENTRAL_DIRECTORY);
+    zip.u16(0); // this disk
+    zip.u16(0); // directory's disk
+    zip.u16(files.len() as u16);
+    zip.u16(files.len() as u16);
+    zip.u32(directory_size);
+    zip.u32(directory_offset);
+    zip.u16(0); // comment
+    zip.into_bytes()
+}
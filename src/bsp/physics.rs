@@ -0,0 +1,215 @@
+// The PhysicsCollision lump, the collision meshes of the world and brush entities
+// For each model there's a dphysmodel_t, then its solids, then a KeyValues text block:
+//   int model_index, int data_size, int key_data_size, int solid_count
+//   solid_count x (int size, byte data[size])
+//   char key_data[key_data_size]
+// The list ends with a model_index of -1
+//
+// Each solid is an IVP "compact surface", optionally after a "VPHY" header
+// A compact surface is a tree of convex pieces ("ledges"), each a list of triangles
+// https://developer.valvesoftware.com/wiki/PHY has the same layout for models
+
+use super::error::*;
+use super::lump_item::{LumpContext, LumpReader};
+use crate::keyvalues::KeyValues;
+use crate::Vector;
+
+use std::collections::HashMap;
+
+const VPHY_ID: [u8; 4] = *b"VPHY";
+// id, version, model type, surface size, drag axis areas, axis map size
+const VPHY_HEADER_SIZE: usize = 28;
+const COMPACT_SURFACE_SIZE: usize = 48;
+const LEDGETREE_NODE_SIZE: usize = 28;
+const LEDGE_SIZE: usize = 16;
+const TRIANGLE_SIZE: usize = 16;
+const POINT_SIZE: usize = 16;
+
+// VPHY model types
+pub const COLLIDE_POLY: i16 = 0;
+pub const COLLIDE_MOPP: i16 = 1;
+
+// IVP works in metres with Y up, the engine in inches with Z up
+const METRES_TO_INCHES: f32 = 39.370_08;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhysicsModel {
+    // Index into the Models lump
+    pub model_index: i32,
+    pub solids: Vec<Solid>,
+    // "solid", "editparams", ... blocks describing mass, damping and so on
+    pub key_values: KeyValues,
+}
+
+// One physics object, made of convex hulls
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Solid {
+    pub hulls: Vec<ConvexHull>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConvexHull {
+    // In map space
+    pub vertices: Vec<Vector>,
+    // Indices into vertices
+    pub triangles: Vec<[usize; 3]>,
+}
+
+impl PhysicsModel {
+    // Read every model in the lump
+    pub fn read_all(data: &[u8], context: LumpContext) -> Result<Vec<PhysicsModel>> {
+        let mut models = Vec::new();
+        let mut offset = 0;
+        while offset + 16 <= data.len() {
+            let header = Data::new(data, context);
+            let model_index = header.i32(offset)?;
+            if model_index == -1 {
+                break;
+            }
+            let data_size = header.i32(offset + 4)?.max(0) as usize;
+            let key_data_size = header.i32(offset + 8)?.max(0) as usize;
+            let solid_count = header.i32(offset + 12)?.max(0) as usize;
+            offset += 16;
+
+            let solid_data = header.slice(offset, data_size)?;
+            let mut solids = Vec::with_capacity(solid_count);
+            let mut solid_offset = 0;
+            for _ in 0..solid_count {
+                let size = Data::new(solid_data, context).i32(solid_offset)?.max(0) as usize;
+                let solid = Data::new(solid_data, context).slice(solid_offset + 4, size)?;
+                solids.push(Solid::read(solid, context)?);
+                solid_offset += 4 + size;
+            }
+            offset += data_size;
+
+            let text = header.slice(offset, key_data_size)?;
+            offset += key_data_size;
+            let text = String::from_utf8_lossy(text);
+            let key_values = KeyValues::from_str(text.trim_end_matches('\0'))
+                .map_err(Error::KeyValuesSyntax)?;
+
+            models.push(PhysicsModel { model_index, solids, key_values });
+        }
+        Ok(models)
+    }
+}
+
+impl Solid {
+    // One solid's data, after its size
+    pub fn read(data: &[u8], context: LumpContext) -> Result<Solid> {
+        let d = Data::new(data, context);
+        let surface = if d.slice(0, 4)? == VPHY_ID {
+            let model_type = d.i16(6)?;
+            if model_type != COLLIDE_POLY {
+                return Err(Error::UnsupportedCollision(model_type));
+            }
+            VPHY_HEADER_SIZE
+        } else {
+            // Old maps have the compact surface on its own
+            0
+        };
+
+        if data.len() < surface + COMPACT_SURFACE_SIZE {
+            return Err(Error::UnexpectedEof);
+        }
+        let root = offset_by(surface, d.i32(surface + 32)?).ok_or(Error::UnexpectedEof)?;
+
+        // Walk the ledge tree, left child is always right after its parent
+        let mut hulls = Vec::new();
+        let mut stack = vec![root];
+        let mut visited = 0;
+        while let Some(node) = stack.pop() {
+            visited += 1;
+            if visited > data.len() / LEDGETREE_NODE_SIZE {
+                return Err(Error::UnexpectedEof);
+            }
+            let right = d.i32(node)?;
+            if right == 0 {
+                let ledge = offset_by(node, d.i32(node + 4)?).ok_or(Error::UnexpectedEof)?;
+                hulls.push(read_ledge(&d, ledge)?);
+            } else {
+                // Right goes on the stack first so ledges come out in the order they're stored
+                stack.push(offset_by(node, right).ok_or(Error::UnexpectedEof)?);
+                stack.push(node + LEDGETREE_NODE_SIZE);
+            }
+        }
+
+        Ok(Solid { hulls })
+    }
+}
+
+// IVP_Compact_Ledge followed by its triangles
+fn read_ledge(d: &Data, ledge: usize) -> Result<ConvexHull> {
+    let points = offset_by(ledge, d.i32(ledge)?).ok_or(Error::UnexpectedEof)?;
+    let triangle_count = d.i16(ledge + 12)?.max(0) as usize;
+
+    let mut hull = ConvexHull::default();
+    // Points are shared by every ledge in the surface, only keep the ones this ledge uses
+    let mut remap: HashMap<usize, usize> = HashMap::new();
+    for t in 0..triangle_count {
+        let triangle = ledge + LEDGE_SIZE + t * TRIANGLE_SIZE;
+        let mut indices = [0; 3];
+        for (e, index) in indices.iter_mut().enumerate() {
+            // The low 16 bits of each edge are its starting point
+            let point = (d.u32(triangle + 4 + e * 4)? & 0xFFFF) as usize;
+            *index = match remap.get(&point) {
+                Some(&i) => i,
+                None => {
+                    let at = points + point * POINT_SIZE;
+                    let ivp = Vector::new(d.f32(at)?, d.f32(at + 4)?, d.f32(at + 8)?);
+                    hull.vertices.push(Vector::new(ivp.x, ivp.z, -ivp.y) * METRES_TO_INCHES);
+                    remap.insert(point, hull.vertices.len() - 1);
+                    hull.vertices.len() - 1
+                },
+            };
+        }
+        hull.triangles.push(indices);
+    }
+    Ok(hull)
+}
+
+fn offset_by(base: usize, offset: i32) -> Option<usize> {
+    if offset >= 0 {
+        base.checked_add(offset as usize)
+    } else {
+        base.checked_sub(offset.unsigned_abs() as usize)
+    }
+}
+
+// Bounds-checked reads at absolute offsets
+struct Data<'a> {
+    bytes: &'a [u8],
+    context: LumpContext,
+}
+
+impl<'a> Data<'a> {
+    fn new(bytes: &'a [u8], context: LumpContext) -> Self {
+        Self { bytes, context }
+    }
+
+    fn slice(&self, offset: usize, length: usize) -> Result<&'a [u8]> {
+        offset.checked_add(length)
+            .and_then(|end| self.bytes.get(offset..end))
+            .ok_or(Error::UnexpectedEof)
+    }
+
+    fn reader(&self, offset: usize, length: usize) -> Result<LumpReader<'a>> {
+        Ok(LumpReader::new(self.slice(offset, length)?, self.context))
+    }
+
+    fn i16(&self, offset: usize) -> Result<i16> {
+        Ok(self.reader(offset, 2)?.i16())
+    }
+
+    fn i32(&self, offset: usize) -> Result<i32> {
+        Ok(self.reader(offset, 4)?.i32())
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+        Ok(self.reader(offset, 4)?.u32())
+    }
+
+    fn f32(&self, offset: usize) -> Result<f32> {
+        Ok(self.reader(offset, 4)?.f32())
+    }
+}
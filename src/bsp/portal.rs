@@ -0,0 +1,143 @@
+// Lumps 22-25 mean different things depending on the map's version
+// Before version 20 they're the vis portals between clusters:
+//   Portals, Clusters, PortalVerts (u16 into Vertices), ClusterPortals (u16 into Portals)
+// In version 20+ they went unused, until Left 4 Dead used them for static prop collision:
+//   PropCollision, PropHulls, PropHullVerts, PropTris
+
+use super::lump_item::{LumpContext, LumpItem, LumpReader, LumpWriter};
+use crate::Vector;
+
+// The first BSP version where lumps 22-25 aren't portals anymore
+pub const PORTAL_LUMPS_BEFORE_VERSION: u32 = 20;
+
+// dportal_t
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Portal {
+    pub first_portal_vert: i32,
+    pub num_portal_verts: i32,
+    pub plane_number: i32,
+    // The clusters on either side
+    pub cluster: [u16; 2],
+}
+
+impl LumpItem for Portal {
+    fn size(_: &LumpContext) -> usize { 16 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self {
+            first_portal_vert: r.i32(),
+            num_portal_verts: r.i32(),
+            plane_number: r.i32(),
+            cluster: [r.u16(), r.u16()],
+        }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.i32(self.first_portal_vert);
+        w.i32(self.num_portal_verts);
+        w.i32(self.plane_number);
+        w.u16(self.cluster[0]);
+        w.u16(self.cluster[1]);
+    }
+}
+
+// dcluster_t, a range of ClusterPortals
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Cluster {
+    pub first_portal: i32,
+    pub num_portals: i32,
+}
+
+impl LumpItem for Cluster {
+    fn size(_: &LumpContext) -> usize { 8 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self { first_portal: r.i32(), num_portals: r.i32() }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.i32(self.first_portal);
+        w.i32(self.num_portals);
+    }
+}
+
+// A portal with its polygon looked up
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PortalPolygon {
+    pub plane_number: i32,
+    pub clusters: [u16; 2],
+    pub vertices: Vec<Vector>,
+}
+
+// dpropcollision_t, a range of PropHulls for one static prop model
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PropCollision {
+    pub hull_count: i32,
+    pub hull_start: i32,
+}
+
+impl LumpItem for PropCollision {
+    fn size(_: &LumpContext) -> usize { 8 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self { hull_count: r.i32(), hull_start: r.i32() }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.i32(self.hull_count);
+        w.i32(self.hull_start);
+    }
+}
+
+// dprophull_t, a range of PropHullVerts
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PropHull {
+    pub vert_count: i32,
+    pub vert_start: i32,
+    pub surface_prop: i32,
+    pub contents: u32,
+}
+
+impl LumpItem for PropHull {
+    fn size(_: &LumpContext) -> usize { 16 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self { vert_count: r.i32(), vert_start: r.i32(), surface_prop: r.i32(), contents: r.u32() }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.i32(self.vert_count);
+        w.i32(self.vert_start);
+        w.i32(self.surface_prop);
+        w.u32(self.contents);
+    }
+}
+
+// dprophulltris_t, one per hull
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PropTris {
+    pub index_start: i32,
+    pub index_count: i32,
+}
+
+impl LumpItem for PropTris {
+    fn size(_: &LumpContext) -> usize { 8 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self { index_start: r.i32(), index_count: r.i32() }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.i32(self.index_start);
+        w.i32(self.index_count);
+    }
+}
+
+// A static prop model's collision hull with its vertices looked up
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PropHullMesh {
+    pub surface_prop: i32,
+    pub contents: u32,
+    pub vertices: Vec<Vector>,
+    pub tris: Option<PropTris>,
+}
@@ -0,0 +1,162 @@
+// Top-down overview images, like CS radar overviews
+// The world's faces are drawn orthographically looking down -Z with a depth buffer,
+// so the highest surface under each pixel wins (set max_z to cut off roofs and ceilings)
+
+use super::error::*;
+use super::geometry::{optional, Geometry};
+use super::texture::*;
+use super::Bsp;
+use crate::Vector;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RadarShading {
+    // Greyscale, darker is lower
+    Height,
+    // The average colour of each face's material, darkened with depth
+    Material,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RadarOptions {
+    // The image is size x size pixels
+    pub size: usize,
+    pub shading: RadarShading,
+    // Anything above this is left out
+    pub max_z: Option<f32>,
+    // Empty space around the map, in units
+    pub padding: f32,
+}
+
+impl Default for RadarOptions {
+    fn default() -> Self {
+        Self { size: 1024, shading: RadarShading::Height, max_z: None, padding: 64.0 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Radar {
+    pub size: usize,
+    // RGBA, row by row from the top (north, +Y) down, transparent where there's nothing
+    pub pixels: Vec<u8>,
+    // World position of the image's top left corner
+    pub pos_x: f32,
+    pub pos_y: f32,
+    // Units per pixel of a 1024 wide image, which is what the game scales overviews to
+    pub scale: f32,
+}
+
+impl Radar {
+    // The resource/overviews/<map>.txt that goes with the image
+    pub fn overview_txt(&self, map_name: &str) -> String {
+        format!("\"{}\"\n{{\n\t\"material\"\t\"overviews/{}\"\n\t\"pos_x\"\t\"{}\"\n\t\"pos_y\"\t\"{}\"\n\t\"scale\"\t\"{}\"\n}}\n",
+            map_name, map_name, self.pos_x, self.pos_y, self.scale)
+    }
+}
+
+// Faces that don't show up in-game
+const HIDDEN: i32 = SURF_SKY | SURF_SKY2D | SURF_NODRAW | SURF_TRIGGER | SURF_HINT | SURF_SKIP;
+
+pub(crate) fn render_radar(bsp: &mut Bsp, options: &RadarOptions) -> Result<Radar> {
+    let geometry = Geometry::load(bsp)?;
+    let texture_infos = optional(bsp.texture_infos())?;
+    let texture_data = optional(bsp.texture_data())?;
+    // Only the world, brush entities move around or disappear
+    let world_faces = match optional(bsp.models())?.first() {
+        Some(world) => world.first_face.max(0) as usize..(world.first_face + world.num_faces).max(0) as usize,
+        None => 0..geometry.faces.len(),
+    };
+
+    // Every visible, upward facing triangle with its colour
+    let mut triangles: Vec<([Vector; 3], Vector)> = Vec::new();
+    for face in geometry.faces.get(world_faces).unwrap_or_default() {
+        let info = texture_infos.get(face.tex_info as usize).filter(|_| face.tex_info >= 0);
+        if info.is_some_and(|info| info.flags & HIDDEN != 0) || geometry.face_normal(face).z <= 0.0 {
+            continue;
+        }
+        let colour = info
+            .and_then(|info| texture_data.get(info.texture_data as usize))
+            .map_or(Vector::new(0.5, 0.5, 0.5), |data| data.reflectivity);
+        for triangle in geometry.triangulate(face) {
+            if options.max_z.is_some_and(|max_z| triangle.iter().all(|v| v.z > max_z)) {
+                continue;
+            }
+            triangles.push((triangle, colour));
+        }
+    }
+
+    let size = options.size.max(1);
+    let mut points = triangles.iter().flat_map(|(t, _)| t.iter());
+    let (mins, maxs) = match points.next() {
+        Some(first) => points.fold((*first, *first), |(mins, maxs), v| (mins.min(v), maxs.max(v))),
+        None => (Vector::default(), Vector::default()),
+    };
+    let max_z = options.max_z.map_or(maxs.z, |max_z| max_z.min(maxs.z));
+
+    // Square, centred on the map
+    let extent = (maxs.x - mins.x).max(maxs.y - mins.y).max(1.0) + options.padding * 2.0;
+    let left = (mins.x + maxs.x - extent) / 2.0;
+    let top = (mins.y + maxs.y + extent) / 2.0;
+    let pixels_per_unit = size as f32 / extent;
+
+    let mut depth = vec![f32::NEG_INFINITY; size * size];
+    let mut pixels = vec![0; size * size * 4];
+    for (triangle, colour) in triangles.iter() {
+        // To pixel space, keeping z
+        let p: Vec<(f32, f32, f32)> = triangle.iter()
+            .map(|v| ((v.x - left) * pixels_per_unit, (top - v.y) * pixels_per_unit, v.z))
+            .collect();
+        let area = edge(p[0], p[1], p[2]);
+        if area.abs() < 1e-6 {
+            continue;
+        }
+
+        let x0 = p.iter().map(|v| v.0).fold(f32::INFINITY, f32::min).floor().max(0.0) as usize;
+        let x1 = p.iter().map(|v| v.0).fold(f32::NEG_INFINITY, f32::max).ceil().min(size as f32) as usize;
+        let y0 = p.iter().map(|v| v.1).fold(f32::INFINITY, f32::min).floor().max(0.0) as usize;
+        let y1 = p.iter().map(|v| v.1).fold(f32::NEG_INFINITY, f32::max).ceil().min(size as f32) as usize;
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let centre = (x as f32 + 0.5, y as f32 + 0.5, 0.0);
+                let w0 = edge(p[1], p[2], centre) / area;
+                let w1 = edge(p[2], p[0], centre) / area;
+                let w2 = edge(p[0], p[1], centre) / area;
+                if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                    continue;
+                }
+                let z = w0 * p[0].2 + w1 * p[1].2 + w2 * p[2].2;
+                let i = y * size + x;
+                if z > max_z + 0.01 || z <= depth[i] {
+                    continue;
+                }
+                depth[i] = z;
+
+                let height = if max_z > mins.z { ((z - mins.z) / (max_z - mins.z)).clamp(0.0, 1.0) } else { 1.0 };
+                let rgb = match options.shading {
+                    RadarShading::Height => {
+                        let grey = 0.15 + 0.85 * height;
+                        [grey, grey, grey]
+                    },
+                    RadarShading::Material => {
+                        // Reflectivity is linear, the image isn't
+                        let shade = 0.6 + 0.4 * height;
+                        [colour.x, colour.y, colour.z].map(|c| c.clamp(0.0, 1.0).powf(1.0 / 2.2) * shade)
+                    },
+                };
+                pixels[i * 4..i * 4 + 4].copy_from_slice(&[
+                    (rgb[0] * 255.0).round() as u8,
+                    (rgb[1] * 255.0).round() as u8,
+                    (rgb[2] * 255.0).round() as u8,
+                    255,
+                ]);
+            }
+        }
+    }
+
+    Ok(Radar { size, pixels, pos_x: left, pos_y: top, scale: extent / 1024.0 })
+}
+
+// Twice the signed area of a, b, c in the XY plane
+fn edge(a: (f32, f32, f32), b: (f32, f32, f32), c: (f32, f32, f32)) -> f32 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
@@ -0,0 +1,82 @@
+// Static props, from the 'sprp' game lump:
+//   i32 count, count model names (128 bytes each, null padded)
+//   i32 count, count u16 leafs
+//   i32 count, count StaticPropLump_t
+// StaticPropLump_t grew with almost every version, but every version starts with
+// origin, angles and the model's index, so only those are read and the rest is skipped
+
+use super::error::*;
+use super::game_lump::GameLump;
+use super::lump::LumpIndex;
+use super::lump_item::{Endian, LumpContext, LumpReader};
+use crate::Vector;
+
+const MODEL_NAME_SIZE: usize = 128;
+// origin + angles + model index
+const MIN_PROP_SIZE: usize = 26;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StaticProp {
+    pub model: String,
+    pub origin: Vector,
+    // pitch, yaw, roll
+    pub angles: Vector,
+}
+
+pub fn read_static_props(lump: &GameLump, endian: Endian) -> Result<Vec<StaticProp>> {
+    check_compression(lump)?;
+    let mut r = LumpReader::new(&lump.data, LumpContext { endian, ..Default::default() });
+    let models = read_dictionary(&mut r)?;
+    let leafs = count(&mut r, 2)?;
+    r.skip(leafs * 2);
+
+    let props = count(&mut r, MIN_PROP_SIZE)?;
+    if props == 0 {
+        return Ok(Vec::new());
+    }
+    let stride = r.remaining() / props;
+    let start = r.position();
+    (0..props).map(|i| {
+        r.seek(start + i * stride);
+        let origin = r.vector();
+        let angles = r.vector();
+        let model = r.u16() as usize;
+        let model = models.get(model)
+            .ok_or(Error::IndexOutOfRange { lump: LumpIndex::GameLump, index: model })?
+            .clone();
+        Ok(StaticProp { model, origin, angles })
+    }).collect()
+}
+
+// Just the model names, in the order the props (and the prop collision lumps) index them
+pub fn read_static_prop_models(lump: &GameLump, endian: Endian) -> Result<Vec<String>> {
+    check_compression(lump)?;
+    read_dictionary(&mut LumpReader::new(&lump.data, LumpContext { endian, ..Default::default() }))
+}
+
+fn check_compression(lump: &GameLump) -> Result<()> {
+    if lump.flags & 1 != 0 {
+        return Err(Error::UnsupportedCompression { name: "sprp game lump".to_string(), method: lump.flags });
+    }
+    Ok(())
+}
+
+fn read_dictionary(r: &mut LumpReader) -> Result<Vec<String>> {
+    Ok((0..count(r, MODEL_NAME_SIZE)?).map(|_| {
+        let name = r.bytes(MODEL_NAME_SIZE);
+        let end = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        String::from_utf8_lossy(&name[..end]).into_owned()
+    }).collect())
+}
+
+// A count followed by that many records of the given size
+fn count(r: &mut LumpReader, size: usize) -> Result<usize> {
+    if r.remaining() < 4 {
+        return Err(Error::UnexpectedEof);
+    }
+    let count = r.i32().max(0) as usize;
+    if r.remaining() < count.saturating_mul(size) {
+        return Err(Error::UnexpectedEof);
+    }
+    Ok(count)
+}
@@ -0,0 +1,97 @@
+// How faces are textured
+// Face -> TextureInfo -> TextureData -> TextureStringTable -> TextureStringData
+// with the last one holding the actual material names
+
+use super::lump_item::{LumpContext, LumpItem, LumpReader, LumpWriter};
+use crate::Vector;
+
+// texinfo_t
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TextureInfo {
+    // [s/t][xyz offset], texture coordinates are dot(position, xyz) + offset
+    pub texture_vecs: [[f32; 4]; 2],
+    pub lightmap_vecs: [[f32; 4]; 2],
+    pub flags: i32,
+    pub texture_data: i32,
+}
+
+// Some of the SURF_* flags in TextureInfo.flags
+pub const SURF_LIGHT: i32 = 0x1;
+pub const SURF_SKY2D: i32 = 0x2;
+pub const SURF_SKY: i32 = 0x4;
+pub const SURF_WARP: i32 = 0x8;
+pub const SURF_TRANS: i32 = 0x10;
+pub const SURF_TRIGGER: i32 = 0x40;
+pub const SURF_NODRAW: i32 = 0x80;
+pub const SURF_HINT: i32 = 0x100;
+pub const SURF_SKIP: i32 = 0x200;
+
+impl LumpItem for TextureInfo {
+    fn size(_: &LumpContext) -> usize { 72 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        let mut vecs = [[0.0; 4]; 4];
+        for v in vecs.iter_mut().flat_map(|v| v.iter_mut()) {
+            *v = r.f32();
+        }
+        Self {
+            texture_vecs: [vecs[0], vecs[1]],
+            lightmap_vecs: [vecs[2], vecs[3]],
+            flags: r.i32(),
+            texture_data: r.i32(),
+        }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        for v in self.texture_vecs.iter().chain(self.lightmap_vecs.iter()).flat_map(|v| v.iter()) {
+            w.f32(*v);
+        }
+        w.i32(self.flags);
+        w.i32(self.texture_data);
+    }
+}
+
+// dtexdata_t
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TextureData {
+    pub reflectivity: Vector, // average colour of the texture, 0-1
+    pub name_string_table_id: i32,
+    pub width: i32,
+    pub height: i32,
+    pub view_width: i32,
+    pub view_height: i32,
+}
+
+impl LumpItem for TextureData {
+    fn size(_: &LumpContext) -> usize { 32 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self {
+            reflectivity: r.vector(),
+            name_string_table_id: r.i32(),
+            width: r.i32(),
+            height: r.i32(),
+            view_width: r.i32(),
+            view_height: r.i32(),
+        }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.vector(&self.reflectivity);
+        w.i32(self.name_string_table_id);
+        w.i32(self.width);
+        w.i32(self.height);
+        w.i32(self.view_width);
+        w.i32(self.view_height);
+    }
+}
+
+// Split TextureStringData into names using the offsets in TextureStringTable
+// A bad offset gives an empty name rather than failing the whole table
+pub fn texture_names(string_data: &[u8], string_table: &[i32]) -> Vec<String> {
+    string_table.iter().map(|&offset| {
+        let bytes = string_data.get(offset.max(0) as usize..).unwrap_or_default();
+        let end = bytes.iter().position(|&c| c == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).into_owned()
+    }).collect()
+}
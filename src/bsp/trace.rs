@@ -0,0 +1,341 @@
+// Box traces against the world's collision, the same test the engine moves players with
+// Every solid is a convex set of planes, and the box is swept against each plane pushed out by
+// the box's extents (the bevel sides vbsp adds to brushes keep that exact at the corners)
+// Displacements are solid too, as a thin solid per triangle in place of the brush they were made from
+// Static props become boxes around their collision hulls, since the hulls are only vertex lists
+
+use super::brush::clip_brush;
+use super::error::*;
+use super::geometry::{optional, Geometry};
+use super::tree::*;
+use super::Bsp;
+use crate::{Plane, Vector};
+
+use std::collections::HashMap;
+
+// What blocks a player. MASK_PLAYERSOLID also has moveable and monster, but those are for entities
+pub const MASK_PLAYERSOLID: i32 = CONTENTS_SOLID | CONTENTS_WINDOW | CONTENTS_GRATE | CONTENTS_PLAYERCLIP;
+
+// Traces stop this far short of what they hit, like DIST_EPSILON in the engine
+const DIST_EPSILON: f32 = 0.03125;
+// How far displacement triangles go back from their surface
+const DISPLACEMENT_THICKNESS: f32 = 1.0;
+// Solids are bucketed in squares this big on XY
+const BUCKET_SIZE: f32 = 256.0;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConvexSolid {
+    // Outward facing, the solid is behind all of them
+    pub planes: Vec<Plane>,
+    pub mins: Vector,
+    pub maxs: Vector,
+    pub contents: i32,
+}
+
+impl ConvexSolid {
+    pub fn from_box(mins: Vector, maxs: Vector, contents: i32) -> Self {
+        let plane = |normal: Vector, distance: f32, kind: i32| Plane { normal, distance, kind };
+        let planes = vec![
+            plane(Vector::new(1.0, 0.0, 0.0), maxs.x, 0),
+            plane(Vector::new(-1.0, 0.0, 0.0), -mins.x, 0),
+            plane(Vector::new(0.0, 1.0, 0.0), maxs.y, 1),
+            plane(Vector::new(0.0, -1.0, 0.0), -mins.y, 1),
+            plane(Vector::new(0.0, 0.0, 1.0), maxs.z, 2),
+            plane(Vector::new(0.0, 0.0, -1.0), -mins.z, 2),
+        ];
+        Self { planes, mins, maxs, contents }
+    }
+
+    // A triangle facing the way it goes anticlockwise, made DISPLACEMENT_THICKNESS thick behind that
+    // (with no thickness a trace would enter and leave it at the same time and go straight through)
+    // It also gets bevels on its bounds (like vbsp gives brushes) so boxes don't catch on its corners
+    pub fn from_triangle([a, b, c]: [Vector; 3], contents: i32) -> Option<Self> {
+        let normal = (b - a).cross(&(c - a));
+        if normal.length() < 1e-3 {
+            return None;
+        }
+        let normal = normal.normalized();
+        let distance = normal.dot(&a);
+        let mut planes = vec![axial_kind(normal, distance), axial_kind(-normal, DISPLACEMENT_THICKNESS - distance)];
+        for (from, to) in [(a, b), (b, c), (c, a)] {
+            let out = (to - from).cross(&normal).normalized();
+            planes.push(axial_kind(out, out.dot(&from)));
+        }
+        let back = normal * -DISPLACEMENT_THICKNESS;
+        let (mins, maxs) = bounds(&[a, b, c, a + back, b + back, c + back])?;
+        planes.extend(Self::from_box(mins, maxs, contents).planes);
+        Some(Self { planes, mins, maxs, contents })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trace {
+    // How far along the way the box got, 1.0 if nothing was hit
+    pub fraction: f32,
+    pub end: Vector,
+    // The plane that was hit, zero if nothing was
+    pub normal: Vector,
+    // The box started inside a solid
+    pub start_solid: bool,
+    // ... and never left it
+    pub all_solid: bool,
+    // Index into CollisionWorld::solids
+    pub solid: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CollisionWorld {
+    pub solids: Vec<ConvexSolid>,
+    buckets: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl CollisionWorld {
+    // The world's brushes that match the mask, and static props if the map has prop collision lumps
+    // Brush entities (doors, ...) are left out since they can move or go away
+    pub fn build(bsp: &mut Bsp, mask: i32) -> Result<Self> {
+        let planes = bsp.planes()?;
+        let brushes = optional(bsp.brushes())?;
+        let brush_sides = optional(bsp.brush_sides())?;
+        let models = optional(bsp.models())?;
+        let nodes = optional(bsp.nodes())?;
+        let leafs = optional(bsp.leafs())?;
+        let leaf_brushes = optional(bsp.leaf_brushes())?;
+
+        let mut world_brushes: Vec<usize> = match models.first() {
+            Some(world) => tree_leafs(world.head_node, &nodes).iter()
+                .filter_map(|&leaf| leafs.get(leaf))
+                .flat_map(|leaf| {
+                    let first = leaf.first_leaf_brush as usize;
+                    leaf_brushes.get(first..first + leaf.num_leaf_brushes as usize).unwrap_or(&[])
+                })
+                .map(|&brush| brush as usize)
+                .collect(),
+            None => Vec::new(),
+        };
+        world_brushes.sort_unstable();
+        world_brushes.dedup();
+
+        let mut solids = Vec::new();
+        for brush in world_brushes.into_iter().filter_map(|b| brushes.get(b)) {
+            if brush.contents & mask == 0 {
+                continue;
+            }
+            let first = brush.first_side.max(0) as usize;
+            let sides = match brush_sides.get(first..first + brush.num_sides.max(0) as usize) {
+                Some(sides) => sides,
+                None => continue,
+            };
+            // vbsp takes brushes with a displacement on them out of collision, the displacement replaces them
+            if sides.iter().any(|side| side.displacement_info >= 0) {
+                continue;
+            }
+            let brush_planes: Vec<Plane> = sides.iter()
+                .filter_map(|side| planes.get(side.plane_number as usize).copied())
+                .collect();
+            if brush_planes.len() != sides.len() {
+                continue;
+            }
+            let outline: Vec<Plane> = brush_planes.iter().zip(sides.iter())
+                .filter(|(_, side)| !side.bevel)
+                .map(|(plane, _)| *plane)
+                .collect();
+            let vertices: Vec<Vector> = clip_brush(&outline).into_iter().flatten().collect();
+            let (mins, maxs) = match bounds(&vertices) {
+                Some(bounds) => bounds,
+                None => continue,
+            };
+            solids.push(ConvexSolid { planes: brush_planes, mins, maxs, contents: brush.contents });
+        }
+
+        solids.extend(displacement_triangles(bsp, mask)?);
+        solids.extend(static_prop_boxes(bsp)?);
+        Ok(Self::new(solids))
+    }
+
+    pub fn new(solids: Vec<ConvexSolid>) -> Self {
+        let mut buckets: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (i, solid) in solids.iter().enumerate() {
+            let ((x0, y0), (x1, y1)) = (bucket(solid.mins), bucket(solid.maxs));
+            for x in x0..=x1 {
+                for y in y0..=y1 {
+                    buckets.entry((x, y)).or_default().push(i);
+                }
+            }
+        }
+        Self { solids, buckets }
+    }
+
+    // Sweep a box (mins and maxs relative to the box's origin) from start to end
+    // A trace with start == end tests whether the box fits there
+    pub fn trace_box(&self, start: Vector, end: Vector, mins: Vector, maxs: Vector) -> Trace {
+        let mut trace = Trace { fraction: 1.0, end, normal: Vector::default(), start_solid: false, all_solid: false, solid: None };
+        let swept_mins = start.min(&end) + mins;
+        let swept_maxs = start.max(&end) + maxs;
+
+        let ((x0, y0), (x1, y1)) = (bucket(swept_mins), bucket(swept_maxs));
+        let mut candidates: Vec<usize> = (x0..=x1)
+            .flat_map(|x| (y0..=y1).map(move |y| (x, y)))
+            .filter_map(|key| self.buckets.get(&key))
+            .flatten()
+            .copied()
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        for i in candidates {
+            let solid = &self.solids[i];
+            let overlaps = swept_mins.x <= solid.maxs.x && swept_maxs.x >= solid.mins.x
+                && swept_mins.y <= solid.maxs.y && swept_maxs.y >= solid.mins.y
+                && swept_mins.z <= solid.maxs.z && swept_maxs.z >= solid.mins.z;
+            if overlaps {
+                clip_box_to_solid(&mut trace, i, solid, start, end, mins, maxs);
+            }
+            if trace.all_solid {
+                break;
+            }
+        }
+
+        trace.end = start + (end - start) * trace.fraction;
+        trace
+    }
+}
+
+fn bucket(v: Vector) -> (i32, i32) {
+    ((v.x / BUCKET_SIZE).floor() as i32, (v.y / BUCKET_SIZE).floor() as i32)
+}
+
+fn bounds(vertices: &[Vector]) -> Option<(Vector, Vector)> {
+    let first = *vertices.first()?;
+    Some(vertices.iter().fold((first, first), |(mins, maxs), v| (mins.min(v), maxs.max(v))))
+}
+
+// CM_ClipBoxToBrush
+fn clip_box_to_solid(trace: &mut Trace, index: usize, solid: &ConvexSolid, start: Vector, end: Vector, mins: Vector, maxs: Vector) {
+    let mut enter = -1.0f32;
+    let mut leave = 1.0f32;
+    let mut normal = Vector::default();
+    let mut starts_out = false;
+    let mut ends_out = false;
+
+    for plane in solid.planes.iter() {
+        // Push the plane out by the corner of the box furthest behind it
+        let n = plane.normal;
+        let corner = Vector::new(
+            if n.x < 0.0 { maxs.x } else { mins.x },
+            if n.y < 0.0 { maxs.y } else { mins.y },
+            if n.z < 0.0 { maxs.z } else { mins.z },
+        );
+        let distance = plane.distance - corner.dot(&n);
+        let d1 = start.dot(&n) - distance;
+        let d2 = end.dot(&n) - distance;
+
+        if d2 > 0.0 {
+            ends_out = true;
+        }
+        if d1 > 0.0 {
+            starts_out = true;
+        }
+        // Completely in front of this plane, so it misses the solid
+        if d1 > 0.0 && (d2 >= DIST_EPSILON || d2 >= d1) {
+            return;
+        }
+        if d1 <= 0.0 && d2 <= 0.0 {
+            continue;
+        }
+        if d1 > d2 {
+            let f = (d1 - DIST_EPSILON) / (d1 - d2);
+            if f > enter {
+                enter = f;
+                normal = n;
+            }
+        } else {
+            leave = leave.min((d1 + DIST_EPSILON) / (d1 - d2));
+        }
+    }
+
+    if !starts_out {
+        trace.start_solid = true;
+        trace.all_solid = !ends_out;
+        trace.fraction = 0.0;
+        trace.solid = Some(index);
+        return;
+    }
+    if enter < leave && enter > -1.0 && enter < trace.fraction {
+        trace.fraction = enter.max(0.0);
+        trace.normal = normal;
+        trace.solid = Some(index);
+    }
+}
+
+// Every triangle of the displacements that match the mask
+fn displacement_triangles(bsp: &mut Bsp, mask: i32) -> Result<Vec<ConvexSolid>> {
+    let displacements: Vec<_> = bsp.displacements()?.into_iter()
+        .filter(|displacement| displacement.info.contents & mask != 0)
+        .collect();
+    if displacements.is_empty() {
+        return Ok(Vec::new());
+    }
+    let geometry = Geometry::load(bsp)?;
+    let mut solids = Vec::new();
+    for displacement in displacements {
+        let face = match geometry.faces.get(displacement.info.map_face as usize) {
+            Some(face) => face,
+            None => continue,
+        };
+        let up = geometry.face_normal(face);
+        let (positions, triangles) = displacement.mesh(&geometry.face_vertices(face));
+        for [a, b, c] in triangles.iter().map(|t| [positions[t[0]], positions[t[1]], positions[t[2]]]) {
+            // Facing the same way as the face, so they're thick underneath
+            let triangle = if (b - a).cross(&(c - a)).dot(&up) < 0.0 { [a, c, b] } else { [a, b, c] };
+            solids.extend(ConvexSolid::from_triangle(triangle, displacement.info.contents));
+        }
+    }
+    Ok(solids)
+}
+
+// Boxes around each static prop's collision hulls, turned and moved into place
+fn static_prop_boxes(bsp: &mut Bsp) -> Result<Vec<ConvexSolid>> {
+    let hulls = match bsp.prop_collision_hulls() {
+        Ok(hulls) => hulls,
+        Err(Error::MissingLump(_)) | Err(Error::LumpNotInVersion { .. }) => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let models = bsp.static_prop_models()?;
+    let mut boxes = Vec::new();
+    for prop in bsp.static_props()? {
+        let model = models.iter().position(|m| *m == prop.model);
+        let model_hulls = match model.and_then(|m| hulls.get(m)) {
+            Some(hulls) => hulls,
+            None => continue,
+        };
+        let [forward, left, up] = angle_vectors(prop.angles);
+        let vertices: Vec<Vector> = model_hulls.iter()
+            .flat_map(|hull| hull.vertices.iter())
+            .map(|v| prop.origin + forward * v.x + left * v.y + up * v.z)
+            .collect();
+        if let Some((mins, maxs)) = bounds(&vertices) {
+            boxes.push(ConvexSolid::from_box(mins, maxs, CONTENTS_SOLID));
+        }
+    }
+    Ok(boxes)
+}
+
+// A plane with its kind filled in, 0-2 for the X, Y and Z planes and 3-5 for the rest (by their biggest axis)
+fn axial_kind(normal: Vector, distance: f32) -> Plane {
+    let axes = [normal.x.abs(), normal.y.abs(), normal.z.abs()];
+    let axis = (0..3).max_by(|&a, &b| axes[a].total_cmp(&axes[b])).unwrap_or(0);
+    let kind = if axes[axis] >= 1.0 { axis } else { axis + 3 };
+    Plane { normal, distance, kind: kind as i32 }
+}
+
+// AngleMatrix's columns, for (pitch, yaw, roll) in degrees
+fn angle_vectors(angles: Vector) -> [Vector; 3] {
+    let (sp, cp) = angles.x.to_radians().sin_cos();
+    let (sy, cy) = angles.y.to_radians().sin_cos();
+    let (sr, cr) = angles.z.to_radians().sin_cos();
+    [
+        Vector::new(cp * cy, cp * sy, -sp),
+        Vector::new(sr * sp * cy - cr * sy, sr * sp * sy + cr * cy, sr * cp),
+        Vector::new(cr * sp * cy + sr * sy, cr * sp * sy - sr * cy, cr * cp),
+    ]
+}
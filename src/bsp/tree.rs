@@ -0,0 +1,221 @@
+// The BSP tree itself: nodes split space by a plane until reaching a leaf
+// Every brush model (the world is model 0) has its own tree, starting at its head node
+
+use super::lump_item::{LumpContext, LumpItem, LumpReader, LumpWriter};
+use crate::Vector;
+
+// dnode_t
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Node {
+    pub plane_number: i32,
+    // Positive numbers are node indices, negative are leaf indices as -(leaf + 1)
+    pub children: [i32; 2],
+    pub mins: [i16; 3],
+    pub maxs: [i16; 3],
+    pub first_face: u16,
+    pub num_faces: u16,
+    pub area: i16,
+}
+
+// Where a node's child leads
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Child {
+    Node(usize),
+    Leaf(usize),
+}
+
+impl Child {
+    pub fn from_index(index: i32) -> Self {
+        if index >= 0 {
+            Child::Node(index as usize)
+        } else {
+            Child::Leaf((-1 - index) as usize)
+        }
+    }
+}
+
+impl Node {
+    // children[0] is in front of the plane, children[1] is behind it
+    pub fn child(&self, side: usize) -> Child {
+        Child::from_index(self.children[side])
+    }
+}
+
+impl LumpItem for Node {
+    fn size(_: &LumpContext) -> usize { 32 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        let node = Self {
+            plane_number: r.i32(),
+            children: [r.i32(), r.i32()],
+            mins: [r.i16(), r.i16(), r.i16()],
+            maxs: [r.i16(), r.i16(), r.i16()],
+            first_face: r.u16(),
+            num_faces: r.u16(),
+            area: r.i16(),
+        };
+        r.skip(2); // padding
+        node
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.i32(self.plane_number);
+        w.i32(self.children[0]);
+        w.i32(self.children[1]);
+        for v in self.mins.iter().chain(self.maxs.iter()) {
+            w.i16(*v);
+        }
+        w.u16(self.first_face);
+        w.u16(self.num_faces);
+        w.i16(self.area);
+        w.i16(0);
+    }
+}
+
+// Some of the CONTENTS_* flags in Leaf.contents
+pub const CONTENTS_EMPTY: i32 = 0x0;
+pub const CONTENTS_SOLID: i32 = 0x1;
+pub const CONTENTS_WINDOW: i32 = 0x2;
+pub const CONTENTS_GRATE: i32 = 0x8;
+pub const CONTENTS_SLIME: i32 = 0x10;
+pub const CONTENTS_WATER: i32 = 0x20;
+pub const CONTENTS_PLAYERCLIP: i32 = 0x10000;
+pub const CONTENTS_MONSTERCLIP: i32 = 0x20000;
+pub const CONTENTS_LADDER: i32 = 0x20000000;
+
+// dleaf_t
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Leaf {
+    pub contents: i32,
+    pub cluster: i16,
+    pub area: u16, // 9 bits in the file
+    pub flags: u8, // 7 bits in the file
+    pub mins: [i16; 3],
+    pub maxs: [i16; 3],
+    pub first_leaf_face: u16,
+    pub num_leaf_faces: u16,
+    pub first_leaf_brush: u16,
+    pub num_leaf_brushes: u16,
+    pub leaf_water_data_id: i16,
+    // Only in version 0 of the lump (HL2 era maps), moved to LeafAmbientLighting later
+    pub ambient_lighting: Option<[u8; 24]>,
+}
+
+impl LumpItem for Leaf {
+    fn size(context: &LumpContext) -> usize {
+        if context.lump_version == 0 { 56 } else { 32 }
+    }
+
+    fn read(r: &mut LumpReader) -> Self {
+        let contents = r.i32();
+        let cluster = r.i16();
+        let area_flags = r.u16();
+        let mins = [r.i16(), r.i16(), r.i16()];
+        let maxs = [r.i16(), r.i16(), r.i16()];
+        let first_leaf_face = r.u16();
+        let num_leaf_faces = r.u16();
+        let first_leaf_brush = r.u16();
+        let num_leaf_brushes = r.u16();
+        let leaf_water_data_id = r.i16();
+        let ambient_lighting = if r.context.lump_version == 0 {
+            let mut cube = [0; 24];
+            cube.copy_from_slice(r.bytes(24));
+            Some(cube)
+        } else {
+            None
+        };
+        r.skip(2); // padding
+
+        Self {
+            contents,
+            cluster,
+            area: area_flags & 0x1FF,
+            flags: (area_flags >> 9) as u8,
+            mins,
+            maxs,
+            first_leaf_face,
+            num_leaf_faces,
+            first_leaf_brush,
+            num_leaf_brushes,
+            leaf_water_data_id,
+            ambient_lighting,
+        }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.i32(self.contents);
+        w.i16(self.cluster);
+        w.u16((self.area & 0x1FF) | ((self.flags as u16) << 9));
+        for v in self.mins.iter().chain(self.maxs.iter()) {
+            w.i16(*v);
+        }
+        w.u16(self.first_leaf_face);
+        w.u16(self.num_leaf_faces);
+        w.u16(self.first_leaf_brush);
+        w.u16(self.num_leaf_brushes);
+        w.i16(self.leaf_water_data_id);
+        if w.context.lump_version == 0 {
+            w.bytes(&self.ambient_lighting.unwrap_or([0; 24]));
+        }
+        w.i16(0);
+    }
+}
+
+// dmodel_t
+// Model 0 is the world, the rest are brush entities (func_door, ...)
+// which refer to them as "*1", "*2", ...
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Model {
+    pub mins: Vector,
+    pub maxs: Vector,
+    pub origin: Vector,
+    pub head_node: i32,
+    pub first_face: i32,
+    pub num_faces: i32,
+}
+
+impl LumpItem for Model {
+    fn size(_: &LumpContext) -> usize { 48 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self {
+            mins: r.vector(),
+            maxs: r.vector(),
+            origin: r.vector(),
+            head_node: r.i32(),
+            first_face: r.i32(),
+            num_faces: r.i32(),
+        }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.vector(&self.mins);
+        w.vector(&self.maxs);
+        w.vector(&self.origin);
+        w.i32(self.head_node);
+        w.i32(self.first_face);
+        w.i32(self.num_faces);
+    }
+}
+
+// Every leaf under a node, in tree order
+// Bad child indices and loops are skipped rather than followed
+pub fn tree_leafs(head_node: i32, nodes: &[Node]) -> Vec<usize> {
+    let mut leafs = Vec::new();
+    let mut visited = vec![false; nodes.len()];
+    let mut stack = vec![Child::from_index(head_node)];
+    while let Some(child) = stack.pop() {
+        match child {
+            Child::Leaf(leaf) => leafs.push(leaf),
+            Child::Node(node) => {
+                if node >= nodes.len() || visited[node] {
+                    continue;
+                }
+                visited[node] = true;
+                stack.push(nodes[node].child(1));
+                stack.push(nodes[node].child(0));
+            },
+        }
+    }
+    leafs
+}
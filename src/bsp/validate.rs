@@ -0,0 +1,320 @@
+// Consistency checks for a whole map, for catching broken compiles before anyone loads them
+// Looks for lumps that overlap or run off the end of the file, records that point
+// past the end of other lumps, entities using brush models that don't exist, etc.
+// Nothing here stops at the first problem, everything found is returned
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning, // probably a mistake, but the engine will cope
+    Error, // the engine will crash, refuse to load the map, or draw garbage
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    LumpOutOfFile { lump: LumpIndex, offset: u32, length: u32, file_size: u64 },
+    LumpsOverlap { first: LumpIndex, second: LumpIndex },
+    // The lump isn't a whole number of records
+    BadLumpLength { lump: LumpIndex, length: u32, record_size: usize },
+    UnreadableLump { lump: LumpIndex, error: String },
+    // Record number `record` in `lump` has a `field` that points past the end of `target`
+    IndexOutOfRange {
+        lump: LumpIndex,
+        record: usize,
+        field: &'static str,
+        index: i64,
+        target: LumpIndex,
+        count: usize,
+    },
+    MissingWorldspawn,
+    // An entity uses model "*N" but there aren't that many brush models
+    MissingBrushModel { entity: usize, model: usize, count: usize },
+    // No TextureInfo uses this TextureData
+    UnreferencedTextureData { index: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub problem: Problem,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: ")?,
+            Severity::Error => write!(f, "error: ")?,
+        }
+        match &self.problem {
+            Problem::LumpOutOfFile { lump, offset, length, file_size } => write!(f,
+                "lump {:?} ({} bytes at {}) goes past the end of the file ({} bytes)",
+                lump, length, offset, file_size),
+            Problem::LumpsOverlap { first, second } => write!(f,
+                "lumps {:?} and {:?} overlap", first, second),
+            Problem::BadLumpLength { lump, length, record_size } => write!(f,
+                "lump {:?} is {} bytes, which isn't a multiple of its record size ({})",
+                lump, length, record_size),
+            Problem::UnreadableLump { lump, error } => write!(f,
+                "lump {:?} couldn't be read: {}", lump, error),
+            Problem::IndexOutOfRange { lump, record, field, index, target, count } => write!(f,
+                "{:?}[{}].{} is {}, but {:?} only has {} entries",
+                lump, record, field, index, target, count),
+            Problem::MissingWorldspawn => write!(f, "there is no worldspawn entity"),
+            Problem::MissingBrushModel { entity, model, count } => write!(f,
+                "entity {} uses model \"*{}\", but there are only {} models",
+                entity, model, count),
+            Problem::UnreferencedTextureData { index } => write!(f,
+                "TextureData[{}] isn't used by any TextureInfo", index),
+        }
+    }
+}
+
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(|d| d.severity == Severity::Error)
+}
+
+pub fn validate(bsp: &mut Bsp) -> Vec<Diagnostic> {
+    let mut v = Validator { diagnostics: Vec::new() };
+    v.check_layout(bsp);
+    v.check_record_sizes(bsp);
+    v.check_indices(bsp);
+    v.check_entities(bsp);
+    v.diagnostics
+}
+
+struct Validator {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator {
+    fn report(&mut self, severity: Severity, problem: Problem) {
+        self.diagnostics.push(Diagnostic { severity, problem });
+    }
+
+    fn check_layout(&mut self, bsp: &Bsp) {
+        let mut present: Vec<(LumpIndex, Lump)> = LumpIndex::ALL.iter()
+            .map(|&index| (index, bsp.lumps[index as usize]))
+            .filter(|(_, lump)| lump.exists())
+            .collect();
+
+        for (index, lump) in present.iter() {
+            if lump.offset as u64 + lump.length as u64 > bsp.file_size() {
+                self.report(Severity::Error, Problem::LumpOutOfFile {
+                    lump: *index,
+                    offset: lump.offset,
+                    length: lump.length,
+                    file_size: bsp.file_size(),
+                });
+            }
+        }
+
+        // Sorted by offset, a lump overlaps anything that starts before the furthest end so far
+        present.sort_by_key(|(_, lump)| lump.offset);
+        let mut furthest: Option<(LumpIndex, u64)> = None;
+        for (index, lump) in present.iter() {
+            let end = lump.offset as u64 + lump.length as u64;
+            if let Some((previous, previous_end)) = furthest {
+                if (lump.offset as u64) < previous_end {
+                    self.report(Severity::Error, Problem::LumpsOverlap { first: previous, second: *index });
+                }
+                if end <= previous_end {
+                    continue;
+                }
+            }
+            furthest = Some((*index, end));
+        }
+    }
+
+    fn check_record_sizes(&mut self, bsp: &Bsp) {
+        fn size<T: LumpItem>(bsp: &Bsp, index: LumpIndex) -> (LumpIndex, usize) {
+            (index, T::size(&bsp.lump_context(index)))
+        }
+
+        let sizes = [
+            size::<Plane>(bsp, LumpIndex::Planes),
+            size::<TextureData>(bsp, LumpIndex::TextureData),
+            size::<Vector>(bsp, LumpIndex::Vertices),
+            size::<Node>(bsp, LumpIndex::Nodes),
+            size::<TextureInfo>(bsp, LumpIndex::TextureInfo),
+            size::<Face>(bsp, LumpIndex::Faces),
+            size::<Leaf>(bsp, LumpIndex::Leafs),
+            size::<Edge>(bsp, LumpIndex::Edges),
+            size::<i32>(bsp, LumpIndex::SurfaceEdges),
+            size::<Model>(bsp, LumpIndex::Models),
+            size::<u16>(bsp, LumpIndex::LeafFaces),
+            size::<u16>(bsp, LumpIndex::LeafBrushes),
+            size::<Face>(bsp, LumpIndex::OriginalFaces),
+            size::<Vector>(bsp, LumpIndex::VertexNormals),
+            size::<u16>(bsp, LumpIndex::VertexNormalIndices),
+            size::<i32>(bsp, LumpIndex::TextureStringTable),
+            size::<Face>(bsp, LumpIndex::FacesHdr),
+        ];
+
+        for (index, record_size) in sizes.iter() {
+            let lump = bsp.lumps[*index as usize];
+            let length = match bsp.lump_file(*index) {
+                Some(lump_file) => lump_file.data.len() as u32,
+                // Compressed lumps keep their uncompressed size in the fourCC
+                None if bsp.is_lump_compressed(*index) => {
+                    LumpReader::new(&lump.indent_code, LumpContext { endian: bsp.endian, ..Default::default() }).u32()
+                },
+                None => lump.length,
+            };
+            if length as usize % *record_size != 0 {
+                self.report(Severity::Error, Problem::BadLumpLength {
+                    lump: *index,
+                    length,
+                    record_size: *record_size,
+                });
+            }
+        }
+    }
+
+    // Missing lumps are empty, anything unreadable gets reported and treated as empty
+    fn load<T: LumpItem>(&mut self, bsp: &mut Bsp, index: LumpIndex) -> Vec<T> {
+        match bsp.get_lump(index) {
+            Ok(items) => items,
+            Err(Error::MissingLump(_)) => Vec::new(),
+            Err(e) => {
+                self.report(Severity::Error, Problem::UnreadableLump { lump: index, error: e.to_string() });
+                Vec::new()
+            },
+        }
+    }
+
+    // Check that index points at something in target
+    #[allow(clippy::too_many_arguments)]
+    fn index(&mut self, lump: LumpIndex, record: usize, field: &'static str,
+             index: i64, target: LumpIndex, count: usize) {
+        if index < 0 || index as usize >= count {
+            self.report(Severity::Error, Problem::IndexOutOfRange { lump, record, field, index, target, count });
+        }
+    }
+
+    // Check that first..first+num fits in target
+    #[allow(clippy::too_many_arguments)]
+    fn range(&mut self, lump: LumpIndex, record: usize, field: &'static str,
+             first: i64, num: i64, target: LumpIndex, count: usize) {
+        if num > 0 {
+            self.index(lump, record, field, first, target, count);
+            self.index(lump, record, field, first + num - 1, target, count);
+        }
+    }
+
+    fn check_indices(&mut self, bsp: &mut Bsp) {
+        use LumpIndex::*;
+
+        let planes: Vec<Plane> = self.load(bsp, Planes);
+        let vertices: Vec<Vector> = self.load(bsp, Vertices);
+        let edges: Vec<Edge> = self.load(bsp, Edges);
+        let surface_edges: Vec<i32> = self.load(bsp, SurfaceEdges);
+        let faces: Vec<Face> = self.load(bsp, Faces);
+        let texture_infos: Vec<super::TextureInfo> = self.load(bsp, TextureInfo);
+        let texture_data: Vec<super::TextureData> = self.load(bsp, TextureData);
+        let string_table: Vec<i32> = self.load(bsp, TextureStringTable);
+        let nodes: Vec<Node> = self.load(bsp, Nodes);
+        let leafs: Vec<Leaf> = self.load(bsp, Leafs);
+        let leaf_faces: Vec<u16> = self.load(bsp, LeafFaces);
+        let leaf_brushes: Vec<u16> = self.load(bsp, LeafBrushes);
+        let models: Vec<Model> = self.load(bsp, Models);
+
+        for (i, edge) in edges.iter().enumerate() {
+            for &v in edge.v.iter() {
+                self.index(Edges, i, "v", v as i64, Vertices, vertices.len());
+            }
+        }
+
+        for (i, &surface_edge) in surface_edges.iter().enumerate() {
+            self.index(SurfaceEdges, i, "edge", (surface_edge as i64).abs(), Edges, edges.len());
+        }
+
+        for (i, face) in faces.iter().enumerate() {
+            self.index(Faces, i, "plane_number", face.plane_number as i64, Planes, planes.len());
+            self.range(Faces, i, "first_edge", face.first_edge as i64, face.num_edges as i64,
+                SurfaceEdges, surface_edges.len());
+            if face.tex_info >= 0 {
+                self.index(Faces, i, "tex_info", face.tex_info as i64, TextureInfo, texture_infos.len());
+            }
+        }
+
+        let mut used_texture_data = vec![false; texture_data.len()];
+        for (i, info) in texture_infos.iter().enumerate() {
+            if info.texture_data >= 0 {
+                self.index(TextureInfo, i, "texture_data", info.texture_data as i64,
+                    TextureData, texture_data.len());
+                if let Some(used) = used_texture_data.get_mut(info.texture_data as usize) {
+                    *used = true;
+                }
+            }
+        }
+
+        for (i, data) in texture_data.iter().enumerate() {
+            self.index(TextureData, i, "name_string_table_id", data.name_string_table_id as i64,
+                TextureStringTable, string_table.len());
+            if !used_texture_data[i] {
+                self.report(Severity::Warning, Problem::UnreferencedTextureData { index: i });
+            }
+        }
+
+        for (i, node) in nodes.iter().enumerate() {
+            self.index(Nodes, i, "plane_number", node.plane_number as i64, Planes, planes.len());
+            for side in 0..2 {
+                match node.child(side) {
+                    Child::Node(n) => self.index(Nodes, i, "children", n as i64, Nodes, nodes.len()),
+                    Child::Leaf(l) => self.index(Nodes, i, "children", l as i64, Leafs, leafs.len()),
+                }
+            }
+            self.range(Nodes, i, "first_face", node.first_face as i64, node.num_faces as i64,
+                Faces, faces.len());
+        }
+
+        for (i, leaf) in leafs.iter().enumerate() {
+            self.range(Leafs, i, "first_leaf_face", leaf.first_leaf_face as i64, leaf.num_leaf_faces as i64,
+                LeafFaces, leaf_faces.len());
+            self.range(Leafs, i, "first_leaf_brush", leaf.first_leaf_brush as i64, leaf.num_leaf_brushes as i64,
+                LeafBrushes, leaf_brushes.len());
+        }
+
+        for (i, &face) in leaf_faces.iter().enumerate() {
+            self.index(LeafFaces, i, "face", face as i64, Faces, faces.len());
+        }
+
+        for (i, model) in models.iter().enumerate() {
+            self.index(Models, i, "head_node", model.head_node as i64, Nodes, nodes.len());
+            self.range(Models, i, "first_face", model.first_face as i64, model.num_faces as i64,
+                Faces, faces.len());
+        }
+    }
+
+    fn check_entities(&mut self, bsp: &mut Bsp) {
+        let entities = match bsp.entities() {
+            Ok(entities) => entities,
+            Err(e) => {
+                self.report(Severity::Error, Problem::UnreadableLump {
+                    lump: LumpIndex::Entities,
+                    error: e.to_string(),
+                });
+                return;
+            },
+        };
+        let models: Vec<Model> = self.load(bsp, LumpIndex::Models);
+
+        if !entities.iter().any(|e| e.classname() == Some("worldspawn")) {
+            self.report(Severity::Error, Problem::MissingWorldspawn);
+        }
+
+        for (i, entity) in entities.iter().enumerate() {
+            let model = entity.get("model").and_then(|m| m.strip_prefix('*')).and_then(|n| n.parse().ok());
+            if let Some(model) = model {
+                if model >= models.len() {
+                    self.report(Severity::Error, Problem::MissingBrushModel {
+                        entity: i,
+                        model,
+                        count: models.len(),
+                    });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,280 @@
+// Turning a compiled map back into a Hammer .vmf
+// Brushes are rebuilt from their side planes, textures from TextureInfo,
+// and brush entities get their brushes back through their "*N" model
+//
+// What can't come back: displacements (they come back as the flat face they were built on),
+// visgroups and groups, func_detail grouping (all detail brushes end up in one func_detail),
+// and brushes vbsp throws away, like areaportals and occluders
+
+use super::brush::{clip_brush, Brush, BrushSide, CONTENTS_DETAIL};
+use super::entity::{Connection, Entity};
+use super::error::*;
+use super::geometry::optional;
+use super::texture::{TextureData, TextureInfo};
+use super::tree::tree_leafs;
+use super::Bsp;
+use crate::keyvalues::KeyValues;
+use crate::{Plane, Vector};
+
+const DEFAULT_MATERIAL: &str = "TOOLS/TOOLSNODRAW";
+
+// Everything needed to rebuild brushes, loaded once
+struct Source {
+    planes: Vec<Plane>,
+    brushes: Vec<Brush>,
+    brush_sides: Vec<BrushSide>,
+    texture_infos: Vec<TextureInfo>,
+    texture_data: Vec<TextureData>,
+    texture_names: Vec<String>,
+}
+
+// Ids for entities, solids and sides, Hammer wants them unique
+#[derive(Default)]
+struct Ids {
+    next_object: usize,
+    next_side: usize,
+}
+
+impl Ids {
+    fn object(&mut self) -> String {
+        self.next_object += 1;
+        self.next_object.to_string()
+    }
+
+    fn side(&mut self) -> String {
+        self.next_side += 1;
+        self.next_side.to_string()
+    }
+}
+
+pub fn decompile(bsp: &mut Bsp) -> Result<KeyValues> {
+    let source = Source {
+        planes: bsp.planes()?,
+        brushes: optional(bsp.brushes())?,
+        brush_sides: optional(bsp.brush_sides())?,
+        texture_infos: optional(bsp.texture_infos())?,
+        texture_data: optional(bsp.texture_data())?,
+        texture_names: match bsp.texture_names() {
+            Err(Error::MissingLump(_)) => Vec::new(),
+            names => names?,
+        },
+    };
+    let models = optional(bsp.models())?;
+    let nodes = optional(bsp.nodes())?;
+    let leafs = optional(bsp.leafs())?;
+    let leaf_brushes = optional(bsp.leaf_brushes())?;
+    let entities = bsp.entities()?;
+
+    // The brushes in a model, found through the leafs of its tree
+    let model_brushes = |model: usize| -> Vec<usize> {
+        let mut brushes: Vec<usize> = match models.get(model) {
+            Some(model) => tree_leafs(model.head_node, &nodes).iter()
+                .filter_map(|&leaf| leafs.get(leaf))
+                .flat_map(|leaf| {
+                    let first = leaf.first_leaf_brush as usize;
+                    leaf_brushes.get(first..first + leaf.num_leaf_brushes as usize).unwrap_or(&[])
+                })
+                .map(|&brush| brush as usize)
+                .collect(),
+            None => Vec::new(),
+        };
+        brushes.sort_unstable();
+        brushes.dedup();
+        brushes
+    };
+
+    let mut ids = Ids::default();
+    let mut vmf = KeyValues::new();
+
+    let mut version = KeyValues::new();
+    version.push_value("editorversion", "400");
+    version.push_value("editorbuild", "0");
+    version.push_value("mapversion", &bsp.iteration.to_string());
+    version.push_value("formatversion", "100");
+    version.push_value("prefab", "0");
+    vmf.push_subkey("versioninfo", version);
+
+    // The world, minus func_detail brushes which go back into their own entity
+    let worldspawn = entities.iter().find(|e| e.classname() == Some("worldspawn"));
+    let mut world = entity_block(worldspawn.unwrap_or(&Entity::new()), &mut ids);
+    world.add_value("classname", "worldspawn");
+    let (detail, structural): (Vec<usize>, Vec<usize>) = model_brushes(0).into_iter()
+        .partition(|&b| source.brushes.get(b).is_some_and(|b| b.contents & CONTENTS_DETAIL != 0));
+    for brush in structural {
+        if let Some(solid) = solid_block(&source, brush, Vector::default(), &mut ids) {
+            world.push_subkey("solid", solid);
+        }
+    }
+    vmf.push_subkey("world", world);
+
+    for entity in entities.iter() {
+        if entity.classname() == Some("worldspawn") {
+            continue;
+        }
+        let mut block = entity_block(entity, &mut ids);
+
+        // Brush entities point at their model as "*N"
+        let model = entity.get("model").and_then(|m| m.strip_prefix('*')).and_then(|n| n.parse::<usize>().ok());
+        if let Some(model) = model {
+            // vbsp moves brush entities with an origin so it's at 0 0 0, move them back
+            let origin = entity.get("origin").map(parse_vector).unwrap_or_default();
+            for brush in model_brushes(model) {
+                if let Some(solid) = solid_block(&source, brush, origin, &mut ids) {
+                    block.push_subkey("solid", solid);
+                }
+            }
+        }
+        vmf.push_subkey("entity", block);
+    }
+
+    if !detail.is_empty() {
+        let mut block = KeyValues::new();
+        block.push_value("id", &ids.object());
+        block.push_value("classname", "func_detail");
+        for brush in detail {
+            if let Some(solid) = solid_block(&source, brush, Vector::default(), &mut ids) {
+                block.push_subkey("solid", solid);
+            }
+        }
+        vmf.push_subkey("entity", block);
+    }
+
+    Ok(vmf)
+}
+
+fn entity_block(entity: &Entity, ids: &mut Ids) -> KeyValues {
+    let mut block = KeyValues::new();
+    block.push_value("id", &ids.object());
+    // Hammer only shows outputs that are in a "connections" block
+    let mut connections = KeyValues::new();
+    for (key, value) in entity.properties.iter() {
+        // "*N" models are replaced by the brushes themselves
+        if key.eq_ignore_ascii_case("model") && value.starts_with('*') {
+            continue;
+        }
+        if Connection::parse(key, value).is_some() {
+            connections.push_value(key, value);
+        } else {
+            block.push_value(key, value);
+        }
+    }
+    if !connections.is_empty() {
+        block.push_subkey("connections", connections);
+    }
+    block
+}
+
+fn solid_block(source: &Source, brush: usize, origin: Vector, ids: &mut Ids) -> Option<KeyValues> {
+    let brush = source.brushes.get(brush)?;
+    let first = brush.first_side.max(0) as usize;
+    let sides: Vec<&BrushSide> = source.brush_sides.get(first..first + brush.num_sides.max(0) as usize)?
+        .iter()
+        .filter(|side| !side.bevel)
+        .collect();
+    let planes: Vec<Plane> = sides.iter()
+        .filter_map(|side| source.planes.get(side.plane_number as usize).copied())
+        .collect();
+    if planes.len() != sides.len() {
+        return None;
+    }
+
+    let mut solid = KeyValues::new();
+    solid.push_value("id", &ids.object());
+    let mut side_count = 0;
+    for ((side, plane), polygon) in sides.iter().zip(planes.iter()).zip(clip_brush(&planes)) {
+        if polygon.is_empty() {
+            continue;
+        }
+        side_count += 1;
+        solid.push_subkey("side", side_block(source, side, plane, &polygon, origin, ids));
+    }
+    // A brush needs at least a tetrahedron's worth of sides
+    if side_count < 4 { None } else { Some(solid) }
+}
+
+fn side_block(source: &Source, side: &BrushSide, plane: &Plane, polygon: &[Vector], origin: Vector, ids: &mut Ids) -> KeyValues {
+    // Hammer's plane normal is (p0 - p1) x (p2 - p1), facing out of the brush
+    let (mut p0, p1, mut p2) = (polygon[0], polygon[1], polygon[2]);
+    if (p0 - p1).cross(&(p2 - p1)).dot(&plane.normal) < 0.0 {
+        std::mem::swap(&mut p0, &mut p2);
+    }
+    let point = |v: Vector| {
+        let v = v + origin;
+        format!("({} {} {})", number(v.x), number(v.y), number(v.z))
+    };
+
+    let info = source.texture_infos.get(side.texture_info as usize).filter(|_| side.texture_info >= 0);
+    let material = info
+        .and_then(|info| source.texture_data.get(info.texture_data as usize))
+        .and_then(|data| source.texture_names.get(data.name_string_table_id as usize))
+        .map(|name| name.as_str())
+        .unwrap_or(DEFAULT_MATERIAL);
+
+    let mut block = KeyValues::new();
+    block.push_value("id", &ids.side());
+    block.push_value("plane", &format!("{} {} {}", point(p0), point(p1), point(p2)));
+    block.push_value("material", material);
+    match info {
+        Some(info) => {
+            block.push_value("uaxis", &texture_axis(info.texture_vecs[0], origin));
+            block.push_value("vaxis", &texture_axis(info.texture_vecs[1], origin));
+        },
+        None => {
+            let (u, v) = default_axes(&plane.normal);
+            block.push_value("uaxis", &format!("[{} {} {} 0] 0.25", number(u.x), number(u.y), number(u.z)));
+            block.push_value("vaxis", &format!("[{} {} {} 0] 0.25", number(v.x), number(v.y), number(v.z)));
+        },
+    }
+    block.push_value("rotation", "0");
+    block.push_value("lightmapscale", &info.map_or(16, lightmap_scale).to_string());
+    block.push_value("smoothing_groups", "0");
+    block
+}
+
+// texture_vecs are axis / scale with the shift last, Hammer wants "[axis shift] scale"
+fn texture_axis(vecs: [f32; 4], origin: Vector) -> String {
+    let axis = Vector::new(vecs[0], vecs[1], vecs[2]);
+    let length = axis.length();
+    if length == 0.0 {
+        return "[1 0 0 0] 0.25".to_string();
+    }
+    let shift = vecs[3] - axis.dot(&origin);
+    let axis = axis * (1.0 / length);
+    format!("[{} {} {} {}] {}", number(axis.x), number(axis.y), number(axis.z), number(shift), number(1.0 / length))
+}
+
+// Luxels per unit are stored the same way as texture scale
+fn lightmap_scale(info: &TextureInfo) -> i32 {
+    let v = info.lightmap_vecs[0];
+    let length = Vector::new(v[0], v[1], v[2]).length();
+    if length == 0.0 { 16 } else { (1.0 / length).round() as i32 }
+}
+
+// World-aligned axes, for sides without texture info
+fn default_axes(normal: &Vector) -> (Vector, Vector) {
+    let (x, y, z) = (normal.x.abs(), normal.y.abs(), normal.z.abs());
+    if z >= x && z >= y {
+        (Vector::new(1.0, 0.0, 0.0), Vector::new(0.0, -1.0, 0.0))
+    } else if x >= y {
+        (Vector::new(0.0, 1.0, 0.0), Vector::new(0.0, 0.0, -1.0))
+    } else {
+        (Vector::new(1.0, 0.0, 0.0), Vector::new(0.0, 0.0, -1.0))
+    }
+}
+
+fn parse_vector(s: &str) -> Vector {
+    let mut parts = s.split_whitespace().map(|p| p.parse::<f32>().unwrap_or(0.0));
+    Vector::new(parts.next().unwrap_or(0.0), parts.next().unwrap_or(0.0), parts.next().unwrap_or(0.0))
+}
+
+// Whole numbers where they're close enough, Hammer prefers those
+fn number(f: f32) -> String {
+    let rounded = f.round();
+    if (f - rounded).abs() < 0.005 {
+        // No "-0"
+        format!("{}", rounded as i64)
+    } else {
+        let s = format!("{:.3}", f);
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    }
+}
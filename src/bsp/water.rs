@@ -0,0 +1,46 @@
+// Water volumes
+// Every leaf inside water points at a LeafWaterData record with its surface height,
+// and LeafDistanceToWater says how far each leaf is from the nearest water
+
+use super::lump_item::{LumpContext, LumpItem, LumpReader, LumpWriter};
+
+// dleafwaterdata_t
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LeafWaterData {
+    pub surface_z: f32,
+    // Bottom of the water volume
+    pub min_z: f32,
+    // TextureInfo of the water's surface
+    pub surface_texture_info: i16,
+}
+
+impl LumpItem for LeafWaterData {
+    fn size(_: &LumpContext) -> usize { 12 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        let water = Self {
+            surface_z: r.f32(),
+            min_z: r.f32(),
+            surface_texture_info: r.i16(),
+        };
+        r.skip(2); // padding
+        water
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.f32(self.surface_z);
+        w.f32(self.min_z);
+        w.i16(self.surface_texture_info);
+        w.i16(0);
+    }
+}
+
+// The answer to Bsp::water_at()
+#[derive(Debug, Clone, PartialEq)]
+pub struct Water {
+    pub leaf: usize,
+    pub surface_z: f32,
+    pub min_z: f32,
+    // Material of the surface, if the texture info leads to one
+    pub material: Option<String>,
+}
@@ -0,0 +1,207 @@
+// Writes a .bsp from scratch, recomputing every lump's offset
+// Start from an existing map with BspWriter::from_bsp, swap out whatever lumps
+// need changing, then write() or save()
+// Lumps can be LZMA-compressed on the way out with set_lump_compressed(),
+// lumps copied from a map keep whatever compression they had
+
+use super::error::*;
+use super::header::{Header, HEADER_SIZE};
+use super::lump::{Lump, LumpIndex, LumpLayout};
+use super::game_lump::GameLump;
+use super::lump_item::{Endian, LumpContext, LumpItem, write_items};
+use super::entity::{entities_to_lump_data, Entity};
+use super::lzma::compress_lump;
+use super::Bsp;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+#[derive(Debug, Default, Clone)]
+struct LumpEntry {
+    version: u32,
+    // Uncompressed, it gets compressed in write() if compress is set
+    data: Vec<u8>,
+    compress: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct BspWriter {
+    pub version: u32,
+    pub iteration: u32,
+    pub layout: LumpLayout,
+    pub endian: Endian,
+    lumps: Vec<LumpEntry>,
+    // Kept apart from the other lumps since its offsets depend on where it ends up
+    // None leaves the GameLump out, an empty list still writes its (zero) count
+    game_lumps: Option<Vec<GameLump>>,
+}
+
+impl BspWriter {
+    // A map with no lumps at all
+    pub fn new(version: u32) -> Self {
+        Self {
+            version,
+            iteration: 0,
+            layout: LumpLayout::Standard,
+            endian: Endian::Little,
+            lumps: vec![LumpEntry::default(); 64],
+            game_lumps: None,
+        }
+    }
+
+    // Copy every lump out of an existing map (including .lmp overrides)
+    // The iteration is bumped, since the result is a new revision of the map
+    pub fn from_bsp(bsp: &mut Bsp) -> Result<Self> {
+        let mut writer = Self::new(bsp.version);
+        writer.iteration = bsp.iteration + 1;
+        writer.layout = bsp.layout;
+        writer.endian = bsp.endian;
+
+        for (i, entry) in writer.lumps.iter_mut().enumerate() {
+            entry.version = bsp.lumps[i].version;
+            entry.compress = bsp.lumps[i].indent_code != [0; 4];
+        }
+        // .lmp data is never compressed, whatever the lump it replaces was
+        for (&i, lump_file) in bsp.lump_files.iter() {
+            writer.lumps[i].version = lump_file.version;
+            writer.lumps[i].compress = false;
+        }
+
+        for index in LumpIndex::ALL.iter() {
+            let data = match bsp.get_lump_data(*index) {
+                Ok(data) => data,
+                Err(Error::MissingLump(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            if *index == LumpIndex::GameLump {
+                let offset = bsp.lump_data_offset(*index);
+                writer.game_lumps = Some(GameLump::read_all(&data, offset, bsp.endian)?);
+            } else {
+                writer.lumps[*index as usize].data = data;
+            }
+        }
+
+        Ok(writer)
+    }
+
+    pub fn lump_data(&self, index: LumpIndex) -> &[u8] {
+        &self.lumps[index as usize].data
+    }
+
+    pub fn lump_version(&self, index: LumpIndex) -> u32 {
+        self.lumps[index as usize].version
+    }
+
+    // Replace a lump's raw bytes
+    // The GameLump can't be set this way, use set_game_lumps()
+    pub fn set_lump_data(&mut self, index: LumpIndex, data: Vec<u8>) {
+        self.lumps[index as usize].data = data;
+    }
+
+    pub fn set_lump_version(&mut self, index: LumpIndex, version: u32) {
+        self.lumps[index as usize].version = version;
+    }
+
+    // Replace a lump with typed records, like set_lump(LumpIndex::Planes, &planes)
+    pub fn set_lump<T: LumpItem>(&mut self, index: LumpIndex, items: &[T]) {
+        let context = LumpContext {
+            bsp_version: self.version,
+            lump_version: self.lump_version(index),
+            endian: self.endian,
+        };
+        self.set_lump_data(index, write_items(items, context));
+    }
+
+    // Replace the Entities lump, like after editing bsp.entities()
+    pub fn set_entities(&mut self, entities: &[Entity]) {
+        self.set_lump_data(LumpIndex::Entities, entities_to_lump_data(entities));
+    }
+
+    // Compress a lump when the map gets written, the way vbsp -lzma does
+    // The GameLump is left alone, since its sub-lumps are compressed separately
+    pub fn set_lump_compressed(&mut self, index: LumpIndex, compressed: bool) {
+        self.lumps[index as usize].compress = compressed;
+    }
+
+    pub fn remove_lump(&mut self, index: LumpIndex) {
+        self.set_lump_data(index, Vec::new());
+        self.set_lump_version(index, 0);
+        if index == LumpIndex::GameLump {
+            self.game_lumps = None;
+        }
+    }
+
+    pub fn game_lumps(&self) -> &[GameLump] {
+        self.game_lumps.as_deref().unwrap_or(&[])
+    }
+
+    pub fn set_game_lumps(&mut self, game_lumps: Vec<GameLump>) {
+        self.game_lumps = Some(game_lumps);
+    }
+
+    // Lay out the lumps one after another (in index order, 4-byte aligned)
+    // and write the whole map
+    pub fn write<T: Write>(&self, file: &mut T) -> Result<()> {
+        let mut header = Header {
+            version: self.version,
+            lumps: [Lump::default(); 64],
+            iteration: self.iteration,
+            layout: self.layout,
+            endian: self.endian,
+        };
+
+        let mut offset = HEADER_SIZE;
+        let mut datas: Vec<Vec<u8>> = Vec::with_capacity(64);
+        for (i, entry) in self.lumps.iter().enumerate() {
+            let mut indent_code = [0; 4];
+            let game_lumps = self.game_lumps.as_ref().filter(|_| i == LumpIndex::GameLump as usize);
+            let data = if let Some(game_lumps) = game_lumps {
+                GameLump::write_all(game_lumps, offset, self.endian)
+            } else if entry.compress && !entry.data.is_empty() {
+                // fourCC becomes the uncompressed size
+                let size = entry.data.len() as u32;
+                indent_code = match self.endian {
+                    Endian::Little => size.to_le_bytes(),
+                    Endian::Big => size.to_be_bytes(),
+                };
+                compress_lump(&entry.data)
+            } else {
+                entry.data.clone()
+            };
+
+            header.lumps[i] = Lump {
+                offset: if data.is_empty() { 0 } else { offset },
+                length: data.len() as u32,
+                version: entry.version,
+                indent_code,
+            };
+            offset = align(offset + data.len() as u32);
+            datas.push(data);
+        }
+
+        header.write(file)?;
+        let mut position = HEADER_SIZE;
+        for (lump, data) in header.lumps.iter().zip(datas.iter()) {
+            if data.is_empty() {
+                continue;
+            }
+            file.write_all(&vec![0; (lump.offset - position) as usize])?;
+            file.write_all(data)?;
+            position = lump.offset + lump.length;
+        }
+        // Pad the end too, so the file's size is a multiple of 4 like VBSP makes it
+        file.write_all(&vec![0; (align(position) - position) as usize])?;
+        Ok(())
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+fn align(offset: u32) -> u32 {
+    (offset + 3) & !3
+}
@@ -0,0 +1,95 @@
+// Lumps only found in Xbox (and later console) maps
+// On the PC, LightMapPages and LightMapPageInfo are reused for leaf ambient lighting
+// indices in Source 2007+, so only read these from console maps
+
+use super::lump_item::{LumpContext, LumpItem, LumpReader, LumpWriter};
+
+// ColorRGBExp32, the engine's packed HDR colour
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ColorRgbExp32 {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub exponent: i8,
+}
+
+impl ColorRgbExp32 {
+    // Linear colour, where 1.0 is full brightness
+    pub fn to_linear(&self) -> [f32; 3] {
+        let scale = 2f32.powi(self.exponent as i32) / 255.0;
+        [self.r as f32 * scale, self.g as f32 * scale, self.b as f32 * scale]
+    }
+}
+
+impl LumpItem for ColorRgbExp32 {
+    fn size(_: &LumpContext) -> usize { 4 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        Self { r: r.u8(), g: r.u8(), b: r.u8(), exponent: r.i8() }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.u8(self.r);
+        w.u8(self.g);
+        w.u8(self.b);
+        w.i8(self.exponent);
+    }
+}
+
+pub const LIGHTMAP_PAGE_SIZE: usize = 128;
+
+// dlightmappage_t
+// Lightmaps packed into 128x128 paletted pages
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightmapPage {
+    pub data: Vec<u8>, // LIGHTMAP_PAGE_SIZE * LIGHTMAP_PAGE_SIZE palette indices
+    pub palette: [[u8; 4]; 256],
+}
+
+impl LumpItem for LightmapPage {
+    fn size(_: &LumpContext) -> usize { LIGHTMAP_PAGE_SIZE * LIGHTMAP_PAGE_SIZE + 256 * 4 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        let data = r.bytes(LIGHTMAP_PAGE_SIZE * LIGHTMAP_PAGE_SIZE).to_vec();
+        let mut palette = [[0; 4]; 256];
+        for colour in palette.iter_mut() {
+            colour.copy_from_slice(r.bytes(4));
+        }
+        Self { data, palette }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.bytes(&self.data);
+        for colour in self.palette.iter() {
+            w.bytes(colour);
+        }
+    }
+}
+
+// dlightmappageinfo_t
+// Where a face's lightmap is in the pages, one per face
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LightmapPageInfo {
+    pub page: u8,
+    pub offset: [u8; 2],
+    pub average_colour: ColorRgbExp32,
+}
+
+impl LumpItem for LightmapPageInfo {
+    fn size(_: &LumpContext) -> usize { 8 }
+
+    fn read(r: &mut LumpReader) -> Self {
+        let page = r.u8();
+        let offset = [r.u8(), r.u8()];
+        r.skip(1); // padding
+        Self { page, offset, average_colour: ColorRgbExp32::read(r) }
+    }
+
+    fn write(&self, w: &mut LumpWriter) {
+        w.u8(self.page);
+        w.u8(self.offset[0]);
+        w.u8(self.offset[1]);
+        w.u8(0);
+        self.average_colour.write(w);
+    }
+}
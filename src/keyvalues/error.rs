@@ -12,7 +12,7 @@ impl std::error::Error for Error {}
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{} on line {}, column {}", match &self.kind {
-                ErrorKind::UnterminatedString => format!("Unterminated string"),
+                ErrorKind::UnterminatedString => "Unterminated string".to_string(),
                 ErrorKind::InvalidEscape(c) => format!("Invalid escape sequence '\\{}'", c),
                 ErrorKind::UnexpectedToken(t) => format!("Unexpected {}", match t {
                     TokenKind::LeftBrace => "{",
@@ -20,8 +20,8 @@ impl std::fmt::Display for Error {
                     TokenKind::Str(s) => s.as_str(),
                     TokenKind::EOF => "EOF",
                 }),
-                ErrorKind::NoMatchingRightBrace => format!("No matching }}"),
-                ErrorKind::UnexpectedEOF => format!("Unexpected End of File"),
+                ErrorKind::NoMatchingRightBrace => "No matching }".to_string(),
+                ErrorKind::UnexpectedEOF => "Unexpected End of File".to_string(),
             },
             self.line,
             self.column,
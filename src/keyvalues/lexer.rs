@@ -21,8 +21,9 @@ pub fn tokenize_file(path: &str) -> std::result::Result<Vec<Token>, Box<dyn std:
 // todo: This is an awful transcribed lexer from Java
 // Make it... rustier
 #[derive(Default)]
-struct Lexer<'a> {
-    source: &'a str,
+struct Lexer {
+    // chars, not bytes, so indexing is O(1) and non-ASCII text doesn't split a char
+    source: Vec<char>,
     tokens: Vec<Token>,
     start: usize,
     current: usize,
@@ -30,19 +31,20 @@ struct Lexer<'a> {
     column: usize,
 }
 
-impl<'a> Lexer<'a> {
-    fn from(string: &'a str) -> Self {
-        Self { source: string, line: 1, ..Default::default() }
+impl Lexer {
+    fn from(string: &str) -> Self {
+        Self { source: string.chars().collect(), line: 1, ..Default::default() }
     }
 
     // Not a reference, will take ownership and drop itself after being called
-    fn tokenize(mut self) -> Result<Vec<Token>>{
+    fn tokenize(mut self) -> Result<Vec<Token>> {
         while !self.is_at_end() {
             self.start = self.current;
             self.scan_token()?;
         }
         
-        self.tokens.push(Token { line: self.tokens[self.tokens.len()-1].line, column: 1, kind: TokenKind::EOF });
+        let line = self.tokens.last().map_or(self.line, |t| t.line);
+        self.tokens.push(Token { line, column: 1, kind: TokenKind::EOF });
 
         Ok(self.tokens)
     }
@@ -74,12 +76,12 @@ impl<'a> Lexer<'a> {
     fn advance(&mut self) -> char {
         self.current += 1;
         self.column += 1;
-        self.source.chars().nth(self.current-1).unwrap() // no reason why this unwrap should fail
+        self.source[self.current-1]
     }
 
     fn peek(&self) -> char {
         if !self.is_at_end() {
-            self.source.chars().nth(self.current).unwrap()
+            self.source[self.current]
         } else {
             '\0'
         }
@@ -95,7 +97,7 @@ impl<'a> Lexer<'a> {
             Err(self.error(ErrorKind::UnterminatedString))
         } else {
             self.advance(); // last "
-            let s = self.source[self.start+1..self.current-1].to_string();
+            let s: String = self.source[self.start+1..self.current-1].iter().collect();
             // todo: escape characters
             self.add_token(TokenKind::Str(s));
             Ok(())
@@ -107,7 +109,7 @@ impl<'a> Lexer<'a> {
             self.advance();
         }
 
-        let s = self.source[self.start..self.current].to_string();
+        let s: String = self.source[self.start..self.current].iter().collect();
         self.add_token(TokenKind::Str(s));
     }
 
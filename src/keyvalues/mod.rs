@@ -1,5 +1,5 @@
 // https://developer.valvesoftware.com/wiki/KeyValues
-// Basically a recursive HashMap<String, String>.
+// Basically a recursive list of (key, value) pairs, in file order.
 // Comes with a way to parse from files and strings
 // in the Source Engine KeyValues format.
 // (Example: .vmt files are Key-Value files)
@@ -17,19 +17,28 @@ pub use lexer::*;
 
 mod parser;
 
-use std::{
-    collections::HashMap,
-    str::FromStr,
-};
+use std::str::FromStr;
 
+// A key's value is either a plain string or another block of KeyValues
 #[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Subkey(KeyValues),
+}
+
+// Entries are kept in file order, and the same key can show up more than once
+// (like "solid" in a .vmf, or multiple "replace" blocks in .vmt patches)
+// Looking up a single key finds the last one, same as when this was a HashMap,
+// and two KeyValues are only equal if their entries are in the same order
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct KeyValues {
-    map: HashMap<String, String>,
-    subkeys: HashMap<String, KeyValues>,
+    entries: Vec<(String, Value)>,
 }
 
 impl KeyValues {
     // Parse a string into KeyValues
+    // Same as the FromStr impl, kept so KeyValues::from_str(s) works without importing the trait
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(string: &str) -> Result<Self> {
         Self::from_tokens(&tokenize(string)?)
     }
@@ -41,45 +50,133 @@ impl KeyValues {
     
     // Empty KeyValues struct
     pub fn new() -> Self {
-        Self { map: HashMap::new(), subkeys: HashMap::new() }
+        Self::default()
     }
 
     pub fn from_pair(key: &str, value: &str) -> Self {
-        let mut map: HashMap<String, String> = HashMap::new();
-        map.insert(key.to_string(), value.to_string());
-        
-        Self { map, subkeys: HashMap::new() }
+        let mut kv = Self::new();
+        kv.add_value(key, value);
+        kv
     }
 
-    pub fn get<T: FromStr>(&self, key: &String) -> Option<T> {
-        if let Ok(t) = self.map.get(key)?.parse::<T>() {
-            Some(t)
-        } else {
-            None
-        }
+    pub fn get<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get_str(key)?.parse::<T>().ok()
     }
 
     // Like get(), but returns the type's default value if it fails
     // (similar to GetInt, GetFloat, etc from the original KeyValues class)
-    pub fn get_or_default<T: FromStr+Default>(&self, key: &String) -> T {
-        self.get(key).unwrap_or(T::default())
+    pub fn get_or_default<T: FromStr+Default>(&self, key: &str) -> T {
+        self.get(key).unwrap_or_default()
     }
 
+    // The last string value with this key
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.entries.iter().rev().find_map(|(k, v)| match v {
+            Value::Str(s) if k == key => Some(s.as_str()),
+            _ => None,
+        })
+    }
+
+    // The last subkey with this name
     pub fn get_subkey(&self, key: &str) -> Option<&KeyValues> {
-        self.subkeys.get(key)
+        self.entries.iter().rev().find_map(|(k, v)| match v {
+            Value::Subkey(kv) if k == key => Some(kv),
+            _ => None,
+        })
+    }
+
+    // Every subkey with this name, in order
+    pub fn get_subkeys<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a KeyValues> + 'a {
+        self.entries.iter().filter_map(move |(k, v)| match v {
+            Value::Subkey(kv) if k == key => Some(kv),
+            _ => None,
+        })
+    }
+
+    // Follow a "/" separated path through subkeys, like "LightmappedGeneric/$basetexture"
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        let mut parts = path.split('/').filter(|p| !p.is_empty());
+        let mut current = self;
+        let mut part = parts.next()?;
+        loop {
+            match parts.next() {
+                None => return current.entries.iter().rev().find(|(k, _)| k == part).map(|(_, v)| v),
+                Some(next) => {
+                    current = current.get_subkey(part)?;
+                    part = next;
+                },
+            }
+        }
+    }
+
+    pub fn entries(&self) -> &[(String, Value)] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
     }
 
+    // Set a value, replacing the last value with the same key if there is one
     pub fn add_value(&mut self, key: &str, value: &str) {
-        self.map.insert(key.to_string(), value.to_string());
+        let found = self.entries.iter_mut().rev().find(|(k, v)| k == key && matches!(v, Value::Str(_)));
+        match found {
+            Some((_, v)) => *v = Value::Str(value.to_string()),
+            None => self.push_value(key, value),
+        }
     }
 
+    // Set a subkey, replacing the last subkey with the same name if there is one
     pub fn add_subkey(&mut self, key: &str, subkey: &KeyValues) {
-        self.subkeys.insert(key.to_string(), subkey.clone());
+        let found = self.entries.iter_mut().rev().find(|(k, v)| k == key && matches!(v, Value::Subkey(_)));
+        match found {
+            Some((_, v)) => *v = Value::Subkey(subkey.clone()),
+            None => self.push_subkey(key, subkey.clone()),
+        }
     }
 
+    // Append a value, even if the key is already there
+    pub fn push_value(&mut self, key: &str, value: &str) {
+        self.entries.push((key.to_string(), Value::Str(value.to_string())));
+    }
 
-    fn from_tokens(tokens: &Vec<Token>) -> Result<Self> {
+    // Append a subkey, even if there's already one with the same name
+    pub fn push_subkey(&mut self, key: &str, subkey: KeyValues) {
+        self.entries.push((key.to_string(), Value::Subkey(subkey)));
+    }
+
+    fn from_tokens(tokens: &[Token]) -> Result<Self> {
         parser::parse_keyvalues(tokens)
     }
+
+    fn write_indented(&self, f: &mut std::fmt::Formatter, depth: usize) -> std::fmt::Result {
+        let indent = "\t".repeat(depth);
+        for (key, value) in self.entries.iter() {
+            match value {
+                Value::Str(s) => writeln!(f, "{}\"{}\"\t\"{}\"", indent, key, s)?,
+                Value::Subkey(kv) => {
+                    writeln!(f, "{}\"{}\"", indent, key)?;
+                    writeln!(f, "{}{{", indent)?;
+                    kv.write_indented(f, depth + 1)?;
+                    writeln!(f, "{}}}", indent)?;
+                },
+            }
+        }
+        Ok(())
+    }
 }
 
+impl FromStr for KeyValues {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        KeyValues::from_str(s)
+    }
+}
+
+// Writes the usual tab-indented text format, with every key and value quoted
+impl std::fmt::Display for KeyValues {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
@@ -5,18 +5,18 @@ use super::{
 };
 
 // Construct KeyValues from a vec of tokens
-pub fn parse_keyvalues(tokens: &Vec<Token>) -> Result<KeyValues> {
+pub fn parse_keyvalues(tokens: &[Token]) -> Result<KeyValues> {
     Builder::from(tokens).build()
 }
 
 struct Builder<'a> {
-    tokens: &'a Vec<Token>,
+    tokens: &'a [Token],
     result: KeyValues,
     current: usize,
 }
 
 impl<'a> Builder<'a> {
-    fn from(tokens: &'a Vec<Token>) -> Self {
+    fn from(tokens: &'a [Token]) -> Self {
         Self { tokens, result: KeyValues::new(), current: 0 }
     }
 
@@ -30,7 +30,7 @@ impl<'a> Builder<'a> {
                     let t = self.advance();
                     match &t.kind {
                         TokenKind::Str(value) => {
-                            self.result.add_value(&key, &value);
+                            self.result.push_value(&key, value);
                         },
                         TokenKind::LeftBrace => {
                             self.parse_subkey(&key, t)?;
@@ -52,14 +52,14 @@ impl<'a> Builder<'a> {
 
     fn parse_subkey(&mut self, key: &str, start_brace: Token) -> Result<()> {
         if let Some(index) = self.find_matching_brace() {
-            self.result.add_subkey(
-                &key,
-                &Builder::from(&self.tokens[self.current..index].to_vec()).build()?
+            self.result.push_subkey(
+                key,
+                Builder::from(&self.tokens[self.current..index]).build()?
             );
             self.current = index+1;
             Ok(())
         } else {
-            return Err(unclosed_brace_err(start_brace));
+            Err(unclosed_brace_err(start_brace))
         }
     }
 
@@ -1,5 +1,6 @@
 pub mod bsp;
 pub mod keyvalues;
+pub mod stripper;
 pub mod vtf;
 
 pub mod vector;
@@ -8,6 +9,7 @@ pub use vector::Vector;
 
 // A plane dividing a map based on the BSP tree
 // ...or something. Not sure, really.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub struct Plane {
     pub normal: Vector,
     pub distance: f32,
@@ -17,12 +19,14 @@ pub struct Plane {
 // A pair of vertices
 // defined by the vertex's index into the .bsp Vertex lump
 // (LumpIndex::Vertices, or Lump #3)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub struct Edge {
     pub v: [u16 ; 2],
 }
 
 
 // A geometrical face, for rendering
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub struct Face {
     pub plane_number: u16,
     pub side: u8,
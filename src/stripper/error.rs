@@ -0,0 +1,28 @@
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    // Something other than what was expected, like a } outside a block
+    Syntax { line: usize, expected: &'static str },
+    // A /regex/ value that didn't compile
+    InvalidRegex { line: usize, pattern: String, reason: String },
+    IoError(std::io::Error),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Syntax { line, expected } => write!(f, "Expected {} on line {}", expected, line),
+            Error::InvalidRegex { line, pattern, reason } => write!(f, "Invalid regex /{}/ on line {}: {}", pattern, line, reason),
+            Error::IoError(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
@@ -0,0 +1,288 @@
+// Stripper:Source configs, for changing a map's entities without recompiling it
+// https://www.bailopan.net/stripper/
+//
+// filter:            (or remove:) every entity matching all of these is deleted
+// {
+// "classname" "/weapon_.*/"
+// }
+// add:               this entity is added
+// {
+// "classname" "info_target"
+// "targetname" "marker"
+// }
+// modify:            every entity matching "match" gets changed
+// {
+//     match:   { "targetname" "door" }
+//     replace: { "speed" "500" }       keys that are already there get the new value
+//     delete:  { "spawnflags" "/.*/" } matching keys are removed
+//     insert:  { "OnOpen" "relay,Trigger,,0,-1" }
+// }
+//
+// A section keyword applies to every block after it, until the next one (filter: is the default)
+// Values between slashes are regexes, see regex.rs. Everything is case-insensitive
+// Lines starting with ; # or // are comments
+
+mod error;
+pub mod regex;
+
+pub use error::*;
+pub use regex::Regex;
+
+use crate::bsp::Entity;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Exact(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            Pattern::Exact(s) => s.eq_ignore_ascii_case(value),
+            Pattern::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+// A key and what its value has to look like
+#[derive(Debug, Clone, PartialEq)]
+pub struct Property {
+    pub key: String,
+    pub value: Pattern,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Modify {
+    pub matches: Vec<Property>,
+    pub replace: Vec<(String, String)>,
+    pub delete: Vec<Property>,
+    pub insert: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Filter(Vec<Property>),
+    Add(Vec<(String, String)>),
+    Modify(Modify),
+}
+
+// What applying a config did
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Changes {
+    pub removed: usize,
+    pub added: usize,
+    pub modified: usize,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    pub actions: Vec<Action>,
+}
+
+impl Config {
+    pub fn from_file(path: &str) -> Result<Self> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    // Run every action in order, like Stripper does when the map loads
+    pub fn apply(&self, entities: &mut Vec<Entity>) -> Changes {
+        let mut changes = Changes::default();
+        for action in self.actions.iter() {
+            match action {
+                Action::Filter(properties) => {
+                    let before = entities.len();
+                    entities.retain(|e| !matches_all(e, properties));
+                    changes.removed += before - entities.len();
+                },
+                Action::Add(pairs) => {
+                    let mut entity = Entity::new();
+                    for (key, value) in pairs.iter() {
+                        entity.add(key, value);
+                    }
+                    entities.push(entity);
+                    changes.added += 1;
+                },
+                Action::Modify(modify) => {
+                    for entity in entities.iter_mut().filter(|e| matches_all(e, &modify.matches)) {
+                        for (key, value) in modify.replace.iter() {
+                            for (k, v) in entity.properties.iter_mut() {
+                                if k.eq_ignore_ascii_case(key) {
+                                    *v = value.clone();
+                                }
+                            }
+                        }
+                        entity.properties.retain(|(k, v)| {
+                            !modify.delete.iter().any(|p| p.key.eq_ignore_ascii_case(k) && p.value.matches(v))
+                        });
+                        for (key, value) in modify.insert.iter() {
+                            entity.add(key, value);
+                        }
+                        changes.modified += 1;
+                    }
+                },
+            }
+        }
+        changes
+    }
+}
+
+// Every property has to match at least one value of its key
+fn matches_all(entity: &Entity, properties: &[Property]) -> bool {
+    properties.iter().all(|p| entity.get_all(&p.key).any(|v| p.value.matches(v)))
+}
+
+impl std::str::FromStr for Config {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        let mut tokens = Tokens { chars: source.chars().peekable(), line: 1 };
+        let mut actions = Vec::new();
+        let mut section = "filter:".to_string();
+
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::Word(word) => {
+                    section = word.to_ascii_lowercase();
+                    if !["filter:", "remove:", "add:", "modify:"].contains(&section.as_str()) {
+                        return Err(tokens.error("filter:, add: or modify:"));
+                    }
+                },
+                Token::Open => actions.push(match section.as_str() {
+                    "add:" => Action::Add(tokens.pairs()?),
+                    "modify:" => Action::Modify(tokens.modify()?),
+                    _ => Action::Filter(tokens.properties()?),
+                }),
+                _ => return Err(tokens.error("a section or {")),
+            }
+        }
+        Ok(Self { actions })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Str(String),
+    // Unquoted, only section names
+    Word(String),
+}
+
+struct Tokens<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+}
+
+impl Tokens<'_> {
+    fn error(&self, expected: &'static str) -> Error {
+        Error::Syntax { line: self.line, expected }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        loop {
+            match *self.chars.peek()? {
+                '\n' => {
+                    self.line += 1;
+                    self.chars.next();
+                },
+                c if c.is_whitespace() => { self.chars.next(); },
+                ';' | '#' => self.skip_line(),
+                '/' => {
+                    self.chars.next();
+                    if self.chars.peek() == Some(&'/') {
+                        self.skip_line();
+                    } else {
+                        return Some(Token::Word("/".to_string()));
+                    }
+                },
+                _ => break,
+            }
+        }
+
+        match self.chars.next()? {
+            '{' => Some(Token::Open),
+            '}' => Some(Token::Close),
+            '"' => {
+                let mut s = String::new();
+                for c in &mut self.chars {
+                    match c {
+                        '"' => break,
+                        '\n' => { self.line += 1; s.push(c) },
+                        _ => s.push(c),
+                    }
+                }
+                Some(Token::Str(s))
+            },
+            c => {
+                let mut s = c.to_string();
+                while let Some(&c) = self.chars.peek() {
+                    if c.is_whitespace() || c == '"' || c == '{' || c == '}' {
+                        break;
+                    }
+                    s.push(c);
+                    self.chars.next();
+                }
+                Some(Token::Word(s))
+            },
+        }
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if c == '\n' {
+                break;
+            }
+            self.chars.next();
+        }
+    }
+
+    // "key" "value" pairs up to the closing }
+    fn pairs(&mut self) -> Result<Vec<(String, String)>> {
+        let mut pairs = Vec::new();
+        loop {
+            match self.next() {
+                Some(Token::Close) => return Ok(pairs),
+                Some(Token::Str(key)) => match self.next() {
+                    Some(Token::Str(value)) => pairs.push((key, value)),
+                    _ => return Err(self.error("a value")),
+                },
+                _ => return Err(self.error("a key or }")),
+            }
+        }
+    }
+
+    // Like pairs, with /regex/ values compiled
+    fn properties(&mut self) -> Result<Vec<Property>> {
+        let line = self.line;
+        self.pairs()?.into_iter().map(|(key, value)| {
+            let value = match value.strip_prefix('/').and_then(|v| v.strip_suffix('/')) {
+                Some(pattern) => Pattern::Regex(Regex::new(pattern)
+                    .map_err(|reason| Error::InvalidRegex { line, pattern: pattern.to_string(), reason })?),
+                None => Pattern::Exact(value),
+            };
+            Ok(Property { key, value })
+        }).collect()
+    }
+
+    fn modify(&mut self) -> Result<Modify> {
+        let mut modify = Modify::default();
+        loop {
+            let section = match self.next() {
+                Some(Token::Close) => return Ok(modify),
+                Some(Token::Word(word)) => word.to_ascii_lowercase(),
+                _ => return Err(self.error("match:, replace:, delete:, insert: or }")),
+            };
+            if self.next() != Some(Token::Open) {
+                return Err(self.error("{"));
+            }
+            match section.as_str() {
+                "match:" => modify.matches.extend(self.properties()?),
+                "replace:" => modify.replace.extend(self.pairs()?),
+                "delete:" => modify.delete.extend(self.properties()?),
+                "insert:" => modify.insert.extend(self.pairs()?),
+                _ => return Err(self.error("match:, replace:, delete: or insert:")),
+            }
+        }
+    }
+}
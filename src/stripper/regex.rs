@@ -0,0 +1,442 @@
+// Just enough of PCRE for Stripper configs
+// Supports . [classes] [^negated] \d \w \s (and uppercase) \b ^ $ (groups) (?:groups) a|b * + ? {n,m} and lazy *? +? ??
+// Matching is case-insensitive and unanchored, like Stripper's PCRE_CASELESS
+// Patterns are compiled to a little program and run on every position at once (a Pike VM),
+// so matching takes time proportional to text length * pattern size and can't blow the stack
+// on long values or patterns like (a*)*b, which a backtracker would
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Char(char),
+    Any,
+    Class { items: Vec<ClassItem>, negated: bool },
+    Start,
+    End,
+    WordBoundary(bool),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    // Lazy repeats are parsed but treated like greedy ones,
+    // since only whether there's a match matters, not which one
+    Repeat { node: Box<Node>, min: usize, max: Option<usize> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Inst {
+    Char(char),
+    Any,
+    Class { items: Vec<ClassItem>, negated: bool },
+    Start,
+    End,
+    WordBoundary(bool),
+    // Carry on at both
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    // \D \W \S inside a class
+    Not(Vec<(char, char)>),
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')];
+const SPACE: &[(char, char)] = &[(' ', ' '), ('\t', '\r')];
+
+// Limits so a silly pattern gets an error instead of eating all the memory or stack
+// a{100}{100} style counts multiply out, and every ( is a level of recursion in the parser
+const MAX_PROGRAM_SIZE: usize = 10_000;
+const MAX_NESTING: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regex {
+    pattern: String,
+    program: Vec<Inst>,
+}
+
+impl Regex {
+    // The error is a description of what's wrong with the pattern
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let mut parser = Parser { chars: pattern.chars().collect(), pos: 0, depth: 0 };
+        let node = parser.alternation()?;
+        if parser.pos < parser.chars.len() {
+            return Err("unmatched )".to_string());
+        }
+        let mut program = Vec::new();
+        compile(&node, &mut program)?;
+        program.push(Inst::Match);
+        Ok(Self { pattern: pattern.to_string(), program })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    // Does the pattern match anywhere in text
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let mut vm = Vm {
+            program: &self.program,
+            text: &text,
+            seen: vec![0; self.program.len()],
+            stack: Vec::new(),
+        };
+
+        // Threads waiting to consume the character at pos, and the ones for pos + 1
+        let mut current = Vec::new();
+        let mut next = Vec::new();
+        for pos in 0..=text.len() {
+            // Unanchored, so a new match can start anywhere
+            if vm.add(&mut current, 0, pos) {
+                return true;
+            }
+            let c = match text.get(pos) {
+                Some(&c) => c,
+                None => break,
+            };
+            for &pc in current.iter() {
+                if vm.consumes(pc, c) && vm.add(&mut next, pc + 1, pos + 1) {
+                    return true;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+        false
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    // How many groups deep we are
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn alternation(&mut self) -> Result<Node, String> {
+        let mut options = vec![self.concat()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            options.push(self.concat()?);
+        }
+        Ok(if options.len() == 1 { options.pop().unwrap() } else { Node::Alternate(options) })
+    }
+
+    fn concat(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.repeat(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn repeat(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => match self.counts() {
+                Some(counts) => counts,
+                None => return Ok(atom),
+            },
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        if matches!(atom, Node::Start | Node::End | Node::WordBoundary(_)) {
+            return Err("nothing to repeat".to_string());
+        }
+        // Lazy
+        if self.peek() == Some('?') {
+            self.pos += 1;
+        }
+        if matches!(self.peek(), Some('*') | Some('+') | Some('?')) {
+            return Err("nothing to repeat".to_string());
+        }
+        Ok(Node::Repeat { node: Box::new(atom), min, max })
+    }
+
+    // {n}, {n,} or {n,m}, leaving pos on the } when it's valid
+    // Anything else is a literal {, like in PCRE
+    fn counts(&mut self) -> Option<(usize, Option<usize>)> {
+        let close = (self.pos..self.chars.len()).find(|&i| self.chars[i] == '}')?;
+        let inner: String = self.chars[self.pos + 1..close].iter().collect();
+        let (min, max) = match inner.split_once(',') {
+            Some((min, "")) => (min.parse().ok()?, None),
+            Some((min, max)) => (min.parse().ok()?, Some(max.parse().ok()?)),
+            None => {
+                let n = inner.parse().ok()?;
+                (n, Some(n))
+            },
+        };
+        if max.is_some_and(|max| max < min) {
+            return None;
+        }
+        self.pos = close;
+        Some((min, max))
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some('(') => {
+                if self.chars.get(self.pos..self.pos + 2) == Some(&['?', ':']) {
+                    self.pos += 2;
+                }
+                if self.depth == MAX_NESTING {
+                    return Err("groups are nested too deep".to_string());
+                }
+                self.depth += 1;
+                let node = self.alternation()?;
+                self.depth -= 1;
+                if self.next() != Some(')') {
+                    return Err("missing )".to_string());
+                }
+                Ok(node)
+            },
+            Some('[') => self.class(),
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Start),
+            Some('$') => Ok(Node::End),
+            Some('\\') => match self.next() {
+                Some('b') => Ok(Node::WordBoundary(true)),
+                Some('B') => Ok(Node::WordBoundary(false)),
+                Some(c) => Ok(match escape_class(c) {
+                    Some((ranges, negated)) => Node::Class { items: ranges.iter().map(|&(a, b)| ClassItem::Range(a, b)).collect(), negated },
+                    None => Node::Char(escape_char(c)),
+                }),
+                None => Err("trailing \\".to_string()),
+            },
+            Some(c @ '*') | Some(c @ '+') | Some(c @ '?') => Err(format!("nothing to repeat before {}", c)),
+            Some(c) => Ok(Node::Char(c)),
+            None => Err("unexpected end".to_string()),
+        }
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let c = match self.next() {
+                Some(']') if !first => break,
+                Some(c) => c,
+                None => return Err("missing ]".to_string()),
+            };
+            first = false;
+            let start = match c {
+                '\\' => {
+                    let e = self.next().ok_or("trailing \\")?;
+                    match escape_class(e) {
+                        Some((ranges, false)) => {
+                            items.extend(ranges.iter().map(|&(a, b)| ClassItem::Range(a, b)));
+                            continue;
+                        },
+                        Some((ranges, true)) => {
+                            items.push(ClassItem::Not(ranges.to_vec()));
+                            continue;
+                        },
+                        None => escape_char(e),
+                    }
+                },
+                c => c,
+            };
+            // a-z, but a trailing - is literal
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                let end = match self.next() {
+                    Some('\\') => escape_char(self.next().ok_or("trailing \\")?),
+                    Some(c) => c,
+                    None => return Err("missing ]".to_string()),
+                };
+                if end < start {
+                    return Err(format!("bad range {}-{}", start, end));
+                }
+                items.push(ClassItem::Range(start, end));
+            } else {
+                items.push(ClassItem::Range(start, start));
+            }
+        }
+        Ok(Node::Class { items, negated })
+    }
+}
+
+// \d \w \s and their negations
+fn escape_class(c: char) -> Option<(&'static [(char, char)], bool)> {
+    match c {
+        'd' => Some((DIGIT, false)),
+        'D' => Some((DIGIT, true)),
+        'w' => Some((WORD, false)),
+        'W' => Some((WORD, true)),
+        's' => Some((SPACE, false)),
+        'S' => Some((SPACE, true)),
+        _ => None,
+    }
+}
+
+fn escape_char(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        c => c,
+    }
+}
+
+fn in_ranges(ranges: &[(char, char)], c: char) -> bool {
+    let (lower, upper) = (c.to_ascii_lowercase(), c.to_ascii_uppercase());
+    ranges.iter().any(|&(a, b)| (a..=b).contains(&c) || (a..=b).contains(&lower) || (a..=b).contains(&upper))
+}
+
+fn is_word(c: Option<&char>) -> bool {
+    c.is_some_and(|&c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Append the instructions for node
+fn compile(node: &Node, program: &mut Vec<Inst>) -> Result<(), String> {
+    if program.len() > MAX_PROGRAM_SIZE {
+        return Err("pattern is too big".to_string());
+    }
+    match node {
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class { items, negated } => program.push(Inst::Class { items: items.clone(), negated: *negated }),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::WordBoundary(expected) => program.push(Inst::WordBoundary(*expected)),
+        Node::Concat(nodes) => {
+            for node in nodes.iter() {
+                compile(node, program)?;
+            }
+        },
+        Node::Alternate(options) => {
+            // Split to each option in turn, every option jumps past the rest when it's done
+            let mut jumps = Vec::new();
+            for (i, option) in options.iter().enumerate() {
+                let split = program.len();
+                if i + 1 < options.len() {
+                    program.push(Inst::Split(split + 1, 0));
+                }
+                compile(option, program)?;
+                if i + 1 < options.len() {
+                    jumps.push(program.len());
+                    program.push(Inst::Jump(0));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+            }
+            for jump in jumps {
+                program[jump] = Inst::Jump(program.len());
+            }
+        },
+        Node::Repeat { node, min, max } => {
+            // The required copies one after the other, then either a loop or that many optional copies
+            for _ in 0..*min {
+                compile(node, program)?;
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program)?;
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                },
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(program.len() + 1, 0));
+                        compile(node, program)?;
+                    }
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, program.len());
+                    }
+                },
+            }
+        },
+    }
+    Ok(())
+}
+
+struct Vm<'a> {
+    program: &'a [Inst],
+    text: &'a [char],
+    // Which instructions were already added for the current position, as pos + 1
+    seen: Vec<usize>,
+    stack: Vec<usize>,
+}
+
+impl Vm<'_> {
+    // Follow splits, jumps and assertions from pc at pos, adding every instruction
+    // that consumes a character to threads
+    // Returns true if a match was reached
+    fn add(&mut self, threads: &mut Vec<usize>, pc: usize, pos: usize) -> bool {
+        let c = self.text.get(pos);
+        self.stack.push(pc);
+        while let Some(pc) = self.stack.pop() {
+            if self.seen[pc] == pos + 1 {
+                continue;
+            }
+            self.seen[pc] = pos + 1;
+            match self.program[pc] {
+                Inst::Split(a, b) => {
+                    // b first, so a comes out first
+                    self.stack.push(b);
+                    self.stack.push(a);
+                },
+                Inst::Jump(target) => self.stack.push(target),
+                Inst::Start => if pos == 0 {
+                    self.stack.push(pc + 1);
+                },
+                Inst::End => if pos == self.text.len() {
+                    self.stack.push(pc + 1);
+                },
+                Inst::WordBoundary(expected) => {
+                    let before = if pos == 0 { None } else { self.text.get(pos - 1) };
+                    if (is_word(before) != is_word(c)) == expected {
+                        self.stack.push(pc + 1);
+                    }
+                },
+                Inst::Match => {
+                    self.stack.clear();
+                    return true;
+                },
+                Inst::Char(_) | Inst::Any | Inst::Class { .. } => threads.push(pc),
+            }
+        }
+        false
+    }
+
+    fn consumes(&self, pc: usize, c: char) -> bool {
+        match &self.program[pc] {
+            Inst::Char(expected) => c.eq_ignore_ascii_case(expected),
+            Inst::Any => c != '\n',
+            Inst::Class { items, negated } => {
+                let found = items.iter().any(|item| match item {
+                    ClassItem::Range(a, b) => in_ranges(&[(*a, *b)], c),
+                    ClassItem::Not(ranges) => !in_ranges(ranges, c),
+                });
+                found != *negated
+            },
+            _ => false,
+        }
+    }
+}
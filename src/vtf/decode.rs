@@ -0,0 +1,176 @@
+// Converting image data to plain 8-bit RGBA
+
+use super::error::Error;
+use super::header::ImageFormat;
+
+pub fn decode_rgba8(format: ImageFormat, data: &[u8], width: usize, height: usize) -> Result<Vec<u8>, Error> {
+    if data.len() < format.image_size(width, height) {
+        return Err(Error::UnexpectedEof);
+    }
+    if format.is_compressed() {
+        return Ok(decode_dxt(format, data, width, height));
+    }
+
+    let size = format.block_size();
+    let mut out = Vec::with_capacity(width * height * 4);
+    for p in data.chunks_exact(size.max(1)).take(width * height) {
+        let rgba = pixel(format, p)?;
+        out.extend_from_slice(&rgba);
+    }
+    Ok(out)
+}
+
+fn pixel(format: ImageFormat, p: &[u8]) -> Result<[u8; 4], Error> {
+    use ImageFormat::*;
+    let u16_at = |i: usize| u16::from_le_bytes([p[i], p[i + 1]]);
+    Ok(match format {
+        Rgba8888 | Uvwq8888 | Uvlx8888 => [p[0], p[1], p[2], p[3]],
+        Abgr8888 => [p[3], p[2], p[1], p[0]],
+        Argb8888 => [p[1], p[2], p[3], p[0]],
+        Bgra8888 => [p[2], p[1], p[0], p[3]],
+        Bgrx8888 => [p[2], p[1], p[0], 255],
+        Rgb888 => [p[0], p[1], p[2], 255],
+        Bgr888 => [p[2], p[1], p[0], 255],
+        // Pure blue means transparent
+        Rgb888BlueScreen => [p[0], p[1], p[2], if p[0..3] == [0, 0, 255] { 0 } else { 255 }],
+        Bgr888BlueScreen => [p[2], p[1], p[0], if p[0..3] == [255, 0, 0] { 0 } else { 255 }],
+        I8 => [p[0], p[0], p[0], 255],
+        Ia88 => [p[0], p[0], p[0], p[1]],
+        A8 => [0, 0, 0, p[0]],
+        Uv88 => [p[0], p[1], 0, 255],
+        Rgb565 => {
+            let [r, g, b] = unpack_565(u16_at(0));
+            [b, g, r, 255]
+        },
+        Bgr565 => {
+            let [r, g, b] = unpack_565(u16_at(0));
+            [r, g, b, 255]
+        },
+        Bgrx5551 | Bgra5551 => {
+            let v = u16_at(0);
+            let a = if format == Bgra5551 && v & 0x8000 == 0 { 0 } else { 255 };
+            [scale_bits((v >> 10) & 31, 5), scale_bits((v >> 5) & 31, 5), scale_bits(v & 31, 5), a]
+        },
+        Bgra4444 => {
+            let v = u16_at(0);
+            [scale_bits((v >> 8) & 15, 4), scale_bits((v >> 4) & 15, 4), scale_bits(v & 15, 4), scale_bits(v >> 12, 4)]
+        },
+        Rgba16161616 => [p[1], p[3], p[5], p[7]],
+        Rgba16161616F => {
+            let c = |i: usize| (half_to_f32(u16_at(i)).clamp(0.0, 1.0) * 255.0).round() as u8;
+            [c(0), c(2), c(4), c(6)]
+        },
+        None | P8 | Dxt1 | Dxt1OneBitAlpha | Dxt3 | Dxt5 => return Err(Error::UnsupportedFormat(format)),
+    })
+}
+
+fn scale_bits(v: u16, bits: u32) -> u8 {
+    (v as u32 * 255 / ((1 << bits) - 1)) as u8
+}
+
+// 5 bits red (high), 6 green, 5 blue (low)
+fn unpack_565(v: u16) -> [u8; 3] {
+    [scale_bits(v >> 11, 5), scale_bits((v >> 5) & 63, 6), scale_bits(v & 31, 5)]
+}
+
+fn half_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((h >> 10) & 31) as i32;
+    let mantissa = (h & 1023) as f32;
+    sign * match exponent {
+        0 => mantissa / 1024.0 * 2f32.powi(-14),
+        31 => if mantissa == 0.0 { f32::INFINITY } else { f32::NAN },
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+fn decode_dxt(format: ImageFormat, data: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut out = vec![0; width * height * 4];
+    let blocks_wide = width.div_ceil(4).max(1);
+    let block_size = format.block_size();
+
+    for (i, block) in data.chunks_exact(block_size).enumerate().take(blocks_wide * height.div_ceil(4).max(1)) {
+        let (bx, by) = (i % blocks_wide * 4, i / blocks_wide * 4);
+        let (alpha, colour) = if block_size == 16 { block.split_at(8) } else { block.split_at(0) };
+
+        let alphas = match format {
+            ImageFormat::Dxt3 => dxt3_alpha(alpha),
+            ImageFormat::Dxt5 => dxt5_alpha(alpha),
+            _ => [255; 16],
+        };
+        // DXT3/5 colour blocks always use 4 colours, DXT1 can have 1-bit alpha
+        let colours = dxt_colours(colour, block_size == 8);
+
+        for y in 0..4 {
+            for x in 0..4 {
+                let (px, py) = (bx + x, by + y);
+                if px >= width || py >= height {
+                    continue;
+                }
+                let n = y * 4 + x;
+                let index = (colour[4 + y] >> (x * 2)) & 3;
+                let mut rgba = colours[index as usize];
+                rgba[3] = rgba[3].min(alphas[n]);
+                out[(py * width + px) * 4..][..4].copy_from_slice(&rgba);
+            }
+        }
+    }
+    out
+}
+
+fn dxt_colours(block: &[u8], allow_transparent: bool) -> [[u8; 4]; 4] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let [r0, g0, b0] = unpack_565(c0);
+    let [r1, g1, b1] = unpack_565(c1);
+    let mix = |a: u8, b: u8, wa: u32, wb: u32| ((a as u32 * wa + b as u32 * wb) / (wa + wb)) as u8;
+
+    if c0 > c1 || !allow_transparent {
+        [
+            [r0, g0, b0, 255],
+            [r1, g1, b1, 255],
+            [mix(r0, r1, 2, 1), mix(g0, g1, 2, 1), mix(b0, b1, 2, 1), 255],
+            [mix(r0, r1, 1, 2), mix(g0, g1, 1, 2), mix(b0, b1, 1, 2), 255],
+        ]
+    } else {
+        [
+            [r0, g0, b0, 255],
+            [r1, g1, b1, 255],
+            [mix(r0, r1, 1, 1), mix(g0, g1, 1, 1), mix(b0, b1, 1, 1), 255],
+            [0, 0, 0, 0],
+        ]
+    }
+}
+
+// 4 bits of alpha per pixel
+fn dxt3_alpha(block: &[u8]) -> [u8; 16] {
+    let mut alphas = [0; 16];
+    for (i, a) in alphas.iter_mut().enumerate() {
+        let nibble = (block[i / 2] >> ((i % 2) * 4)) & 15;
+        *a = nibble * 17;
+    }
+    alphas
+}
+
+// Two endpoint alphas and 3-bit indices between them
+fn dxt5_alpha(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let mut bits = 0u64;
+    for (i, b) in block[2..8].iter().enumerate() {
+        bits |= (*b as u64) << (8 * i);
+    }
+
+    let mut alphas = [0; 16];
+    for (i, a) in alphas.iter_mut().enumerate() {
+        let index = ((bits >> (3 * i)) & 7) as u32;
+        *a = match index {
+            0 => a0,
+            1 => a1,
+            _ if a0 > a1 => ((8 - index) * a0 + (index - 1) * a1) / 7,
+            6 => 0,
+            7 => 255,
+            _ => ((6 - index) * a0 + (index - 1) * a1) / 5,
+        } as u8;
+    }
+    alphas
+}
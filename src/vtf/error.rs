@@ -1,4 +1,34 @@
 #[derive(Debug)]
 pub enum Error {
+    InvalidSignature,
+    UnknownFormat(i32),
+    UnsupportedFormat(super::ImageFormat),
+    // More mipmaps than the size allows
+    TooManyMipmaps(u8),
+    // Asked for a mipmap, frame, face or slice that isn't in the file
+    NoSuchImage,
+    UnexpectedEof,
+    IoError(std::io::Error),
+}
 
-}
\ No newline at end of file
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidSignature => write!(f, "Not a VTF file"),
+            Error::UnknownFormat(n) => write!(f, "Unknown image format {}", n),
+            Error::UnsupportedFormat(format) => write!(f, "Can't decode {:?} images", format),
+            Error::TooManyMipmaps(n) => write!(f, "Too many mipmaps ({}) for the image size", n),
+            Error::NoSuchImage => write!(f, "No such image in the file"),
+            Error::UnexpectedEof => write!(f, "Unexpected end of file"),
+            Error::IoError(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
@@ -1,7 +1,11 @@
 use super::error::Error;
 
-#[derive(Debug)]
-enum Flags {
+use std::convert::TryInto;
+
+// Bits of Header.flags
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u32)]
+pub enum Flags {
     PointSample = 0x00000001,
     TriLinear   = 0x00000002,
     ClampS      = 0x00000004,
@@ -37,8 +41,8 @@ enum Flags {
     Unused6     = 0x80000000,
 }
 
-#[derive(Debug)]
-enum ImageFormat {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
     None = -1,
     Rgba8888 = 0,
     Abgr8888,
@@ -69,29 +73,144 @@ enum ImageFormat {
     Uvlx8888,
 }
 
-#[derive(Debug)]
+impl ImageFormat {
+    pub fn from_i32(n: i32) -> Option<Self> {
+        use ImageFormat::*;
+        const ALL: [ImageFormat; 27] = [
+            Rgba8888, Abgr8888, Rgb888, Bgr888, Rgb565, I8, Ia88, P8, A8,
+            Rgb888BlueScreen, Bgr888BlueScreen, Argb8888, Bgra8888, Dxt1, Dxt3, Dxt5,
+            Bgrx8888, Bgr565, Bgrx5551, Bgra4444, Dxt1OneBitAlpha, Bgra5551, Uv88,
+            Uvwq8888, Rgba16161616F, Rgba16161616, Uvlx8888,
+        ];
+        match n {
+            -1 => Some(None),
+            0..=26 => Some(ALL[n as usize]),
+            _ => Option::None,
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        matches!(self, ImageFormat::Dxt1 | ImageFormat::Dxt1OneBitAlpha | ImageFormat::Dxt3 | ImageFormat::Dxt5)
+    }
+
+    // Bytes per pixel, or per 4x4 block for DXT
+    pub fn block_size(&self) -> usize {
+        use ImageFormat::*;
+        match self {
+            None => 0,
+            I8 | P8 | A8 => 1,
+            Rgb565 | Ia88 | Bgr565 | Bgrx5551 | Bgra4444 | Bgra5551 | Uv88 => 2,
+            Rgb888 | Bgr888 | Rgb888BlueScreen | Bgr888BlueScreen => 3,
+            Rgba8888 | Abgr8888 | Argb8888 | Bgra8888 | Bgrx8888 | Uvwq8888 | Uvlx8888 => 4,
+            Rgba16161616F | Rgba16161616 => 8,
+            Dxt1 | Dxt1OneBitAlpha => 8,
+            Dxt3 | Dxt5 => 16,
+        }
+    }
+
+    // Size of a width x height image in this format
+    pub fn image_size(&self, width: usize, height: usize) -> usize {
+        if self.is_compressed() {
+            width.div_ceil(4).max(1) * height.div_ceil(4).max(1) * self.block_size()
+        } else {
+            width * height * self.block_size()
+        }
+    }
+}
+
+// https://developer.valvesoftware.com/wiki/Valve_Texture_Format#VTF_header
+#[derive(Debug, Clone, PartialEq)]
 pub struct Header {
-    signature: [char; 4],
-    version: [u32; 2],
-    header_size: u32,
-    width: u16,
-    height: u16,
-    flags: Flags,
-    frames: u16,
-    first_frame: u16,
-    reflectivity: [f32; 3],
-    bumpmap_scale: f32,
-    high_res_format: ImageFormat,
-    mipmaps: u8,
-    low_res_format: ImageFormat,
-    low_res_width: u8,
-    low_res_height: u8,
-    depth: u16,
-    resources: u32,
+    pub signature: [char; 4],
+    pub version: [u32; 2],
+    pub header_size: u32,
+    pub width: u16,
+    pub height: u16,
+    pub flags: u32,
+    pub frames: u16,
+    pub first_frame: u16,
+    pub reflectivity: [f32; 3],
+    pub bumpmap_scale: f32,
+    pub high_res_format: ImageFormat,
+    pub mipmaps: u8,
+    pub low_res_format: ImageFormat,
+    pub low_res_width: u8,
+    pub low_res_height: u8,
+    pub depth: u16, // 7.2+
+    pub resources: u32, // 7.3+
 }
 
+pub const VTF_SIGNATURE: [u8; 4] = *b"VTF\0";
+
 impl Header {
-    pub fn from_bytes(bytes: &Vec<u8>) -> Result<Self, Error> {
-        
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        // The 7.0 header is 63 bytes, everything after that depends on the version
+        if bytes.len() < 63 {
+            return Err(Error::UnexpectedEof);
+        }
+        if bytes[0..4] != VTF_SIGNATURE {
+            return Err(Error::InvalidSignature);
+        }
+
+        let u16_at = |i: usize| u16::from_le_bytes(bytes[i..i+2].try_into().unwrap());
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i+4].try_into().unwrap());
+        let f32_at = |i: usize| f32::from_le_bytes(bytes[i..i+4].try_into().unwrap());
+        let format_at = |i: usize| {
+            let n = u32_at(i) as i32;
+            ImageFormat::from_i32(n).ok_or(Error::UnknownFormat(n))
+        };
+
+        let version = [u32_at(4), u32_at(8)];
+        let depth = if version >= [7, 2] && bytes.len() >= 65 { u16_at(63) } else { 1 };
+        let resources = if version >= [7, 3] && bytes.len() >= 72 { u32_at(68) } else { 0 };
+
+        // Every mipmap halves the size, down to 1x1x1
+        let largest = u16_at(16).max(u16_at(18)).max(depth).max(1);
+        let max_mipmaps = 16 - largest.leading_zeros() as u8;
+        if bytes[56] > max_mipmaps {
+            return Err(Error::TooManyMipmaps(bytes[56]));
+        }
+
+        Ok(Self {
+            signature: ['V', 'T', 'F', '\0'],
+            version,
+            header_size: u32_at(12),
+            width: u16_at(16),
+            height: u16_at(18),
+            flags: u32_at(20),
+            frames: u16_at(24),
+            first_frame: u16_at(26),
+            reflectivity: [f32_at(32), f32_at(36), f32_at(40)],
+            bumpmap_scale: f32_at(48),
+            high_res_format: format_at(52)?,
+            mipmaps: bytes[56],
+            low_res_format: format_at(57)?,
+            low_res_width: bytes[61],
+            low_res_height: bytes[62],
+            depth: depth.max(1),
+            resources,
+        })
+    }
+
+    pub fn has_flag(&self, flag: Flags) -> bool {
+        self.flags & flag as u32 != 0
     }
-}
\ No newline at end of file
+
+    // Cube maps have 6 faces, plus a sphere map before 7.5
+    pub fn faces(&self) -> usize {
+        if !self.has_flag(Flags::EnvMap) {
+            1
+        } else if self.version < [7, 5] && self.first_frame != 0xFFFF {
+            7
+        } else {
+            6
+        }
+    }
+
+    // Size of mipmap level `mip` (0 is the full size image)
+    pub fn mip_size(&self, mip: usize) -> (usize, usize, usize) {
+        let shift = mip.try_into().unwrap_or(u32::MAX);
+        let shrink = |n: u16| (n as usize).checked_shr(shift).unwrap_or(0).max(1);
+        (shrink(self.width), shrink(self.height), shrink(self.depth))
+    }
+}
@@ -2,6 +2,7 @@
 // The proprietary image format for the Source Engine
 
 use std::error::Error as stdError;
+use std::convert::TryInto;
 
 mod error;
 pub use error::*;
@@ -9,18 +10,89 @@ pub use error::*;
 mod header;
 pub use header::*;
 
+mod decode;
+pub use decode::*;
+
+// Resource ids (7.3+), the first 3 bytes of each resource entry
+const RESOURCE_LOW_RES_IMAGE: [u8; 3] = [0x01, 0, 0];
+const RESOURCE_IMAGE: [u8; 3] = [0x30, 0, 0];
+
 #[derive(Debug)]
 pub struct Vtf {
     pub header: Header,
+    data: Vec<u8>,
 }
 
 impl Vtf {
     pub fn from_file(path: &str) -> Result<Self, Box<dyn stdError>> {
-        
+        Ok(Self::from_bytes(&std::fs::read(path)?)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let header = Header::from_bytes(bytes)?;
+        Ok(Self { header, data: bytes.to_vec() })
     }
 
-    pub fn from_bytes(bytes: &Vec<u8>) -> Result<Self, Error> {
-        let header = Header::from_bytes(&bytes)?;
-        Ok(Self{header})
+    // Where the full-size image data starts
+    // Before 7.3 it's right after the low-res thumbnail, after that it's listed as a resource
+    fn image_data_offset(&self) -> usize {
+        let h = &self.header;
+        if h.version >= [7, 3] {
+            let mut low_res_end = h.header_size as usize;
+            for i in 0..h.resources as usize {
+                let entry = 80 + i * 8;
+                let entry = match self.data.get(entry..entry + 8) {
+                    Some(entry) => entry,
+                    None => break,
+                };
+                let offset = u32::from_le_bytes(entry[4..8].try_into().unwrap()) as usize;
+                if entry[0..3] == RESOURCE_IMAGE {
+                    return offset;
+                } else if entry[0..3] == RESOURCE_LOW_RES_IMAGE {
+                    low_res_end = offset + self.low_res_size();
+                }
+            }
+            low_res_end
+        } else {
+            h.header_size as usize + self.low_res_size()
+        }
     }
-}
\ No newline at end of file
+
+    fn low_res_size(&self) -> usize {
+        let h = &self.header;
+        h.low_res_format.image_size(h.low_res_width as usize, h.low_res_height as usize)
+    }
+
+    // The raw bytes of one image, in header.high_res_format
+    // Mipmap 0 is the full size image
+    pub fn image_data(&self, mip: usize, frame: usize, face: usize, slice: usize) -> Result<&[u8], Error> {
+        let h = &self.header;
+        let frames = h.frames.max(1) as usize;
+        let faces = h.faces();
+        let (width, height, depth) = h.mip_size(mip);
+        if mip >= h.mipmaps.max(1) as usize || frame >= frames || face >= faces || slice >= depth {
+            return Err(Error::NoSuchImage);
+        }
+
+        // Mipmaps are stored smallest first, each one holding every frame, face and slice
+        // Saturating since the counts come from the file, anything that big is past the end anyway
+        let mut offset = self.image_data_offset();
+        for smaller in (mip + 1)..h.mipmaps as usize {
+            let (w, h2, d) = h.mip_size(smaller);
+            let mip_bytes = h.high_res_format.image_size(w, h2).saturating_mul(d * faces * frames);
+            offset = offset.saturating_add(mip_bytes);
+        }
+        let size = h.high_res_format.image_size(width, height);
+        offset = offset.saturating_add(((frame * faces + face) * depth + slice).saturating_mul(size));
+
+        self.data.get(offset..offset.saturating_add(size)).ok_or(Error::UnexpectedEof)
+    }
+
+    // Decode one image to 8-bit RGBA, returning (width, height, pixels)
+    pub fn to_rgba8(&self, mip: usize, frame: usize, face: usize, slice: usize) -> Result<(usize, usize, Vec<u8>), Error> {
+        let (width, height, _) = self.header.mip_size(mip);
+        let data = self.image_data(mip, frame, face, slice)?;
+        let pixels = decode_rgba8(self.header.high_res_format, data, width, height)?;
+        Ok((width, height, pixels))
+    }
+}
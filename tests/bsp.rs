@@ -0,0 +1,1391 @@
+use sourcelib::bsp::*;
+
+use std::io::Cursor;
+
+// A header with every lump empty except the ones given as (index, offset, length, version)
+// laid out in the given order
+fn header_bytes(layout: LumpLayout, lumps: &[(usize, u32, u32, u32)]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&VBSP_HEADER.to_le_bytes());
+    bytes.extend_from_slice(&21u32.to_le_bytes());
+    for i in 0..64 {
+        let (offset, length, version) = lumps.iter()
+            .find(|l| l.0 == i)
+            .map(|l| (l.1, l.2, l.3))
+            .unwrap_or((0, 0, 0));
+        let fields = match layout {
+            LumpLayout::Standard => [offset, length, version],
+            LumpLayout::Left4Dead2 => [version, offset, length],
+        };
+        for field in fields.iter() {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0; 4]);
+    }
+    bytes.extend_from_slice(&7u32.to_le_bytes());
+    bytes
+}
+
+#[test]
+fn read_standard_header() {
+    let bytes = header_bytes(LumpLayout::Standard, &[(0, 1036, 100, 0), (1, 1136, 40, 0)]);
+    let header = Header::read(&mut Cursor::new(bytes)).unwrap();
+
+    assert_eq!(header.layout, LumpLayout::Standard);
+    assert_eq!(header.version, 21);
+    assert_eq!(header.iteration, 7);
+    assert_eq!(header.lumps[1].offset, 1136);
+    assert_eq!(header.lumps[1].length, 40);
+}
+
+#[test]
+fn detect_left4dead2_header() {
+    let bytes = header_bytes(LumpLayout::Left4Dead2, &[(0, 1036, 100, 0), (10, 1136, 64, 1)]);
+    let header = Header::read(&mut Cursor::new(bytes)).unwrap();
+
+    assert_eq!(header.layout, LumpLayout::Left4Dead2);
+    assert_eq!(header.lumps[0].offset, 1036);
+    assert_eq!(header.lumps[10].offset, 1136);
+    assert_eq!(header.lumps[10].length, 64);
+    assert_eq!(header.lumps[10].version, 1);
+}
+
+#[test]
+fn lump_file_round_trip() {
+    let lump_file = LumpFile::new(LumpIndex::Entities, 0, 3, b"{\n\"classname\" \"worldspawn\"\n}\n\0".to_vec());
+
+    let mut bytes = Vec::new();
+    lump_file.write(&mut bytes).unwrap();
+    assert_eq!(bytes.len() as u32, LMP_HEADER_SIZE + lump_file.length);
+
+    let read = LumpFile::read(&mut Cursor::new(bytes.clone())).unwrap();
+    assert_eq!(read, lump_file);
+
+    // A length or offset way past the end of the file is an error, not a 4 GiB allocation
+    let mut huge = bytes.clone();
+    huge[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(LumpFile::read(&mut Cursor::new(huge)), Err(Error::UnexpectedEof)));
+    let mut huge = bytes;
+    huge[0..4].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(LumpFile::read(&mut Cursor::new(huge)), Err(Error::UnexpectedEof)));
+}
+
+#[test]
+fn lump_file_names() {
+    assert_eq!(
+        lump_file_path("maps/de_dust2.bsp", 2),
+        std::path::PathBuf::from("maps/de_dust2_l_2.lmp")
+    );
+}
+
+#[test]
+fn write_and_read_back() {
+    use sourcelib::{Plane, Vector};
+
+    let planes = vec![
+        Plane { normal: Vector { x: 0.0, y: 0.0, z: 1.0 }, distance: 64.0, kind: 2 },
+        Plane { normal: Vector { x: 1.0, y: 0.0, z: 0.0 }, distance: -32.0, kind: 0 },
+    ];
+
+    let mut writer = BspWriter::new(20);
+    writer.iteration = 4;
+    writer.set_lump_data(LumpIndex::Entities, b"{\n\"classname\" \"worldspawn\"\n}\n\0".to_vec());
+    writer.set_lump(LumpIndex::Planes, &planes);
+    writer.set_game_lumps(vec![GameLump {
+        id: GameLump::id_from_name("sprp"),
+        flags: 0,
+        version: 10,
+        data: vec![1, 2, 3],
+    }]);
+
+    let path = std::env::temp_dir().join("sourcelib_write_and_read_back.bsp");
+    let path = path.to_str().unwrap();
+    writer.save(path).unwrap();
+
+    let mut bsp = Bsp::from_file(path).unwrap();
+    assert_eq!(bsp.version, 20);
+    assert_eq!(bsp.iteration, 4);
+    for lump in bsp.lumps.iter().filter(|lump| lump.exists()) {
+        assert_eq!(lump.offset % 4, 0);
+    }
+    assert_eq!(bsp.get_lump::<Plane>(LumpIndex::Planes).unwrap(), planes);
+    assert_eq!(bsp.entity_lump_as_string().unwrap(), "{\n\"classname\" \"worldspawn\"\n}\n");
+
+    let game_lumps = bsp.game_lumps().unwrap();
+    assert_eq!(game_lumps[0].name(), "sprp");
+    assert_eq!(game_lumps[0].data, vec![1, 2, 3]);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn copy_game_lumps() {
+    let sub_lump = |name: &str, data: Vec<u8>| GameLump { id: GameLump::id_from_name(name), flags: 0, version: 1, data };
+    let path = std::env::temp_dir().join("sourcelib_copy_game_lumps.bsp");
+    let path = path.to_str().unwrap();
+
+    // An empty GameLump is still copied, as just its count
+    let mut writer = BspWriter::new(20);
+    writer.set_lump_data(LumpIndex::PakFile, vec![9; 100]);
+    writer.set_game_lumps(Vec::new());
+    writer.save(path).unwrap();
+    let mut bsp = Bsp::from_file(path).unwrap();
+    assert_eq!(bsp.lumps[LumpIndex::GameLump as usize].length, 4);
+
+    BspWriter::from_bsp(&mut bsp).unwrap().save(path).unwrap();
+    let mut copy = Bsp::from_file(path).unwrap();
+    assert_eq!(copy.lumps[LumpIndex::GameLump as usize].length, 4);
+    assert!(copy.game_lumps().unwrap().is_empty());
+
+    // A .lmp override's sub-lump offsets are relative to the .lmp, not the .bsp
+    let mut writer = BspWriter::new(20);
+    writer.set_lump_data(LumpIndex::PakFile, vec![9; 100]);
+    writer.set_game_lumps(vec![sub_lump("sprp", vec![1, 2, 3])]);
+    writer.save(path).unwrap();
+    let mut bsp = Bsp::from_file(path).unwrap();
+
+    let replaced = vec![sub_lump("sprp", vec![4, 5, 6, 7]), sub_lump("dprp", vec![8])];
+    let data = GameLump::write_all(&replaced, LMP_HEADER_SIZE, Endian::Little);
+    bsp.apply_lump_file(LumpFile::new(LumpIndex::GameLump, 0, 0, data));
+    assert_eq!(bsp.game_lumps().unwrap(), replaced);
+
+    let writer = BspWriter::from_bsp(&mut bsp).unwrap();
+    assert_eq!(writer.game_lumps(), &replaced[..]);
+    writer.save(path).unwrap();
+    assert_eq!(Bsp::from_file(path).unwrap().game_lumps().unwrap(), replaced);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn read_from_memory() {
+    let mut writer = BspWriter::new(19);
+    writer.set_lump_data(LumpIndex::Entities, b"{\n\"classname\" \"worldspawn\"\n}\n\0".to_vec());
+    writer.set_lump_data(LumpIndex::PakFile, vec![9; 10]);
+
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+    assert_eq!(bsp.version, 19);
+    assert_eq!(bsp.lump_slice(LumpIndex::PakFile).unwrap(), &[9; 10][..]);
+    assert!(matches!(bsp.lump_slice(LumpIndex::Planes), Err(Error::MissingLump(LumpIndex::Planes))));
+
+    let mut bsp = Bsp::from_reader(Cursor::new(bytes.clone())).unwrap();
+    assert_eq!(bsp.get_lump_data(LumpIndex::PakFile).unwrap(), vec![9; 10]);
+    assert_eq!(bsp.entity_lump_as_string().unwrap(), "{\n\"classname\" \"worldspawn\"\n}\n");
+}
+
+#[test]
+fn compress_lumps() {
+    let entities = b"{\n\"classname\" \"worldspawn\"\n}\n{\n\"classname\" \"info_player_start\"\n}\n\0".to_vec();
+    let pakfile: Vec<u8> = (0..4000u32).map(|i| (i % 7 * 31 + i / 500) as u8).collect();
+
+    let mut writer = BspWriter::new(20);
+    writer.set_lump_data(LumpIndex::Entities, entities.clone());
+    writer.set_lump_data(LumpIndex::PakFile, pakfile.clone());
+    writer.set_lump_compressed(LumpIndex::PakFile, true);
+    let path = std::env::temp_dir().join("sourcelib_compress_lumps.bsp");
+    let path = path.to_str().unwrap();
+    writer.save(path).unwrap();
+
+    let mut bsp = Bsp::from_file(path).unwrap();
+    assert_eq!(bsp.lumps[LumpIndex::Entities as usize].indent_code, [0; 4]);
+    let lump = bsp.lumps[LumpIndex::PakFile as usize];
+    assert_eq!(lump.indent_code, 4000u32.to_le_bytes());
+    assert!((lump.length as usize) < pakfile.len());
+
+    let bytes = std::fs::read(path).unwrap();
+    let data = &bytes[lump.offset as usize..(lump.offset + lump.length) as usize];
+    assert_eq!(&data[..4], b"LZMA");
+    assert_eq!(&data[4..8], &4000u32.to_le_bytes());
+    assert_eq!(data[8..12], ((data.len() - LZMA_HEADER_SIZE) as u32).to_le_bytes());
+    assert_eq!(data[12], 0x5D);
+    assert_eq!(decompress_lump(data).unwrap(), pakfile);
+    assert!(matches!(decompress_lump(&data[..10]), Err(Error::UnexpectedEof)));
+    assert!(matches!(decompress_lump(&pakfile), Err(Error::InvalidLzma(_))));
+
+    // Reading the lump decompresses it
+    assert!(bsp.is_lump_compressed(LumpIndex::PakFile));
+    assert_eq!(bsp.get_lump_data(LumpIndex::PakFile).unwrap(), pakfile);
+    assert!(sourcelib::bsp::validate::validate(&mut bsp).is_empty());
+
+    // Copying the map keeps the lump compressed, without compressing it twice
+    let writer = BspWriter::from_bsp(&mut bsp).unwrap();
+    assert_eq!(writer.lump_data(LumpIndex::PakFile), &pakfile[..]);
+    writer.save(path).unwrap();
+    let mut copy = Bsp::from_file(path).unwrap();
+    assert_eq!(copy.lumps[LumpIndex::PakFile as usize].indent_code, 4000u32.to_le_bytes());
+    assert_eq!(copy.get_lump_data(LumpIndex::PakFile).unwrap(), pakfile);
+
+    // A .lmp replacing a compressed lump holds plain data, so the copy does too
+    bsp.apply_lump_file(LumpFile::new(LumpIndex::PakFile, 0, 0, vec![7; 20]));
+    BspWriter::from_bsp(&mut bsp).unwrap().save(path).unwrap();
+    let mut copy = Bsp::from_file(path).unwrap();
+    assert_eq!(copy.lumps[LumpIndex::PakFile as usize].indent_code, [0; 4]);
+    assert_eq!(copy.get_lump_data(LumpIndex::PakFile).unwrap(), vec![7; 20]);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn truncated_maps() {
+    let mut writer = BspWriter::new(20);
+    writer.set_lump_data(LumpIndex::PakFile, vec![9; 100]);
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+
+    // Cut off the end of the pakfile
+    let mut bsp = Bsp::from_bytes(&bytes[..bytes.len() - 50]).unwrap();
+    assert!(matches!(
+        bsp.lump_slice(LumpIndex::PakFile),
+        Err(Error::LumpOutOfBounds { index: LumpIndex::PakFile, length: 100, .. })
+    ));
+
+    // Cut off half the header
+    assert!(matches!(Bsp::from_bytes(&bytes[..500]), Err(Error::UnexpectedEof)));
+}
+
+#[test]
+fn parse_entity_lump() {
+    let entities = parse_entities(
+"{
+\"classname\" \"worldspawn\"
+}
+{
+\"classname\" \"logic_relay\"
+\"OnTrigger\" \"door,Open,,0,-1\"
+\"OnTrigger\" \"light,TurnOn,,0,-1\"
+}
+\0").unwrap();
+
+    assert_eq!(entities.len(), 2);
+    assert_eq!(entities[0].classname(), Some("worldspawn"));
+    assert_eq!(entities[1].get_all("ontrigger").count(), 2);
+    assert_eq!(parse_entities(&entities_to_string(&entities)).unwrap(), entities);
+
+    assert!(matches!(parse_entities("{\n\"classname\"\n}"), Err(Error::EntitySyntax { line: 3 })));
+}
+
+#[test]
+fn validate_broken_map() {
+    use sourcelib::{Edge, Vector};
+    use sourcelib::bsp::validate::*;
+
+    let mut writer = BspWriter::new(20);
+    writer.set_lump_data(LumpIndex::Entities, b"{\n\"classname\" \"func_door\"\n\"model\" \"*3\"\n}\n\0".to_vec());
+    writer.set_lump(LumpIndex::Vertices, &[Vector::default(); 2]);
+    writer.set_lump(LumpIndex::Edges, &[Edge { v: [0, 1] }, Edge { v: [1, 2] }]);
+    writer.set_lump_data(LumpIndex::Planes, vec![0; 30]);
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+    let diagnostics = validate(&mut bsp);
+    let problems: Vec<&Problem> = diagnostics.iter().map(|d| &d.problem).collect();
+
+    assert!(has_errors(&diagnostics));
+    assert!(problems.contains(&&Problem::BadLumpLength { lump: LumpIndex::Planes, length: 30, record_size: 20 }));
+    assert!(problems.contains(&&Problem::IndexOutOfRange {
+        lump: LumpIndex::Edges,
+        record: 1,
+        field: "v",
+        index: 2,
+        target: LumpIndex::Vertices,
+        count: 2,
+    }));
+    assert!(problems.contains(&&Problem::MissingWorldspawn));
+    assert!(problems.contains(&&Problem::MissingBrushModel { entity: 0, model: 3, count: 0 }));
+}
+
+#[test]
+fn map_crc_skips_entities() {
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+
+    let mut writer = BspWriter::new(20);
+    writer.set_lump_data(LumpIndex::Planes, b"1234".to_vec());
+    writer.set_lump_data(LumpIndex::Vertices, b"56789".to_vec());
+    writer.set_lump_data(LumpIndex::Entities, b"{\n\"classname\" \"worldspawn\"\n}\n\0".to_vec());
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+    assert_eq!(bsp.map_crc().unwrap(), crc32(b"123456789"));
+
+    bsp.apply_lump_file(LumpFile::new(LumpIndex::Planes, 0, 0, b"4321".to_vec()));
+    assert_eq!(bsp.map_crc().unwrap(), crc32(b"123456789"));
+}
+
+#[test]
+fn triangulate_faces() {
+    use sourcelib::{Edge, Face, Vector};
+
+    // A square, with its third edge stored the other way around
+    let geometry = Geometry {
+        vertices: vec![
+            Vector::new(0.0, 0.0, 0.0), Vector::new(64.0, 0.0, 0.0),
+            Vector::new(64.0, 64.0, 0.0), Vector::new(0.0, 64.0, 0.0),
+        ],
+        edges: vec![Edge { v: [0, 1] }, Edge { v: [1, 2] }, Edge { v: [3, 2] }, Edge { v: [3, 0] }],
+        surface_edges: vec![0, 1, -2, 3],
+        primitives: vec![Primitive { kind: PRIM_TRILIST, first_index: 0, index_count: 6, first_vertex: 0, vertex_count: 0 }],
+        primitive_indices: vec![0, 1, 3, 1, 2, 3],
+        ..Default::default()
+    };
+
+    let face = Face { first_edge: 0, num_edges: 4, ..Default::default() };
+    assert_eq!(geometry.face_vertex_indices(&face), vec![0, 1, 2, 3]);
+
+    let face = Face { first_edge: 0, num_edges: 4, num_primitives: 0x8001, ..Default::default() };
+    let triangles = geometry.triangulate(&face);
+    assert_eq!(triangles.len(), 2);
+    assert_eq!(triangles[1][0], Vector::new(64.0, 0.0, 0.0));
+
+    let face = Face { first_edge: 0, num_edges: 4, ..Default::default() };
+    assert_eq!(geometry.triangulate(&face).len(), 2);
+}
+
+#[test]
+fn smooth_vertex_normals() {
+    use sourcelib::{Edge, Face, Plane, Vector};
+
+    // Two faces meeting at a right angle along the edge from vertex 0 to 1
+    let mut geometry = Geometry {
+        planes: vec![
+            Plane { normal: Vector::new(0.0, 0.0, 1.0), ..Default::default() },
+            Plane { normal: Vector::new(0.0, 1.0, 0.0), ..Default::default() },
+        ],
+        vertices: vec![
+            Vector::new(0.0, 0.0, 0.0), Vector::new(64.0, 0.0, 0.0),
+            Vector::new(64.0, 64.0, 0.0), Vector::new(0.0, 0.0, -64.0),
+        ],
+        edges: vec![Edge { v: [0, 1] }, Edge { v: [1, 2] }, Edge { v: [2, 0] }, Edge { v: [1, 3] }, Edge { v: [3, 0] }],
+        surface_edges: vec![0, 1, 2, 0, 3, 4],
+        faces: vec![
+            Face { plane_number: 0, first_edge: 0, num_edges: 3, smoothing_group: 1, ..Default::default() },
+            Face { plane_number: 1, first_edge: 3, num_edges: 3, smoothing_group: 1, ..Default::default() },
+        ],
+        ..Default::default()
+    };
+
+    let normals = geometry.vertex_normals();
+    let diagonal = Vector::new(0.0, 1.0, 1.0).normalized();
+    assert_eq!(normals[0], vec![diagonal, diagonal, Vector::new(0.0, 0.0, 1.0)]);
+
+    // Different smoothing groups stay flat
+    geometry.faces[1].smoothing_group = 2;
+    assert_eq!(geometry.vertex_normals()[0], vec![Vector::new(0.0, 0.0, 1.0); 3]);
+
+    // Normals stored in the map win
+    geometry.vertex_normals = vec![Vector::new(1.0, 0.0, 0.0)];
+    geometry.vertex_normal_indices = vec![0; 6];
+    assert_eq!(geometry.vertex_normals()[1], vec![Vector::new(1.0, 0.0, 0.0); 3]);
+}
+
+#[test]
+fn big_endian_maps() {
+    use sourcelib::{Plane, Vector};
+
+    let planes = vec![Plane { normal: Vector::new(0.0, 0.0, 1.0), distance: 64.0, kind: 2 }];
+
+    let mut writer = BspWriter::new(20);
+    writer.endian = Endian::Big;
+    writer.iteration = 2;
+    writer.set_lump(LumpIndex::Planes, &planes);
+    writer.set_game_lumps(vec![GameLump { id: GameLump::id_from_name("sprp"), flags: 0, version: 5, data: vec![1] }]);
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+    assert_eq!(&bytes[0..4], b"PSBV");
+    let planes_lump = Lump::read_with_layout(&mut &bytes[8 + 16..], LumpLayout::Standard, Endian::Big).unwrap();
+    assert_eq!(planes_lump.length, 20);
+
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+    assert_eq!(bsp.endian, Endian::Big);
+    assert_eq!(bsp.version, 20);
+    assert_eq!(bsp.iteration, 2);
+    assert_eq!(bsp.planes().unwrap(), planes);
+    assert_eq!(bsp.game_lumps().unwrap()[0].name(), "sprp");
+}
+
+// A zip with stored files, the way vbsp/bspzip write pakfiles
+fn stored_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut zip = Vec::new();
+    let mut directory = Vec::new();
+    for (name, data) in files {
+        let offset = zip.len() as u32;
+        let crc = crc32(data);
+        let fields = |out: &mut Vec<u8>| {
+            out.extend_from_slice(&[0; 4]); // flags, compression
+            out.extend_from_slice(&[0; 4]); // time, date
+            out.extend_from_slice(&crc.to_le_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            out.extend_from_slice(&[0; 2]); // extra
+        };
+        zip.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        zip.extend_from_slice(&10u16.to_le_bytes());
+        fields(&mut zip);
+        zip.extend_from_slice(name.as_bytes());
+        zip.extend_from_slice(data);
+
+        directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        directory.extend_from_slice(&[10, 0, 10, 0]);
+        fields(&mut directory);
+        directory.extend_from_slice(&[0; 6]); // comment, disk, internal attributes
+        directory.extend_from_slice(&[0; 4]); // external attributes
+        directory.extend_from_slice(&offset.to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+    }
+    let directory_offset = zip.len() as u32;
+    zip.extend_from_slice(&directory);
+    zip.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    zip.extend_from_slice(&[0; 4]);
+    zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    zip.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    zip.extend_from_slice(&directory_offset.to_le_bytes());
+    zip.extend_from_slice(&[0; 2]);
+    zip
+}
+
+#[test]
+fn read_pakfile() {
+    let zip = stored_zip(&[
+        ("materials/test/wall.vmt", b"LightmappedGeneric {}"),
+        ("maps/test.nav", &[1, 2, 3]),
+    ]);
+
+    let mut writer = BspWriter::new(20);
+    writer.set_lump_data(LumpIndex::PakFile, zip);
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+    let pak = bsp.pak_file().unwrap();
+    assert_eq!(pak.entries.len(), 2);
+    assert_eq!(pak.entries[1].name, "maps/test.nav");
+    assert_eq!(pak.file("Materials\\Test\\wall.VMT").unwrap(), b"LightmappedGeneric {}");
+    assert_eq!(pak.file("maps/test.nav").unwrap(), &[1, 2, 3]);
+    assert!(matches!(pak.file("nope.txt"), Err(Error::MissingFile(_))));
+
+    assert!(matches!(PakFile::read(b"not a zip"), Err(Error::InvalidZip(_))));
+}
+
+#[test]
+fn find_water() {
+    use sourcelib::{Plane, Vector};
+
+    // One plane at z = 0, air above it and water below
+    let mut writer = BspWriter::new(20);
+    writer.set_lump(LumpIndex::Planes, &[Plane { normal: Vector::new(0.0, 0.0, 1.0), distance: 0.0, kind: 2 }]);
+    writer.set_lump(LumpIndex::Nodes, &[Node { children: [-1, -2], ..Default::default() }]);
+    writer.set_lump_version(LumpIndex::Leafs, 1);
+    writer.set_lump(LumpIndex::Leafs, &[
+        Leaf { contents: CONTENTS_EMPTY, leaf_water_data_id: -1, ..Default::default() },
+        Leaf { contents: CONTENTS_WATER, leaf_water_data_id: 0, ..Default::default() },
+    ]);
+    writer.set_lump(LumpIndex::LeafWaterData, &[LeafWaterData { surface_z: 0.0, min_z: -128.0, surface_texture_info: 0 }]);
+    writer.set_lump(LumpIndex::LeafDistanceToWater, &[16u16, 0]);
+    writer.set_lump(LumpIndex::TextureInfo, &[TextureInfo::default()]);
+    writer.set_lump(LumpIndex::TextureData, &[TextureData::default()]);
+    writer.set_lump(LumpIndex::TextureStringTable, &[0i32]);
+    writer.set_lump_data(LumpIndex::TextureStringData, b"liquids/water\0".to_vec());
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+    assert_eq!(bsp.leaf_distances_to_water().unwrap(), vec![16, 0]);
+    assert_eq!(bsp.leaf_at(&Vector::new(0.0, 0.0, 32.0)).unwrap(), 0);
+    assert_eq!(bsp.water_at(&Vector::new(0.0, 0.0, 32.0)).unwrap(), None);
+
+    let water = bsp.water_at(&Vector::new(0.0, 0.0, -32.0)).unwrap().unwrap();
+    assert_eq!(water.leaf, 1);
+    assert_eq!(water.surface_z, 0.0);
+    assert_eq!(water.min_z, -128.0);
+    assert_eq!(water.material.as_deref(), Some("liquids/water"));
+}
+
+#[test]
+fn read_occluders() {
+    use sourcelib::Vector;
+
+    let vertices = [
+        Vector::new(0.0, 0.0, 0.0), Vector::new(128.0, 0.0, 0.0),
+        Vector::new(128.0, 0.0, 64.0), Vector::new(0.0, 0.0, 64.0),
+    ];
+    let occlusion = Occlusion {
+        occluders: vec![
+            OccluderData { first_poly: 0, poly_count: 1, area: 1, ..Default::default() },
+            OccluderData { flags: OCCLUDER_FLAGS_INACTIVE, first_poly: 1, poly_count: 0, ..Default::default() },
+        ],
+        polygons: vec![OccluderPolyData { first_vertex_index: 0, vertex_count: 4, plane_number: 0 }],
+        vertex_indices: vec![0, 1, 2, 3],
+    };
+
+    let mut writer = BspWriter::new(20);
+    writer.set_lump(LumpIndex::Vertices, &vertices);
+    writer.set_lump_version(LumpIndex::Occlusion, 2);
+    let context = LumpContext { bsp_version: 20, lump_version: 2, endian: Endian::Little };
+    writer.set_lump_data(LumpIndex::Occlusion, occlusion.write(context));
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+    assert_eq!(bsp.occlusion().unwrap(), occlusion);
+
+    let occluders = bsp.occluders().unwrap();
+    assert_eq!(occluders.len(), 2);
+    assert!(occluders[0].is_active());
+    assert!(!occluders[1].is_active());
+    assert_eq!(occluders[0].area, 1);
+    assert_eq!(occluders[0].polygons[0], vertices.to_vec());
+    assert_eq!(occluders[0].maxs, Vector::new(128.0, 0.0, 64.0));
+    assert_eq!(occluders[0].surface_area(), 128.0 * 64.0);
+
+    // Version 1 occluders don't have an area
+    let context = LumpContext { lump_version: 1, ..context };
+    assert_eq!(Occlusion::read(&occlusion.write(context), context).unwrap().occluders[0].area, 0);
+    assert!(matches!(Occlusion::read(&[1, 0, 0, 0], context), Err(Error::UnexpectedEof)));
+}
+
+fn put_i32(data: &mut [u8], at: usize, v: i32) {
+    data[at..at + 4].copy_from_slice(&v.to_le_bytes());
+}
+
+// A VPHY header, then a compact surface with three points,
+// two single-triangle ledges and a ledge tree with a node and two leaves
+fn ivp_solid() -> Vec<u8> {
+    let s = 28;
+    let mut solid = vec![0; s + 244];
+    solid[0..4].copy_from_slice(b"VPHY");
+    put_i32(&mut solid, s + 32, 160);
+    for (i, point) in [[1.0f32, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]].iter().enumerate() {
+        for (j, v) in point.iter().enumerate() {
+            solid[s + 48 + i * 16 + j * 4..][..4].copy_from_slice(&v.to_le_bytes());
+        }
+    }
+    for (ledge, points) in [(s + 96, [0, 1, 2]), (s + 128, [2, 1, 0])].iter() {
+        put_i32(&mut solid, *ledge, (s + 48) as i32 - *ledge as i32);
+        solid[ledge + 12] = 1; // triangle count
+        for (e, p) in points.iter().enumerate() {
+            put_i32(&mut solid, ledge + 16 + 4 + e * 4, *p);
+        }
+    }
+    put_i32(&mut solid, s + 160, 56);
+    put_i32(&mut solid, s + 188 + 4, -92);
+    put_i32(&mut solid, s + 216 + 4, -88);
+    solid
+}
+
+#[test]
+fn read_physics_collision() {
+    use sourcelib::Vector;
+
+    let solid = ivp_solid();
+    let text = b"solid\n{\n\"index\" \"0\"\n\"mass\" \"5000\"\n}\n\0";
+    let mut lump = Vec::new();
+    for v in [0, 4 + solid.len() as i32, text.len() as i32, 1, solid.len() as i32].iter() {
+        lump.extend_from_slice(&v.to_le_bytes());
+    }
+    lump.extend_from_slice(&solid);
+    lump.extend_from_slice(text);
+    for v in [-1i32, -1, 0, 0].iter() {
+        lump.extend_from_slice(&v.to_le_bytes());
+    }
+
+    let models = PhysicsModel::read_all(&lump, LumpContext::default()).unwrap();
+    assert_eq!(models.len(), 1);
+    assert_eq!(models[0].model_index, 0);
+    assert_eq!(models[0].key_values.get_subkey("solid").unwrap().get::<f32>("mass"), Some(5000.0));
+
+    let hulls = &models[0].solids[0].hulls;
+    assert_eq!(hulls.len(), 2);
+    assert_eq!(hulls[0].triangles, vec![[0, 1, 2]]);
+    // IVP's +Y is the engine's -Z
+    assert!((hulls[0].vertices[1] - Vector::new(0.0, 0.0, -39.37)).length() < 0.01);
+    assert!((hulls[1].vertices[0] - Vector::new(0.0, 39.37, 0.0)).length() < 0.01);
+
+    assert!(matches!(PhysicsModel::read_all(&lump[..40], LumpContext::default()), Err(Error::UnexpectedEof)));
+}
+
+#[test]
+fn assemble_displacements() {
+    use sourcelib::Vector;
+
+    let infos = [
+        DispInfo { power: 2, disp_vert_start: 0, min_tess: DISP_INFO_FLAG_MAGIC, ..Default::default() },
+        DispInfo { power: 2, disp_vert_start: 25, min_tess: DISP_INFO_FLAG_MAGIC | DISP_INFO_FLAG_HAS_MULTIBLEND, map_face: 3, ..Default::default() },
+    ];
+    let vertices: Vec<DispVert> = (0..50)
+        .map(|i| DispVert { vector: Vector::new(0.0, 0.0, 1.0), distance: i as f32, alpha: 0.0 })
+        .collect();
+    let multiblend = vec![MultiBlend { blend: [1.0, 0.0, 0.0, 0.0], ..Default::default() }; 25];
+
+    // The first displacement has collision, the second doesn't
+    let solid = ivp_solid();
+    let mut physics = Vec::new();
+    physics.extend_from_slice(&2u16.to_le_bytes());
+    physics.extend_from_slice(&(solid.len() as u16).to_le_bytes());
+    physics.extend_from_slice(&0xFFFFu16.to_le_bytes());
+    physics.extend_from_slice(&solid);
+
+    let mut writer = BspWriter::new(21);
+    writer.set_lump(LumpIndex::DisplacementInfo, &infos);
+    writer.set_lump(LumpIndex::DisplacementVertices, &vertices);
+    writer.set_lump(LumpIndex::DisplacementMultiblend, &multiblend);
+    writer.set_lump_data(LumpIndex::PhysicsDisplacement, physics);
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+    assert_eq!(bsp.displacement_infos().unwrap().to_vec(), infos.to_vec());
+
+    let displacements = bsp.displacements().unwrap();
+    assert_eq!(displacements.len(), 2);
+    assert_eq!(displacements[0].vertices.len(), 25);
+    assert_eq!(displacements[1].vertices[0].distance, 25.0);
+    assert_eq!(displacements[1].info.map_face, 3);
+    assert_eq!(displacements[0].collision.as_ref().unwrap().hulls.len(), 2);
+    assert_eq!(displacements[1].collision, None);
+    assert_eq!(displacements[0].multiblend, None);
+    assert_eq!(displacements[1].multiblend.as_ref().unwrap()[0].blend, [1.0, 0.0, 0.0, 0.0]);
+}
+
+#[test]
+fn decompile_to_vmf() {
+    use sourcelib::keyvalues::Value;
+    use sourcelib::{Plane, Vector};
+
+    // Axis-aligned box planes, +x -x +y -y +z -z
+    fn box_planes(mins: f32, maxs: f32) -> Vec<Plane> {
+        let mut planes = Vec::new();
+        for axis in 0..3 {
+            let mut n = [0.0; 3];
+            n[axis] = 1.0;
+            planes.push(Plane { normal: Vector::new(n[0], n[1], n[2]), distance: maxs, kind: axis as i32 });
+            planes.push(Plane { normal: Vector::new(-n[0], -n[1], -n[2]), distance: -mins, kind: axis as i32 });
+        }
+        planes
+    }
+
+    // A 64 unit cube in the world, and a 32 unit door built around its origin
+    let mut planes = box_planes(0.0, 64.0);
+    planes.extend(box_planes(-16.0, 16.0));
+    let mut sides: Vec<BrushSide> = (0..12)
+        .map(|i| BrushSide { plane_number: i, texture_info: if i < 6 { 0 } else { 1 }, displacement_info: -1, ..Default::default() })
+        .collect();
+    // A bevel that would cut the cube in half if it wasn't skipped
+    planes.push(Plane { normal: Vector::new(1.0, 0.0, 0.0), distance: 32.0, kind: 0 });
+    sides.insert(6, BrushSide { plane_number: 12, texture_info: -1, displacement_info: -1, bevel: true, thin: false });
+
+    let mut writer = BspWriter::new(20);
+    writer.set_lump(LumpIndex::Planes, &planes);
+    writer.set_lump(LumpIndex::BrushSides, &sides);
+    writer.set_lump(LumpIndex::Brushes, &[
+        Brush { first_side: 0, num_sides: 7, contents: 1 },
+        Brush { first_side: 7, num_sides: 6, contents: 1 },
+    ]);
+    writer.set_lump(LumpIndex::Nodes, &[
+        Node { children: [-1, -1], ..Default::default() },
+        Node { children: [-2, -2], ..Default::default() },
+    ]);
+    writer.set_lump_version(LumpIndex::Leafs, 1);
+    writer.set_lump(LumpIndex::Leafs, &[
+        Leaf { first_leaf_brush: 0, num_leaf_brushes: 1, ..Default::default() },
+        Leaf { first_leaf_brush: 1, num_leaf_brushes: 1, ..Default::default() },
+    ]);
+    writer.set_lump(LumpIndex::LeafBrushes, &[0u16, 1]);
+    writer.set_lump(LumpIndex::Models, &[Model { head_node: 0, ..Default::default() }, Model { head_node: 1, ..Default::default() }]);
+    writer.set_lump(LumpIndex::TextureInfo, &[TextureInfo {
+        texture_vecs: [[4.0, 0.0, 0.0, 8.0], [0.0, -4.0, 0.0, 0.0]],
+        lightmap_vecs: [[0.0625, 0.0, 0.0, 0.0], [0.0, -0.0625, 0.0, 0.0]],
+        ..Default::default()
+    }, TextureInfo {
+        // vbsp adds the door's origin into the shift: 8 + 4 * 100
+        texture_vecs: [[4.0, 0.0, 0.0, 408.0], [0.0, -4.0, 0.0, 0.0]],
+        lightmap_vecs: [[0.0625, 0.0, 0.0, 0.0], [0.0, -0.0625, 0.0, 0.0]],
+        ..Default::default()
+    }]);
+    writer.set_lump(LumpIndex::TextureData, &[TextureData::default()]);
+    writer.set_lump(LumpIndex::TextureStringTable, &[0i32]);
+    writer.set_lump_data(LumpIndex::TextureStringData, b"DEV/DEV_MEASUREWALL01A\0".to_vec());
+    writer.set_lump_data(LumpIndex::Entities, b"{\n\"classname\" \"worldspawn\"\n\"skyname\" \"sky_day01_01\"\n}\n\
+{\n\"classname\" \"func_door\"\n\"model\" \"*1\"\n\"origin\" \"100 0 0\"\n\
+\"OnOpen\" \"relay,Trigger,,0,-1\"\n\"OnClose\" \"sound\x1bPlaySound\x1b\x1b0.5\x1b1\"\n}\n\0".to_vec());
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+    let vmf = vmf::decompile(&mut bsp).unwrap();
+
+    let world = vmf.get_subkey("world").unwrap();
+    assert_eq!(world.get_str("skyname"), Some("sky_day01_01"));
+    let solids: Vec<_> = world.get_subkeys("solid").collect();
+    assert_eq!(solids.len(), 1);
+    let cube_sides: Vec<_> = solids[0].get_subkeys("side").collect();
+    assert_eq!(cube_sides.len(), 6);
+    assert_eq!(cube_sides[0].get_str("material"), Some("DEV/DEV_MEASUREWALL01A"));
+    assert_eq!(cube_sides[0].get_str("uaxis"), Some("[1 0 0 8] 0.25"));
+    assert_eq!(cube_sides[0].get_str("vaxis"), Some("[0 -1 0 0] 0.25"));
+    assert_eq!(cube_sides[0].get_str("lightmapscale"), Some("16"));
+
+    // The +x side, with Hammer's point order giving an outward normal
+    let points: Vec<f32> = cube_sides[0].get_str("plane").unwrap()
+        .split(['(', ')', ' '])
+        .filter_map(|s| s.parse().ok())
+        .collect();
+    let p: Vec<Vector> = points.chunks(3).map(|c| Vector::new(c[0], c[1], c[2])).collect();
+    assert!(p.iter().all(|v| v.x == 64.0 && (v.y == 0.0 || v.y == 64.0) && (v.z == 0.0 || v.z == 64.0)));
+    assert!((p[0] - p[1]).cross(&(p[2] - p[1])).x > 0.0);
+
+    // The door goes back around its origin, with no "*1" model key
+    let door = vmf.get_subkeys("entity").next().unwrap();
+    assert_eq!(door.get_str("classname"), Some("func_door"));
+    assert_eq!(door.get_str("model"), None);
+    // Outputs go in the connections block, where Hammer's Outputs tab looks for them
+    assert_eq!(door.get_str("OnOpen"), None);
+    let connections = door.get_subkey("connections").unwrap();
+    assert_eq!(connections.get_str("OnOpen"), Some("relay,Trigger,,0,-1"));
+    assert_eq!(connections.get_str("OnClose"), Some("sound\x1bPlaySound\x1b\x1b0.5\x1b1"));
+    assert!(vmf.get_subkey("world").unwrap().get_subkey("connections").is_none());
+    let door_solid = door.get_subkey("solid").unwrap();
+    assert_eq!(door_solid.get_subkeys("side").count(), 6);
+    let door_side = door_solid.get_subkey("side").unwrap();
+    assert!(door_side.get_str("plane").unwrap().contains("(116 "));
+    assert_eq!(door_side.get_str("uaxis"), Some("[1 0 0 8] 0.25"));
+
+    // And it's valid KeyValues
+    let text = vmf.to_string();
+    assert!(matches!(sourcelib::keyvalues::KeyValues::from_str(&text).unwrap().get_path("versioninfo/formatversion"), Some(Value::Str(_))));
+}
+
+#[test]
+fn portal_lumps_by_version() {
+    use sourcelib::Vector;
+
+    let vertices = [
+        Vector::new(0.0, 0.0, 0.0), Vector::new(0.0, 64.0, 0.0),
+        Vector::new(0.0, 64.0, 64.0), Vector::new(0.0, 0.0, 64.0),
+    ];
+
+    // Old maps: one portal between clusters 0 and 1
+    let mut writer = BspWriter::new(19);
+    writer.set_lump(LumpIndex::Vertices, &vertices);
+    writer.set_lump(LumpIndex::Portals, &[Portal { first_portal_vert: 0, num_portal_verts: 4, plane_number: 0, cluster: [0, 1] }]);
+    writer.set_lump(LumpIndex::Clusters, &[Cluster { first_portal: 0, num_portals: 1 }, Cluster { first_portal: 1, num_portals: 1 }]);
+    writer.set_lump(LumpIndex::PortalVerts, &[3u16, 2, 1, 0]);
+    writer.set_lump(LumpIndex::ClusterPortals, &[0u16, 0]);
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+    let portals = bsp.portal_polygons().unwrap();
+    assert_eq!(portals[0].clusters, [0, 1]);
+    assert_eq!(portals[0].vertices[0], vertices[3]);
+    assert_eq!(bsp.cluster_portal_lists().unwrap(), vec![vec![0], vec![0]]);
+    assert!(matches!(bsp.prop_collisions(), Err(Error::LumpNotInVersion { version: 19, .. })));
+
+    // Left 4 Dead: one prop model with two hulls
+    let mut writer = BspWriter::new(20);
+    writer.set_lump(LumpIndex::Portals, &[PropCollision { hull_count: 2, hull_start: 0 }]);
+    writer.set_lump(LumpIndex::Clusters, &[
+        PropHull { vert_count: 3, vert_start: 0, surface_prop: 5, contents: 1 },
+        PropHull { vert_count: 1, vert_start: 3, surface_prop: 6, contents: 1 },
+    ]);
+    writer.set_lump(LumpIndex::PortalVerts, &vertices);
+    writer.set_lump(LumpIndex::ClusterPortals, &[PropTris { index_start: 0, index_count: 3 }]);
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+    let hulls = bsp.prop_collision_hulls().unwrap();
+    assert_eq!(hulls.len(), 1);
+    assert_eq!(hulls[0][0].vertices, vertices[0..3].to_vec());
+    assert_eq!(hulls[0][0].tris, Some(PropTris { index_start: 0, index_count: 3 }));
+    assert_eq!(hulls[0][1].surface_prop, 6);
+    assert_eq!(hulls[0][1].tris, None);
+    assert!(matches!(bsp.portals(), Err(Error::LumpNotInVersion { version: 20, .. })));
+}
+
+// Flat, axis aligned squares at height z, for maps that only need a few faces
+// Each face starts out as the template given, for its plane, side, texture info and so on
+#[derive(Default)]
+struct Squares {
+    vertices: Vec<sourcelib::Vector>,
+    edges: Vec<sourcelib::Edge>,
+    faces: Vec<sourcelib::Face>,
+}
+
+impl Squares {
+    fn add(&mut self, mins: (f32, f32), maxs: (f32, f32), z: f32, template: sourcelib::Face) {
+        use sourcelib::{Edge, Face, Vector};
+
+        let first = self.vertices.len() as u16;
+        self.vertices.extend_from_slice(&[
+            Vector::new(mins.0, mins.1, z), Vector::new(maxs.0, mins.1, z),
+            Vector::new(maxs.0, maxs.1, z), Vector::new(mins.0, maxs.1, z),
+        ]);
+        let first_edge = self.edges.len() as i32;
+        self.edges.extend((0..4).map(|i| Edge { v: [first + i, first + (i + 1) % 4] }));
+        self.faces.push(Face { first_edge, num_edges: 4, ..template });
+    }
+
+    // The Vertices, Edges, SurfaceEdges and Faces lumps
+    fn write(&self, writer: &mut BspWriter) {
+        writer.set_lump(LumpIndex::Vertices, &self.vertices);
+        writer.set_lump(LumpIndex::Edges, &self.edges);
+        writer.set_lump(LumpIndex::SurfaceEdges, &(0..self.edges.len() as i32).collect::<Vec<_>>());
+        writer.set_lump(LumpIndex::Faces, &self.faces);
+    }
+}
+
+#[test]
+fn render_radar() {
+    use sourcelib::{Face, Plane, Vector};
+
+    let mut squares = Squares::default();
+    let face = |plane_number: u16, side: u8, tex_info: i16| Face { plane_number, side, tex_info, ..Default::default() };
+    squares.add((0.0, 0.0), (256.0, 256.0), 0.0, face(0, 0, 0)); // floor
+    squares.add((0.0, 128.0), (128.0, 256.0), 128.0, face(1, 0, 1)); // red platform in the north west
+    squares.add((0.0, 0.0), (256.0, 256.0), 512.0, face(2, 0, 2)); // sky
+    squares.add((0.0, 0.0), (256.0, 256.0), 64.0, face(3, 1, 0)); // ceiling, facing down
+    squares.add((0.0, 0.0), (256.0, 256.0), 200.0, face(4, 0, 0)); // a brush entity
+
+    let up = |distance| Plane { normal: Vector::new(0.0, 0.0, 1.0), distance, kind: 2 };
+    let mut writer = BspWriter::new(20);
+    writer.set_lump(LumpIndex::Planes, &[up(0.0), up(128.0), up(512.0), up(64.0), up(200.0)]);
+    squares.write(&mut writer);
+    writer.set_lump(LumpIndex::Models, &[Model { first_face: 0, num_faces: 4, ..Default::default() }]);
+    writer.set_lump(LumpIndex::TextureInfo, &[
+        TextureInfo { texture_data: 0, ..Default::default() },
+        TextureInfo { texture_data: 1, ..Default::default() },
+        TextureInfo { flags: SURF_SKY, texture_data: 0, ..Default::default() },
+    ]);
+    writer.set_lump(LumpIndex::TextureData, &[
+        TextureData { reflectivity: Vector::new(0.5, 0.5, 0.5), ..Default::default() },
+        TextureData { reflectivity: Vector::new(1.0, 0.0, 0.0), ..Default::default() },
+    ]);
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+
+    let pixel = |radar: &Radar, x: usize, y: usize| radar.pixels[(y * radar.size + x) * 4..][..4].to_vec();
+    let options = RadarOptions { size: 16, padding: 0.0, ..Default::default() };
+    let radar = bsp.radar(&options).unwrap();
+    assert_eq!((radar.pos_x, radar.pos_y, radar.scale), (0.0, 256.0, 0.25));
+    assert_eq!(radar.pixels.len(), 16 * 16 * 4);
+    assert_eq!(pixel(&radar, 0, 0), vec![255, 255, 255, 255]); // the platform, as high as it goes
+    assert_eq!(pixel(&radar, 15, 15), vec![38, 38, 38, 255]); // the floor
+    assert!(radar.overview_txt("de_test").contains("\"pos_y\"\t\"256\""));
+
+    let radar = bsp.radar(&RadarOptions { shading: RadarShading::Material, ..options.clone() }).unwrap();
+    assert_eq!(pixel(&radar, 0, 0), vec![255, 0, 0, 255]);
+
+    // Cut off above the floor, so only the floor is left
+    let radar = bsp.radar(&RadarOptions { max_z: Some(64.0), ..options }).unwrap();
+    assert_eq!(pixel(&radar, 0, 0), pixel(&radar, 15, 15));
+
+    // With padding there's nothing at the edges
+    let radar = bsp.radar(&RadarOptions { size: 16, padding: 128.0, ..Default::default() }).unwrap();
+    assert_eq!(pixel(&radar, 0, 0), vec![0, 0, 0, 0]);
+}
+
+#[test]
+fn entity_io_graph() {
+    let entities = parse_entities(
+"{
+\"classname\" \"logic_auto\"
+\"OnMapSpawn\" \"relay_a,Trigger,,0,-1\"
+}
+{
+\"classname\" \"logic_relay\"
+\"targetname\" \"relay_a\"
+\"OnTrigger\" \"relay_b,Trigger,,1,-1\"
+\"OnTrigger\" \"door_*,Open,,0,1\"
+\"OnTrigger\" \"!activator\x1bSetHealth\x1b100,5\x1b0\x1b-1\"
+}
+{
+\"classname\" \"logic_relay\"
+\"targetname\" \"relay_b\"
+\"OnTrigger\" \"RELAY_A,Trigger,,1,-1\"
+\"OnTrigger\" \"nothing,Kill,,0,-1\"
+}
+{
+\"classname\" \"func_door\"
+\"targetname\" \"door_1\"
+\"origin\" \"0 0 0\"
+}
+{
+\"classname\" \"func_door\"
+\"targetname\" \"door_2\"
+}
+{
+\"classname\" \"math_counter\"
+\"targetname\" \"counter\"
+\"OnHitMax\" \"!self,Disable,,0,-1\"
+}
+").unwrap();
+
+    let connections = entities[1].connections();
+    assert_eq!(connections.len(), 3);
+    assert_eq!(connections[0].delay, 1.0);
+    assert_eq!(connections[2].parameter, "100,5");
+    assert!(entities[3].connections().is_empty());
+
+    let graph = IoGraph::new(&entities);
+    assert_eq!(graph.edges.len(), 7);
+    assert_eq!(graph.edges[0].target, IoTarget::Entities(vec![1]));
+    assert_eq!(graph.edges[2].target, IoTarget::Entities(vec![3, 4]));
+    assert_eq!(graph.edges[3].target, IoTarget::Special("!activator".to_string()));
+    assert_eq!(graph.inputs_to(3).count(), 1);
+
+    let dangling: Vec<_> = graph.dangling().collect();
+    assert_eq!(dangling.len(), 1);
+    assert_eq!(dangling[0].from, 2);
+    assert_eq!(dangling[0].connection.target, "nothing");
+
+    // The counter has no inputs, and the relays are reached from the logic_auto
+    assert_eq!(graph.unreachable(), vec![5]);
+    assert_eq!(graph.cycles(), vec![vec![1, 2], vec![5]]);
+
+    let dot = graph.to_dot();
+    assert!(dot.starts_with("digraph io {"));
+    assert!(dot.contains("e1 -> e4 [label=\"OnTrigger > Open\"];"));
+    assert!(dot.contains("\"missing:nothing\" [label=\"nothing\", color=red, style=dashed];"));
+    assert!(dot.contains("\"!activator\" [shape=box];"));
+}
+
+// A version 10-ish 'sprp' game lump, padded out past the fields that get read
+fn static_prop_lump(models: &[&str], props: &[([f32; 3], f32, u16)]) -> GameLump {
+    let mut data = Vec::new();
+    data.extend_from_slice(&(models.len() as i32).to_le_bytes());
+    for model in models {
+        let mut name = [0u8; 128];
+        name[..model.len()].copy_from_slice(model.as_bytes());
+        data.extend_from_slice(&name);
+    }
+    data.extend_from_slice(&1i32.to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&(props.len() as i32).to_le_bytes());
+    for (origin, yaw, model) in props {
+        for v in origin.iter().chain([0.0, *yaw, 0.0].iter()) {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data.extend_from_slice(&model.to_le_bytes());
+        data.extend_from_slice(&[0; 50]);
+    }
+    GameLump { id: GameLump::id_from_name("sprp"), flags: 0, version: 10, data }
+}
+
+#[test]
+fn diff_map_revisions() {
+    use sourcelib::{Plane, Vector};
+
+    let map = |plane: f32, entities: &str, pak: &[(&str, &[u8])], props: &[([f32; 3], f32, u16)]| {
+        let mut writer = BspWriter::new(20);
+        writer.set_lump(LumpIndex::Planes, &[Plane { normal: Vector::new(0.0, 0.0, 1.0), distance: plane, kind: 2 }]);
+        writer.set_lump_data(LumpIndex::Entities, format!("{}\0", entities).into_bytes());
+        writer.set_lump_data(LumpIndex::PakFile, stored_zip(pak));
+        writer.set_game_lumps(vec![static_prop_lump(&["models/crate.mdl", "models/barrel.mdl"], props)]);
+        let mut bytes = Vec::new();
+        writer.write(&mut bytes).unwrap();
+        bytes
+    };
+    let old_bytes = map(0.0,
+        "{\n\"classname\" \"worldspawn\"\n}\n\
+         {\n\"classname\" \"func_door\"\n\"hammerid\" \"5\"\n\"targetname\" \"door\"\n\"OnOpen\" \"a,Trigger,,0,-1\"\n\"OnOpen\" \"b,Trigger,,0,-1\"\n}\n\
+         {\n\"classname\" \"light\"\n\"origin\" \"0 0 64\"\n}\n\
+         {\n\"classname\" \"info_target\"\n\"targetname\" \"gone\"\n}\n",
+        &[("materials/a.vmt", b"old"), ("materials/b.vmt", b"same"), ("sound/c.wav", b"c")],
+        &[([0.0, 0.0, 0.0], 0.0, 0), ([64.0, 0.0, 0.0], 0.0, 0), ([0.0, 128.0, 0.0], 90.0, 1)]);
+    let new = map(16.0,
+        "{\n\"classname\" \"worldspawn\"\n}\n\
+         {\n\"classname\" \"func_door\"\n\"hammerid\" \"5\"\n\"targetname\" \"big_door\"\n\"OnOpen\" \"b,Trigger,,0,-1\"\n\"OnOpen\" \"c,Trigger,,0,-1\"\n}\n\
+         {\n\"classname\" \"light\"\n\"origin\" \"0 0 64\"\n\"_light\" \"255 0 0 200\"\n}\n\
+         {\n\"classname\" \"info_target\"\n\"targetname\" \"new\"\n}\n",
+        &[("materials/A.vmt", b"new"), ("materials/b.vmt", b"same"), ("sound/d.wav", b"d")],
+        &[([64.0, 0.0, 0.0], 0.0, 0), ([0.0, 0.0, 8.0], 0.0, 0), ([0.0, 128.0, 0.0], 90.0, 1), ([0.0, 0.0, 0.0], 0.0, 1)]);
+
+    let mut old = Bsp::from_bytes(&old_bytes).unwrap();
+    let mut new = Bsp::from_bytes(&new).unwrap();
+    assert_eq!(new.static_props().unwrap()[2].model, "models/barrel.mdl");
+    assert_eq!(new.static_props().unwrap()[2].angles, Vector::new(0.0, 90.0, 0.0));
+
+    let same = diff(&mut old, &mut Bsp::from_bytes(&old_bytes).unwrap()).unwrap();
+    assert!(same.is_empty(), "{:?}", same);
+
+    let report = diff(&mut old, &mut new).unwrap();
+    let lumps: Vec<LumpIndex> = report.lumps.iter().map(|l| l.index).collect();
+    assert_eq!(lumps, vec![LumpIndex::Entities, LumpIndex::Planes, LumpIndex::GameLump, LumpIndex::PakFile]);
+    assert_eq!(report.lumps[1].old_size, report.lumps[1].new_size);
+
+    assert_eq!(report.entities.len(), 4);
+    match &report.entities[0] {
+        EntityChange::Modified { keys, .. } => {
+            let keys: Vec<(&str, Option<&str>, Option<&str>)> = keys.iter()
+                .map(|k| (k.key.as_str(), k.old.as_deref(), k.new.as_deref()))
+                .collect();
+            assert_eq!(keys, vec![
+                ("targetname", Some("door"), Some("big_door")),
+                ("OnOpen", Some("a,Trigger,,0,-1"), None),
+                ("OnOpen", None, Some("c,Trigger,,0,-1")),
+            ]);
+        },
+        change => panic!("{:?}", change),
+    }
+    assert!(matches!(&report.entities[1], EntityChange::Modified { keys, .. } if keys[0].key == "_light"));
+    assert!(matches!(&report.entities[2], EntityChange::Removed(e) if e.targetname() == Some("gone")));
+    assert!(matches!(&report.entities[3], EntityChange::Added(e) if e.targetname() == Some("new")));
+
+    assert_eq!(report.pak_files.len(), 3);
+    assert!(matches!(&report.pak_files[0], PakChange::Changed { old, new } if old.name == "materials/a.vmt" && new.name == "materials/A.vmt"));
+    assert!(matches!(&report.pak_files[1], PakChange::Removed(e) if e.name == "sound/c.wav"));
+    assert!(matches!(&report.pak_files[2], PakChange::Added(e) if e.name == "sound/d.wav"));
+
+    assert_eq!(report.static_props.len(), 2);
+    assert!(matches!(&report.static_props[0], StaticPropChange::Moved { old, new }
+        if old.origin == Vector::new(0.0, 0.0, 0.0) && new.origin == Vector::new(0.0, 0.0, 8.0)));
+    assert!(matches!(&report.static_props[1], StaticPropChange::Added(p) if p.model == "models/barrel.mdl"));
+}
+
+#[test]
+fn ray_cast_bvh() {
+    use sourcelib::{Face, Plane, Vector};
+
+    let mut squares = Squares::default();
+    let face = |tex_info: i16, disp_info: i16| Face { tex_info, disp_info, ..Default::default() };
+    squares.add((0.0, 0.0), (256.0, 256.0), 0.0, face(0, -1));
+    squares.add((512.0, 0.0), (768.0, 256.0), 0.0, face(1, 0));
+    squares.add((0.0, 0.0), (256.0, 256.0), 100.0, face(2, -1));
+
+    // A 9x9 displacement with a bump in the middle
+    let disp_info = DispInfo { start_position: Vector::new(512.0, 0.0, 0.0), power: 3, map_face: 1, ..Default::default() };
+    let disp_vertices: Vec<DispVert> = (0..81).map(|i| DispVert {
+        vector: Vector::new(0.0, 0.0, 1.0),
+        distance: if i == 40 { 32.0 } else { 0.0 },
+        alpha: 0.0,
+    }).collect();
+
+    let mut writer = BspWriter::new(20);
+    writer.set_lump(LumpIndex::Planes, &[Plane { normal: Vector::new(0.0, 0.0, 1.0), distance: 0.0, kind: 2 }]);
+    squares.write(&mut writer);
+    writer.set_lump(LumpIndex::DisplacementInfo, &[disp_info]);
+    writer.set_lump(LumpIndex::DisplacementVertices, &disp_vertices);
+    writer.set_lump(LumpIndex::TextureInfo, &[
+        TextureInfo { texture_data: 0, ..Default::default() },
+        TextureInfo { texture_data: 1, ..Default::default() },
+        TextureInfo { flags: SURF_NODRAW, texture_data: 2, ..Default::default() },
+    ]);
+    writer.set_lump(LumpIndex::TextureData, &[
+        TextureData { name_string_table_id: 0, ..Default::default() },
+        TextureData { name_string_table_id: 1, ..Default::default() },
+        TextureData { name_string_table_id: 2, ..Default::default() },
+    ]);
+    writer.set_lump(LumpIndex::TextureStringTable, &[0i32, 10, 23]);
+    writer.set_lump_data(LumpIndex::TextureStringData, b"dev/floor\0nature/grass\0tools/nodraw\0".to_vec());
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+
+    let bvh = Bvh::build(&mut bsp).unwrap();
+    assert_eq!(bvh.triangles.len(), 2 + 128);
+    let down = Vector::new(0.0, 0.0, -1.0);
+
+    // Straight through the nodraw face onto the floor
+    let hit = bvh.ray_cast(Vector::new(128.0, 64.0, 200.0), down, 1000.0).unwrap();
+    assert_eq!((hit.face, hit.displacement, hit.material), (0, None, Some("dev/floor")));
+    assert_eq!(hit.position, Vector::new(128.0, 64.0, 0.0));
+    assert_eq!(hit.normal, Vector::new(0.0, 0.0, 1.0));
+    assert_eq!(hit.distance, 200.0);
+
+    // From underneath, the normal faces the other way
+    let hit = bvh.ray_cast(Vector::new(128.0, 64.0, -8.0), -down, 1000.0).unwrap();
+    assert_eq!(hit.normal, Vector::new(0.0, 0.0, -1.0));
+
+    // The top of the bump
+    let hit = bvh.ray_cast(Vector::new(640.0, 128.0, 200.0), down, 1000.0).unwrap();
+    assert_eq!((hit.face, hit.displacement, hit.material), (1, Some(0), Some("nature/grass")));
+    assert!((hit.position.z - 32.0).abs() < 1e-3);
+
+    // Sideways into the side of the bump
+    let hit = bvh.ray_cast(Vector::new(0.0, 128.0, 8.0), Vector::new(1.0, 0.0, 0.0), 1000.0).unwrap();
+    assert_eq!(hit.face, 1);
+    assert!(hit.position.x > 608.0 && hit.position.x < 640.0);
+
+    assert_eq!(bvh.ray_cast(Vector::new(128.0, 64.0, 200.0), down, 100.0), None);
+    assert_eq!(bvh.ray_cast(Vector::new(384.0, 64.0, 200.0), down, 1000.0), None);
+    assert_eq!(bvh.ray_cast(Vector::new(128.0, 64.0, 200.0), -down, 1000.0), None);
+}
+
+#[test]
+fn build_synthetic_map() {
+    use sourcelib::bsp::builder::MapBuilder;
+    use sourcelib::bsp::validate::*;
+    use sourcelib::Vector;
+
+    let mut builder = MapBuilder::new();
+    builder.add_room(Vector::new(-256.0, -256.0, 0.0), Vector::new(256.0, 256.0, 128.0), 16.0, "dev/dev_measurewall01a");
+    builder.add_box(Vector::new(-32.0, -32.0, 0.0), Vector::new(32.0, 32.0, 64.0), "dev/dev_measuregeneric01");
+    builder.add_entity(&[("classname", "info_player_start"), ("origin", "-128 0 16")]);
+    builder.add_file("materials/custom/thing.vmt", b"LightmappedGeneric {}");
+    let bytes = builder.to_bytes().unwrap();
+
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+    let diagnostics = validate(&mut bsp);
+    assert!(!has_errors(&diagnostics), "{:?}", diagnostics);
+
+    let entities = bsp.entities().unwrap();
+    assert_eq!(entities[0].classname(), Some("worldspawn"));
+    assert_eq!(entities[1].classname(), Some("info_player_start"));
+    assert_eq!(bsp.brushes().unwrap().len(), 7);
+    assert_eq!(bsp.brush_sides().unwrap().len(), 42);
+    assert_eq!(bsp.pak_file().unwrap().file("materials/custom/thing.vmt").unwrap(), b"LightmappedGeneric {}");
+
+    // Faces point out of their boxes
+    let geometry = Geometry::load(&mut bsp).unwrap();
+    assert_eq!(geometry.faces.len(), 42);
+    assert_eq!(geometry.face_normal(&geometry.faces[4]), Vector::new(0.0, 0.0, 1.0)); // the floor's top
+    assert_eq!(geometry.face_normal(&geometry.faces[5]), Vector::new(0.0, 0.0, -1.0));
+    assert_eq!(geometry.face_vertices(&geometry.faces[4]).len(), 4);
+
+    // Empty inside the room and outside it, solid in the walls and the box
+    let leafs = bsp.leafs().unwrap();
+    let contents = |bsp: &mut Bsp, x, y, z| leafs[bsp.leaf_at(&Vector::new(x, y, z)).unwrap()].contents;
+    assert_eq!(contents(&mut bsp, -128.0, 0.0, 64.0), CONTENTS_EMPTY);
+    assert_eq!(contents(&mut bsp, 0.0, 0.0, -8.0), CONTENTS_SOLID);
+    assert_eq!(contents(&mut bsp, 264.0, 0.0, 64.0), CONTENTS_SOLID);
+    assert_eq!(contents(&mut bsp, 0.0, 0.0, 32.0), CONTENTS_SOLID);
+    assert_eq!(bsp.leaf_at(&Vector::new(0.0, 0.0, 32.0)).unwrap(), 7);
+    assert_eq!(contents(&mut bsp, 1000.0, 0.0, 0.0), CONTENTS_EMPTY);
+
+    let bvh = Bvh::build(&mut bsp).unwrap();
+    let hit = bvh.ray_cast(Vector::new(-128.0, 0.0, 64.0), Vector::new(0.0, 0.0, -1.0), 1000.0).unwrap();
+    assert_eq!(hit.position, Vector::new(-128.0, 0.0, 0.0));
+    assert_eq!(hit.material, Some("dev/dev_measurewall01a"));
+
+    let vmf = vmf::decompile(&mut bsp).unwrap();
+    assert_eq!(vmf.get_subkey("world").unwrap().get_subkeys("solid").count(), 7);
+}
+
+#[test]
+fn read_goldsrc_map() {
+    use sourcelib::{Edge, Plane, Vector};
+
+    let context = LumpContext::default();
+    let entities = b"{\n\"classname\" \"worldspawn\"\n\"wad\" \"halflife.wad\"\n}\n\0".to_vec();
+    let planes = write_items(&[Plane { normal: Vector::new(0.0, 0.0, 1.0), distance: 0.0, kind: 2 }], context);
+    let vertices = write_items(&[
+        Vector::new(0.0, 0.0, 0.0), Vector::new(64.0, 0.0, 0.0), Vector::new(64.0, 64.0, 0.0),
+    ], context);
+    let edges = write_items(&[Edge { v: [0, 0] }, Edge { v: [0, 1] }, Edge { v: [2, 1] }, Edge { v: [2, 0] }], context);
+    let surface_edges = write_items(&[1i32, -2, 3], context);
+    let faces = write_items(&[goldsrc::Face { first_edge: 0, num_edges: 3, light_offset: -1, ..Default::default() }], context);
+
+    // One 16x16 '{' texture in the map and one in a WAD
+    let mut textures = Vec::new();
+    textures.extend_from_slice(&2i32.to_le_bytes());
+    textures.extend_from_slice(&12i32.to_le_bytes());
+    let external: i32 = 12 + 40 + 256 + 64 + 16 + 4 + 2 + 768 + 2;
+    textures.extend_from_slice(&external.to_le_bytes());
+    let miptex_header = |name: &str, offsets: [u32; 4]| {
+        let mut bytes = name.as_bytes().to_vec();
+        bytes.resize(16, 0);
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        offsets.iter().for_each(|o| bytes.extend_from_slice(&o.to_le_bytes()));
+        bytes
+    };
+    textures.extend(miptex_header("{fence", [40, 296, 360, 376]));
+    textures.extend((0..256 + 64 + 16 + 4).map(|i| if i == 0 { 255 } else { 1 }));
+    textures.extend_from_slice(&256u16.to_le_bytes());
+    textures.extend((0..256).flat_map(|i| [i as u8, 0, 255 - i as u8]));
+    textures.extend_from_slice(&[0, 0]);
+    textures.extend(miptex_header("c1a0_floor", [0; 4]));
+
+    let mut lumps = vec![Vec::new(); goldsrc::LUMP_COUNT];
+    lumps[goldsrc::LumpIndex::Entities as usize] = entities;
+    lumps[goldsrc::LumpIndex::Planes as usize] = planes;
+    lumps[goldsrc::LumpIndex::Textures as usize] = textures;
+    lumps[goldsrc::LumpIndex::Vertices as usize] = vertices;
+    lumps[goldsrc::LumpIndex::Faces as usize] = faces;
+    lumps[goldsrc::LumpIndex::Edges as usize] = edges;
+    lumps[goldsrc::LumpIndex::SurfaceEdges as usize] = surface_edges;
+
+    let mut bytes = 30u32.to_le_bytes().to_vec();
+    let mut offset = 4 + goldsrc::LUMP_COUNT * 8;
+    for lump in lumps.iter() {
+        bytes.extend_from_slice(&(offset as u32).to_le_bytes());
+        bytes.extend_from_slice(&(lump.len() as u32).to_le_bytes());
+        offset += lump.len();
+    }
+    lumps.iter().for_each(|lump| bytes.extend_from_slice(lump));
+
+    assert!(goldsrc::is_goldsrc(&bytes));
+    assert!(matches!(Bsp::from_bytes(&bytes), Err(Error::InvalidIdentifier(30))));
+
+    let bsp = goldsrc::GoldSrcBsp::from_bytes(bytes.clone()).unwrap();
+    assert_eq!(bsp.entities().unwrap()[0].get("wad"), Some("halflife.wad"));
+    assert_eq!(bsp.planes()[0].normal, Vector::new(0.0, 0.0, 1.0));
+    let faces = bsp.faces();
+    assert_eq!(faces.len(), 1);
+    assert_eq!(bsp.face_vertices(&faces[0]), vec![
+        Vector::new(0.0, 0.0, 0.0), Vector::new(64.0, 0.0, 0.0), Vector::new(64.0, 64.0, 0.0),
+    ]);
+
+    let textures = bsp.textures().unwrap();
+    assert_eq!(textures.len(), 2);
+    assert_eq!(textures[0].name, "{fence");
+    assert_eq!(textures[0].palette.as_ref().unwrap().len(), 256);
+    let rgba = textures[0].to_rgba8(0).unwrap();
+    assert_eq!(rgba.len(), 16 * 16 * 4);
+    assert_eq!(&rgba[..8], &[255, 0, 0, 0, 1, 0, 254, 255]);
+    assert_eq!(textures[0].to_rgba8(3).unwrap().len(), 2 * 2 * 4);
+    assert!(textures[1].is_external());
+    assert_eq!(textures[1].name, "c1a0_floor");
+    assert_eq!(textures[1].to_rgba8(0), None);
+
+    // Lumps past the end, and VBSP files, are rejected
+    let mut broken = bytes.clone();
+    broken.truncate(broken.len() - 100);
+    assert!(matches!(goldsrc::GoldSrcBsp::from_bytes(broken), Err(Error::GoldSrcLumpOutOfBounds { index: 2, .. })));
+    bytes[0] = 31;
+    assert!(matches!(goldsrc::GoldSrcBsp::from_bytes(bytes), Err(Error::UnsupportedVersion(31))));
+}
+
+#[test]
+fn generate_nav_mesh() {
+    use sourcelib::bsp::builder::MapBuilder;
+    use sourcelib::Vector;
+
+    let mut builder = MapBuilder::new();
+    builder.add_room(Vector::new(-256.0, -256.0, 0.0), Vector::new(256.0, 256.0, 128.0), 16.0, "dev/dev_measurewall01a");
+    // Too tall to step onto, and a platform that's low enough
+    builder.add_box(Vector::new(-32.0, -32.0, 0.0), Vector::new(32.0, 32.0, 64.0), "dev/dev_measuregeneric01");
+    builder.add_box(Vector::new(128.0, -256.0, 0.0), Vector::new(256.0, 256.0, 16.0), "dev/dev_measuregeneric01");
+    builder.add_entity(&[("classname", "info_player_start"), ("origin", "-128 0 16")]);
+    let bytes = builder.to_bytes().unwrap();
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+
+    let world = CollisionWorld::build(&mut bsp, MASK_PLAYERSOLID).unwrap();
+    let zero = Vector::default();
+    let trace = world.trace_box(Vector::new(-128.0, 0.0, 40.0), Vector::new(-128.0, 0.0, -100.0), zero, zero);
+    assert!(trace.fraction < 1.0 && (trace.end.z - 0.0).abs() < 0.1);
+    assert_eq!(trace.normal, Vector::new(0.0, 0.0, 1.0));
+    let hull = (Vector::new(-16.0, -16.0, 0.0), Vector::new(16.0, 16.0, 72.0));
+    assert!(world.trace_box(Vector::new(0.0, 0.0, 1.0), Vector::new(0.0, 0.0, 1.0), hull.0, hull.1).start_solid);
+    let trace = world.trace_box(Vector::new(-128.0, 0.0, 1.0), Vector::new(128.0, 0.0, 1.0), hull.0, hull.1);
+    assert!(trace.fraction < 0.5 && (trace.end.x + 48.0).abs() < 0.1);
+
+    let nav = bsp.nav_mesh(&NavOptions::default()).unwrap();
+    // Everywhere 16 units from the walls, except around the tall box
+    let cells: f32 = nav.areas.iter().map(|a| (a.maxs.x - a.mins.x) * (a.maxs.y - a.mins.y) / 256.0).sum();
+    assert_eq!(cells, (30 * 30 - 6 * 6) as f32);
+    assert!(nav.areas.iter().all(|a| a.mins.x >= -240.0 && a.maxs.x <= 240.0 && a.mins.y >= -240.0 && a.maxs.y <= 240.0));
+    assert!(nav.areas.iter().all(|a| a.mins.z == 0.0 || a.mins.z == 16.0));
+
+    let start = nav.area_at(Vector::new(-128.0, 0.0, 0.0), 18.0).unwrap();
+    let platform = nav.area_at(Vector::new(200.0, 0.0, 16.0), 18.0).unwrap();
+    assert_eq!(nav.areas[platform].mins.z, 16.0);
+    assert_eq!(nav.area_at(Vector::new(0.0, 0.0, 64.0), 18.0), None);
+
+    // Every area can be walked to from the spawn
+    let mut seen = vec![false; nav.areas.len()];
+    let mut stack = vec![start];
+    while let Some(area) = stack.pop() {
+        if !std::mem::replace(&mut seen[area], true) {
+            stack.extend(nav.areas[area].connections.iter().copied());
+        }
+    }
+    assert!(seen.iter().all(|&s| s));
+
+    let text = nav.to_text();
+    assert!(text.starts_with("nav 1 16\narea 0 "));
+    assert_eq!(text.lines().filter(|l| l.starts_with("area ")).count(), nav.areas.len());
+
+    for options in [
+        NavOptions { cell_size: 0.0, ..Default::default() },
+        NavOptions { cell_size: f32::NAN, ..Default::default() },
+        NavOptions { step_height: -1.0, ..Default::default() },
+    ].iter() {
+        assert!(matches!(bsp.nav_mesh(options), Err(Error::InvalidOption(_))));
+    }
+}
+
+#[test]
+fn nav_mesh_on_displacements_and_props() {
+    use sourcelib::bsp::builder::MapBuilder;
+    use sourcelib::{Face, Vector};
+
+    let mut builder = MapBuilder::new();
+    builder.add_room(Vector::new(-256.0, -256.0, 0.0), Vector::new(256.0, 256.0, 128.0), 16.0, "dev/dev_measurewall01a");
+    // The room's 6 boxes come first, so this is brush 6, and its top is face and side 6 * 6 + 4
+    builder.add_box(Vector::new(0.0, -128.0, 0.0), Vector::new(256.0, 128.0, 8.0), "nature/blendgroundtograss001");
+    builder.add_entity(&[("classname", "info_player_start"), ("origin", "-128 -128 16")]);
+    let mut writer = builder.build();
+    let mut bsp_bytes = Vec::new();
+    writer.write(&mut bsp_bytes).unwrap();
+    let mut built = Bsp::from_bytes(&bsp_bytes).unwrap();
+
+    // A 5x5 pyramid on top of the box, 32 units high in the middle
+    let top = 6 * 6 + 4;
+    let mut faces: Vec<Face> = built.faces().unwrap();
+    faces[top].disp_info = 0;
+    let mut sides = built.brush_sides().unwrap();
+    sides[top].displacement_info = 0;
+    let vertices: Vec<DispVert> = (0..25)
+        .map(|i: i32| {
+            let ring = (i % 5 - 2).abs().max((i / 5 - 2).abs());
+            DispVert { vector: Vector::new(0.0, 0.0, 1.0), distance: 32.0 - 16.0 * ring as f32, alpha: 0.0 }
+        })
+        .collect();
+    let info = DispInfo {
+        start_position: Vector::new(0.0, -128.0, 8.0),
+        power: 2,
+        contents: CONTENTS_SOLID,
+        map_face: top as u16,
+        ..Default::default()
+    };
+    writer.set_lump(LumpIndex::Faces, &faces);
+    writer.set_lump(LumpIndex::BrushSides, &sides);
+    writer.set_lump(LumpIndex::DisplacementInfo, &[info]);
+    writer.set_lump(LumpIndex::DisplacementVertices, &vertices);
+
+    // A crate, 64 units across and 48 high
+    let hull: Vec<Vector> = (0..8)
+        .map(|i| Vector::new(if i & 1 == 0 { -32.0 } else { 32.0 }, if i & 2 == 0 { -32.0 } else { 32.0 }, if i & 4 == 0 { 0.0 } else { 48.0 }))
+        .collect();
+    writer.set_lump(LumpIndex::Portals, &[PropCollision { hull_count: 1, hull_start: 0 }]);
+    writer.set_lump(LumpIndex::Clusters, &[PropHull { vert_count: 8, vert_start: 0, surface_prop: 0, contents: CONTENTS_SOLID as u32 }]);
+    writer.set_lump(LumpIndex::PortalVerts, &hull);
+    writer.set_game_lumps(vec![static_prop_lump(&["models/crate.mdl"], &[([-128.0, 128.0, 0.0], 45.0, 0)])]);
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+
+    // The displacement is solid instead of the box it was made from, and so is the crate
+    let world = CollisionWorld::build(&mut bsp, MASK_PLAYERSOLID).unwrap();
+    let zero = Vector::default();
+    let down = |x: f32, y: f32| world.trace_box(Vector::new(x, y, 100.0), Vector::new(x, y, -100.0), zero, zero);
+
+    assert!((down(128.0, 0.0).end.z - 40.0).abs() < 0.1);
+    assert!((down(64.0, 64.0).end.z - 24.0).abs() < 0.1);
+    assert!(down(64.0, 0.0).normal.z > 0.7);
+    assert!((down(-16.0, 0.0).end.z - 0.0).abs() < 0.1);
+    assert!((down(-128.0, 128.0).end.z - 48.0).abs() < 0.1);
+    // Walking into the crate stops at its turned corner, 32 * sqrt(2) out plus the hull
+    let hull = (Vector::new(-16.0, -16.0, 0.0), Vector::new(16.0, 16.0, 72.0));
+    let trace = world.trace_box(Vector::new(-232.0, 128.0, 1.0), Vector::new(0.0, 128.0, 1.0), hull.0, hull.1);
+    assert!((trace.end.x - (-128.0 - 45.25 - 16.0)).abs() < 0.1);
+
+    let nav = bsp.nav_mesh(&NavOptions::default()).unwrap();
+    // Up the side of the pyramid to the top, 2 units up for every 8 across
+    let slope = &nav.areas[nav.area_at(Vector::new(40.0, 0.0, 18.0), 18.0).unwrap()];
+    assert_eq!((slope.mins.x, slope.maxs.x), (0.0, 128.0));
+    assert!((slope.corners[0] - 10.0).abs() < 0.1 && (slope.corners[1] - 38.0).abs() < 0.1);
+    assert!(nav.area_at(Vector::new(128.0, 0.0, 40.0), 18.0).is_some());
+    // Nothing around the crate, or on top of it where nobody can get to
+    assert_eq!(nav.area_at(Vector::new(-128.0, 128.0, 0.0), 18.0), None);
+    assert_eq!(nav.area_at(Vector::new(-128.0, 128.0, 48.0), 18.0), None);
+    assert!(nav.area_at(Vector::new(-128.0, 200.0, 0.0), 18.0).is_some());
+}
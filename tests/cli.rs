@@ -0,0 +1,64 @@
+use sourcelib::bsp::{BspWriter, LumpIndex};
+
+use std::path::PathBuf;
+use std::process::Command;
+
+// Run the sourcelib binary with --json, returning what it printed
+fn run_json(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_sourcelib")).arg("--json").args(args).output().unwrap();
+    assert!(output.status.success(), "{:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn temp_file(name: &str, data: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, data).unwrap();
+    path
+}
+
+#[test]
+fn bsp_info_json() {
+    let mut writer = BspWriter::new(20);
+    writer.set_lump_data(LumpIndex::Entities, b"{\n\"classname\" \"worldspawn\"\n}\n\0".to_vec());
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+    let path = temp_file("sourcelib_cli_info.bsp", &bytes);
+
+    let out = run_json(&["bsp", "info", path.to_str().unwrap()]);
+    assert!(out.starts_with("{\"version\":20,\"iteration\":0,\"endian\":\"Little\",\"layout\":\"Standard\","));
+    assert!(out.contains("{\"index\":0,\"name\":\"Entities\","));
+    assert!(out.trim_end().ends_with("]}"));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn kv_get_json() {
+    let out = run_json(&["kv", "get", "tests/resources/spray.vmt", "LightmappedGeneric/$basetexture"]);
+    assert_eq!(out, "\"vgui\\\\logos\\\\spray_headshot\"\n");
+
+    // Blocks are [key, value] pairs, since keys can repeat
+    let out = run_json(&["kv", "get", "tests/resources/spray.vmt", "LightmappedGeneric"]);
+    assert!(out.starts_with("[[\"$basetexture\",\"vgui\\\\logos\\\\spray_headshot\"],[\"$translucent\",\"1\"],"));
+}
+
+#[test]
+fn vtf_info_json() {
+    // A 4x2 RGBA8888 7.2 texture with one mipmap and no thumbnail
+    let mut bytes = vec![0; 80];
+    bytes[0..4].copy_from_slice(b"VTF\0");
+    bytes[4..8].copy_from_slice(&7u32.to_le_bytes());
+    bytes[8..12].copy_from_slice(&2u32.to_le_bytes());
+    bytes[12..16].copy_from_slice(&80u32.to_le_bytes());
+    bytes[16..18].copy_from_slice(&4u16.to_le_bytes());
+    bytes[18..20].copy_from_slice(&2u16.to_le_bytes());
+    bytes[24..26].copy_from_slice(&1u16.to_le_bytes());
+    bytes[56] = 1;
+    bytes[57..61].copy_from_slice(&(-1i32).to_le_bytes());
+    bytes[63..65].copy_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&[0; 4 * 2 * 4]);
+    let path = temp_file("sourcelib_cli_info.vtf", &bytes);
+
+    let out = run_json(&["vtf", "info", path.to_str().unwrap()]);
+    assert!(out.starts_with("{\"version\":\"7.2\",\"width\":4,\"height\":2,\"depth\":1,\"format\":\"Rgba8888\",\"mipmaps\":1,"));
+    std::fs::remove_file(path).unwrap();
+}
@@ -64,3 +64,68 @@ fn parse_keyvalues_unexpected_brace() {
         })
     );
 }
+
+#[test]
+fn keyvalues_keep_order_and_duplicates() {
+    let kv = KeyValues::from_str("
+world
+{
+    solid { id 1 }
+    solid { id 2 }
+    classname worldspawn
+}
+").unwrap();
+
+    let world = kv.get_subkey("world").unwrap();
+    let ids: Vec<i32> = world.get_subkeys("solid").map(|s| s.get_or_default("id")).collect();
+    assert_eq!(ids, vec![1, 2]);
+    assert_eq!(kv.get_path("world/classname"), Some(&Value::Str("worldspawn".to_string())));
+    assert_eq!(kv.get_path("world/solid/id"), Some(&Value::Str("2".to_string())));
+    assert_eq!(kv.get_path("world/nope"), None);
+}
+
+#[test]
+fn format_keyvalues() {
+    let kv = KeyValues::from_file("tests/resources/spray.vmt").unwrap();
+    let text = kv.to_string();
+    assert_eq!(text,
+"\"LightmappedGeneric\"
+{
+\t\"$basetexture\"\t\"vgui\\logos\\spray_headshot\"
+\t\"$translucent\"\t\"1\"
+\t\"$decal\"\t\"1\"
+\t\"$decalscale\"\t\"0.250\"
+}
+");
+    assert_eq!(KeyValues::from_str(&text).unwrap(), kv);
+    assert_eq!(KeyValues::from_str("").unwrap(), KeyValues::new());
+}
+
+#[test]
+fn keyvalues_equality_and_duplicates() {
+    // Order matters, since it's kept when writing the text back out
+    let ab = KeyValues::from_str("a 1\nb 2").unwrap();
+    assert_eq!(ab, KeyValues::from_str("\"a\" \"1\"\n\"b\" \"2\"").unwrap());
+    assert_ne!(ab, KeyValues::from_str("b 2\na 1").unwrap());
+    // So do duplicates, even ones that lookups never see
+    assert_ne!(ab, KeyValues::from_str("a 1\nb 2\nb 3").unwrap());
+
+    // Lookups find the last one, like when later keys overwrote earlier ones in a HashMap
+    let mut kv = KeyValues::from_str("key first\nkey second\nblock { n 1 }\nblock { n 2 }").unwrap();
+    assert_eq!(kv.get_str("key"), Some("second"));
+    assert_eq!(kv.get_path("key"), Some(&Value::Str("second".to_string())));
+    assert_eq!(kv.get_subkey("block").unwrap().get::<i32>("n"), Some(2));
+    assert_eq!(kv.get_subkeys("block").count(), 2);
+
+    // add_value() replaces the last, push_value() adds another
+    kv.add_value("key", "replaced");
+    assert_eq!(kv.get_str("key"), Some("replaced"));
+    kv.push_value("key", "third");
+    let values: Vec<&Value> = kv.entries().iter().filter(|(k, _)| k == "key").map(|(_, v)| v).collect();
+    assert_eq!(values, vec![
+        &Value::Str("first".to_string()),
+        &Value::Str("replaced".to_string()),
+        &Value::Str("third".to_string()),
+    ]);
+    assert_eq!(KeyValues::from_str(&kv.to_string()).unwrap(), kv);
+}
@@ -0,0 +1,153 @@
+use sourcelib::bsp::{parse_entities, Bsp, BspWriter, LumpFile, LumpIndex};
+use sourcelib::stripper::*;
+
+use std::io::Cursor;
+
+#[test]
+fn regex_matching() {
+    let regex = |pattern: &str| Regex::new(pattern).unwrap();
+
+    assert!(regex("weapon_").is_match("weapon_ak47"));
+    assert!(regex("^weapon_(ak47|m4a1)$").is_match("WEAPON_M4A1"));
+    assert!(!regex("^weapon_(ak47|m4a1)$").is_match("weapon_m4a1_silencer"));
+    assert!(regex("^item_[a-z]+\\d{2,3}$").is_match("item_ammo100"));
+    assert!(!regex("^item_[a-z]+\\d{2,3}$").is_match("item_ammo1"));
+    assert!(regex("^a.*?b$").is_match("a--b--b"));
+    assert!(regex("[^0-9 ]").is_match("12 x"));
+    assert!(!regex("[^0-9 ]").is_match("12 3"));
+    assert!(regex("\\bdoor\\b").is_match("big door 2"));
+    assert!(!regex("\\bdoor\\b").is_match("doorway"));
+    assert!(regex("(a*)*$").is_match("aaab"));
+    assert!(regex("a{,").is_match("a{,")); // not a count, so literal
+
+    assert!(Regex::new("(abc").is_err());
+    assert!(Regex::new("abc)").is_err());
+    assert!(Regex::new("[abc").is_err());
+    assert!(Regex::new("*a").is_err());
+}
+
+#[test]
+fn regex_pathological() {
+    // These would overflow the stack or take forever with a backtracker
+    let long = "a".repeat(50_000);
+    assert!(!Regex::new(".*b").unwrap().is_match(&long));
+    assert!(Regex::new(".*a$").unwrap().is_match(&long));
+    let short = "a".repeat(22);
+    assert!(!Regex::new("(a*)*b").unwrap().is_match(&short));
+    assert!(!Regex::new("(a|a)*b").unwrap().is_match(&short));
+    assert!(Regex::new("^(a|a)*$").unwrap().is_match(&short));
+
+    assert!(Regex::new("a{2,3}b").unwrap().is_match("xaab"));
+    assert!(!Regex::new("^a{2,3}b").unwrap().is_match("aaaab"));
+    assert!(Regex::new("^(ab|cd|ef)+$").unwrap().is_match("abefcd"));
+
+    assert!(Regex::new("((a{100}){100}){100}").is_err());
+    assert!(Regex::new(&format!("{}a{}", "(".repeat(1000), ")".repeat(1000))).is_err());
+}
+
+const CONFIG: &str = "
+; Stripper config for de_test
+filter:
+{
+\"classname\" \"/^weapon_/\"
+}
+{
+\"targetname\" \"remove_me\"
+}
+
+add:
+{
+\"classname\" \"info_target\"
+\"targetname\" \"added\"
+}
+
+modify:
+{
+    match:
+    {
+    \"classname\" \"func_door\"
+    }
+    replace:
+    {
+    \"speed\" \"500\"
+    \"missing\" \"ignored\"
+    }
+    delete:
+    {
+    \"OnOpen\" \"/relay/\"
+    }
+    insert:
+    {
+    \"OnOpen\" \"new_relay,Trigger,,0,-1\"
+    }
+}
+";
+
+const ENTITIES: &str = "
+{
+\"classname\" \"worldspawn\"
+}
+{
+\"classname\" \"weapon_ak47\"
+}
+{
+\"classname\" \"prop_physics\"
+\"targetname\" \"REMOVE_ME\"
+}
+{
+\"classname\" \"func_door\"
+\"speed\" \"100\"
+\"OnOpen\" \"old_relay,Trigger,,0,-1\"
+\"OnOpen\" \"sound,PlaySound,,0,-1\"
+}
+";
+
+#[test]
+fn parse_and_apply_config() {
+    let config: Config = CONFIG.parse().unwrap();
+    assert_eq!(config.actions.len(), 4);
+    assert!(matches!(&config.actions[0], Action::Filter(p) if matches!(p[0].value, Pattern::Regex(_))));
+    assert!(matches!(&config.actions[1], Action::Filter(p) if p[0].value == Pattern::Exact("remove_me".to_string())));
+
+    let mut entities = parse_entities(ENTITIES).unwrap();
+    let changes = config.apply(&mut entities);
+    assert_eq!(changes, Changes { removed: 2, added: 1, modified: 1 });
+
+    assert_eq!(entities.len(), 3);
+    let door = &entities[1];
+    assert_eq!(door.get("speed"), Some("500"));
+    assert_eq!(door.get("missing"), None);
+    assert_eq!(door.get_all("OnOpen").collect::<Vec<_>>(), vec!["sound,PlaySound,,0,-1", "new_relay,Trigger,,0,-1"]);
+    assert_eq!(entities[2].targetname(), Some("added"));
+
+    assert!(matches!("filter:\n{\n\"classname\"\n}".parse::<Config>(), Err(Error::Syntax { line: 4, .. })));
+    assert!(matches!("explode:".parse::<Config>(), Err(Error::Syntax { line: 1, .. })));
+    assert!(matches!("{\n\"classname\" \"/(/\"\n}".parse::<Config>(), Err(Error::InvalidRegex { .. })));
+    assert!(matches!("modify:\n{\nmatch: \"x\"\n}".parse::<Config>(), Err(Error::Syntax { .. })));
+}
+
+#[test]
+fn write_patched_entities() {
+    let mut writer = BspWriter::new(20);
+    writer.set_lump_data(LumpIndex::Entities, format!("{}\0", ENTITIES).into_bytes());
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+    let mut bsp = Bsp::from_bytes(&bytes).unwrap();
+
+    let config: Config = CONFIG.parse().unwrap();
+    let mut entities = bsp.entities().unwrap();
+    config.apply(&mut entities);
+
+    // Back into a whole map
+    let mut writer = BspWriter::from_bsp(&mut bsp).unwrap();
+    writer.set_entities(&entities);
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+    assert_eq!(Bsp::from_bytes(&bytes).unwrap().entities().unwrap(), entities);
+
+    // Or as a .lmp next to the original
+    let mut lmp = Vec::new();
+    LumpFile::from_entities(&entities, bsp.iteration).write(&mut lmp).unwrap();
+    bsp.apply_lump_file(LumpFile::read(&mut Cursor::new(lmp)).unwrap());
+    assert_eq!(bsp.entities().unwrap(), entities);
+}
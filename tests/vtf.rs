@@ -0,0 +1,71 @@
+use sourcelib::vtf::*;
+
+// A 7.2 header followed by the given image data, no low-res thumbnail
+fn vtf_bytes(width: u16, height: u16, format: i32, mipmaps: u8, data: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0; 80];
+    bytes[0..4].copy_from_slice(b"VTF\0");
+    bytes[4..8].copy_from_slice(&7u32.to_le_bytes());
+    bytes[8..12].copy_from_slice(&2u32.to_le_bytes());
+    bytes[12..16].copy_from_slice(&80u32.to_le_bytes());
+    bytes[16..18].copy_from_slice(&width.to_le_bytes());
+    bytes[18..20].copy_from_slice(&height.to_le_bytes());
+    bytes[24..26].copy_from_slice(&1u16.to_le_bytes());
+    bytes[52..56].copy_from_slice(&format.to_le_bytes());
+    bytes[56] = mipmaps;
+    bytes[57..61].copy_from_slice(&(-1i32).to_le_bytes());
+    bytes[63..65].copy_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+#[test]
+fn read_vtf_header() {
+    let vtf = Vtf::from_bytes(&vtf_bytes(2, 2, 0, 2, &[0; 20])).unwrap();
+    assert_eq!(vtf.header.version, [7, 2]);
+    assert_eq!((vtf.header.width, vtf.header.height), (2, 2));
+    assert_eq!(vtf.header.high_res_format, ImageFormat::Rgba8888);
+    assert_eq!(vtf.header.low_res_format, ImageFormat::None);
+    assert_eq!(vtf.header.faces(), 1);
+
+    let mut bytes = vtf_bytes(2, 2, 0, 1, &[0; 16]);
+    bytes[2] = b'X';
+    assert!(matches!(Vtf::from_bytes(&bytes), Err(Error::InvalidSignature)));
+    assert!(matches!(Vtf::from_bytes(b"VTF\0"), Err(Error::UnexpectedEof)));
+}
+
+#[test]
+fn decode_mipmaps() {
+    // Smallest mipmap first: 1x1, then 2x2
+    let mut data = vec![9, 9, 9, 9];
+    for i in 0..4u8 {
+        data.extend_from_slice(&[i, i * 2, i * 3, 255]);
+    }
+    let vtf = Vtf::from_bytes(&vtf_bytes(2, 2, 0, 2, &data)).unwrap();
+
+    let (width, height, pixels) = vtf.to_rgba8(0, 0, 0, 0).unwrap();
+    assert_eq!((width, height), (2, 2));
+    assert_eq!(&pixels[12..16], &[3, 6, 9, 255]);
+    assert_eq!(vtf.to_rgba8(1, 0, 0, 0).unwrap().2, vec![9, 9, 9, 9]);
+    assert!(matches!(vtf.to_rgba8(2, 0, 0, 0), Err(Error::NoSuchImage)));
+
+    // 4x4 only has room for 3 mipmaps
+    assert!(Vtf::from_bytes(&vtf_bytes(4, 4, 0, 3, &[0; 84])).is_ok());
+    assert!(matches!(Vtf::from_bytes(&vtf_bytes(4, 4, 0, 4, &[0; 84])), Err(Error::TooManyMipmaps(4))));
+    assert!(matches!(Vtf::from_bytes(&vtf_bytes(4, 4, 0, 255, &[0; 64])), Err(Error::TooManyMipmaps(255))));
+
+    // Mipmaps past the 1x1 one stay 1x1 instead of shifting out of range
+    let mut header = vtf.header.clone();
+    header.mipmaps = 255;
+    assert_eq!(header.mip_size(200), (1, 1, 1));
+}
+
+#[test]
+fn decode_dxt1() {
+    // Pure red and pure blue endpoints, top row red, everything else blue
+    let block = [0x00, 0xF8, 0x1F, 0x00, 0b00000000, 0b01010101, 0b01010101, 0b01010101];
+    let vtf = Vtf::from_bytes(&vtf_bytes(4, 4, 13, 1, &block)).unwrap();
+    let (_, _, pixels) = vtf.to_rgba8(0, 0, 0, 0).unwrap();
+    assert_eq!(&pixels[0..4], &[255, 0, 0, 255]);
+    assert_eq!(&pixels[16..20], &[0, 0, 255, 255]);
+    assert_eq!(pixels.len(), 4 * 4 * 4);
+}